@@ -0,0 +1,232 @@
+use crate::error::Result;
+use crate::format::{self, SaveFile};
+use crate::storage::write_atomic;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const BACKUPS_DIR: &str = "backups";
+
+/// How many snapshots of `notes.json` to keep in the `backups` folder of the app data dir.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct BackupPolicy {
+    /// one snapshot per hour, from the first save of that hour
+    pub hourly: usize,
+    /// one snapshot per day, from the first save of that day
+    pub daily: usize,
+}
+
+impl Default for BackupPolicy {
+    fn default() -> Self {
+        BackupPolicy {
+            hourly: 24,
+            daily: 7,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Hourly,
+    Daily,
+}
+
+impl Kind {
+    fn prefix(self) -> &'static str {
+        match self {
+            Kind::Hourly => "notes-hourly-",
+            Kind::Daily => "notes-daily-",
+        }
+    }
+
+    fn stamp(self, now: DateTime<Utc>) -> String {
+        match self {
+            Kind::Hourly => now.format("%Y%m%d%H").to_string(),
+            Kind::Daily => now.format("%Y%m%d").to_string(),
+        }
+    }
+
+    fn keep(self, policy: &BackupPolicy) -> usize {
+        match self {
+            Kind::Hourly => policy.hourly,
+            Kind::Daily => policy.daily,
+        }
+    }
+}
+
+/// Snapshots `contents` into the current hourly and daily slots if they are still empty,
+/// then drops the oldest snapshots beyond what `policy` keeps.
+pub fn make_backups(
+    app_data_dir: &Path,
+    contents: &str,
+    now: DateTime<Utc>,
+    policy: &BackupPolicy,
+) -> Result<()> {
+    let backups_dir = app_data_dir.join(BACKUPS_DIR);
+
+    fs::create_dir_all(&backups_dir)?;
+
+    for kind in [Kind::Hourly, Kind::Daily] {
+        let path = backups_dir.join(format!("{}{}.json", kind.prefix(), kind.stamp(now)));
+
+        if kind.keep(policy) > 0 && !path.exists() {
+            write_atomic(&path, contents.as_bytes())?;
+        }
+
+        let mut backups = list_backups(&backups_dir, kind)?;
+        backups.sort_by(|a, b| b.0.cmp(&a.0));
+
+        for (_stamp, path) in backups.into_iter().skip(kind.keep(policy)) {
+            fs::remove_file(path)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The most recent backup that still parses, along with what it holds.
+pub fn newest_valid_backup(app_data_dir: &Path) -> Option<(PathBuf, SaveFile)> {
+    let backups_dir = app_data_dir.join(BACKUPS_DIR);
+
+    // a daily snapshot is taken at the same time as the hourly one for that hour, so on a tie prefer the hourly one,
+    // and treat the daily one as if it was taken at the start of the day otherwise
+    let mut backups = list_backups(&backups_dir, Kind::Hourly).ok()?;
+    backups.extend(
+        list_backups(&backups_dir, Kind::Daily)
+            .ok()?
+            .into_iter()
+            .map(|(stamp, path)| (format!("{stamp}00"), path)),
+    );
+    backups.sort_by(|a, b| b.0.cmp(&a.0));
+
+    backups.into_iter().find_map(|(_stamp, path)| {
+        let save_file = format::parse(&fs::read_to_string(&path).ok()?).ok()?;
+        Some((path, save_file))
+    })
+}
+
+fn list_backups(backups_dir: &Path, kind: Kind) -> Result<Vec<(String, PathBuf)>> {
+    if !backups_dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut backups = Vec::new();
+
+    for entry in fs::read_dir(backups_dir)? {
+        let path = entry?.path();
+
+        let stamp = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_prefix(kind.prefix()))
+            .and_then(|name| name.strip_suffix(".json"))
+            .filter(|stamp| stamp.chars().all(|c| c.is_ascii_digit()))
+            .map(|stamp| stamp.to_string());
+
+        if let Some(stamp) = stamp {
+            backups.push((stamp, path));
+        }
+    }
+
+    Ok(backups)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(day: u32, hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, day, hour, 15, 0).unwrap()
+    }
+
+    fn names(app_data_dir: &Path) -> Vec<String> {
+        let mut names = fs::read_dir(app_data_dir.join(BACKUPS_DIR))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<String>>();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn first_save_of_the_hour_is_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        let policy = BackupPolicy::default();
+
+        make_backups(dir.path(), "[]", at(1, 9), &policy).unwrap();
+        make_backups(dir.path(), "[{}]", at(1, 9), &policy).unwrap();
+
+        assert_eq!(
+            names(dir.path()),
+            vec!["notes-daily-20240301.json", "notes-hourly-2024030109.json"]
+        );
+        assert_eq!(
+            fs::read_to_string(
+                dir.path()
+                    .join(BACKUPS_DIR)
+                    .join("notes-hourly-2024030109.json")
+            )
+            .unwrap(),
+            "[]"
+        );
+    }
+
+    #[test]
+    fn old_snapshots_rotate_out() {
+        let dir = tempfile::tempdir().unwrap();
+        let policy = BackupPolicy {
+            hourly: 2,
+            daily: 1,
+        };
+
+        for (day, hour) in [(1, 9), (1, 10), (2, 8), (2, 9)] {
+            make_backups(dir.path(), "[]", at(day, hour), &policy).unwrap();
+        }
+
+        assert_eq!(
+            names(dir.path()),
+            vec![
+                "notes-daily-20240302.json",
+                "notes-hourly-2024030208.json",
+                "notes-hourly-2024030209.json"
+            ]
+        );
+    }
+
+    #[test]
+    fn zero_disables_a_kind_of_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let policy = BackupPolicy {
+            hourly: 0,
+            daily: 1,
+        };
+
+        make_backups(dir.path(), "[]", at(1, 9), &policy).unwrap();
+
+        assert_eq!(names(dir.path()), vec!["notes-daily-20240301.json"]);
+    }
+
+    #[test]
+    fn newest_valid_backup_skips_broken_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let policy = BackupPolicy::default();
+
+        make_backups(dir.path(), "[]", at(1, 9), &policy).unwrap();
+        make_backups(dir.path(), "[{\"col", at(1, 10), &policy).unwrap();
+
+        let (path, save_file) = newest_valid_backup(dir.path()).unwrap();
+
+        assert_eq!(path.file_name().unwrap(), "notes-hourly-2024030109.json");
+        assert!(save_file.notes.is_empty());
+    }
+
+    #[test]
+    fn no_backups_means_no_fallback() {
+        let dir = tempfile::tempdir().unwrap();
+
+        assert!(newest_valid_backup(dir.path()).is_none());
+    }
+}
@@ -0,0 +1,138 @@
+use crate::control::Request;
+use crate::model::Note;
+use serde::{Deserialize, Serialize};
+
+/// What Enter does in the quick capture window.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CaptureMode {
+    /// opens a note with the text
+    NewNote,
+    /// adds the text as a line at the end of the inbox note
+    Line,
+    /// adds the text as an unchecked checkbox at the end of the inbox note
+    Checkbox,
+}
+
+impl Default for CaptureMode {
+    fn default() -> Self {
+        CaptureMode::Checkbox
+    }
+}
+
+/// The system-wide quick capture shortcut, and where what is typed into it goes.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CapturePolicy {
+    /// an accelerator like `CmdOrCtrl+Shift+Space`, no shortcut is registered when unset
+    pub shortcut: Option<String>,
+    pub mode: CaptureMode,
+    /// title of the note lines are added to, made the first time something is captured
+    pub inbox: String,
+}
+
+impl Default for CapturePolicy {
+    fn default() -> Self {
+        CapturePolicy {
+            shortcut: None,
+            mode: CaptureMode::default(),
+            inbox: "Inbox".to_string(),
+        }
+    }
+}
+
+impl CapturePolicy {
+    /// What to do with `text` typed into the capture window, `None` when nothing was typed.
+    pub fn request(&self, text: &str, notes: &[Note]) -> Option<Request> {
+        let text = text.trim();
+        if text.is_empty() {
+            return None;
+        }
+
+        let line = match self.mode {
+            CaptureMode::NewNote => {
+                return Some(Request::New {
+                    color: None,
+                    text: text.to_string(),
+                })
+            }
+            CaptureMode::Line => text.to_string(),
+            CaptureMode::Checkbox => format!("- [ ] {text}"),
+        };
+
+        let inbox = notes
+            .iter()
+            .find(|note| note.title().eq_ignore_ascii_case(self.inbox.trim()));
+
+        Some(match inbox {
+            Some(inbox) => Request::Append {
+                id: inbox.id,
+                text: line,
+            },
+            None => Request::New {
+                color: None,
+                text: format!("# {}\n{line}", self.inbox.trim()),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::markdown;
+    use uuid::Uuid;
+
+    #[test]
+    fn captures_go_to_the_inbox() {
+        let policy = CapturePolicy::default();
+        let notes = [Note {
+            id: Uuid::from_u128(1),
+            contents: markdown::to_contents("# inbox\n- [ ] milk").unwrap(),
+            ..Default::default()
+        }];
+
+        assert_eq!(
+            policy.request(" eggs ", &notes),
+            Some(Request::Append {
+                id: notes[0].id,
+                text: "- [ ] eggs".to_string()
+            })
+        );
+        assert_eq!(
+            policy.request("eggs", &[]),
+            Some(Request::New {
+                color: None,
+                text: "# Inbox\n- [ ] eggs".to_string()
+            })
+        );
+        assert_eq!(policy.request("  ", &notes), None);
+    }
+
+    #[test]
+    fn modes_decide_what_enter_does() {
+        let line = CapturePolicy {
+            mode: CaptureMode::Line,
+            ..Default::default()
+        };
+        assert_eq!(
+            line.request("call back", &[]),
+            Some(Request::New {
+                color: None,
+                text: "# Inbox\ncall back".to_string()
+            })
+        );
+
+        let new_note = CapturePolicy {
+            mode: CaptureMode::NewNote,
+            ..Default::default()
+        };
+        assert_eq!(
+            new_note.request("call back", &[]),
+            Some(Request::New {
+                color: None,
+                text: "call back".to_string()
+            })
+        );
+    }
+}
@@ -0,0 +1,342 @@
+use crate::control::{self, Client, Reply, Request};
+use crate::error::{Result, StickyError};
+use crate::format::{self, SaveFile};
+use crate::instance::InstanceLock;
+use crate::markdown;
+use crate::model::Note;
+use crate::settings::Settings;
+use crate::store;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use uuid::Uuid;
+
+pub const USAGE: &str = "usage:
+  md-sticky list                                 list the open notes as <id> <color> <title>
+  md-sticky show <id>                            print a note as Markdown
+  md-sticky new [--color <color>] [--text <text>|-]
+                                                 open a note with Markdown text, print its id
+  md-sticky append <id> <text>|-                 add Markdown lines to the end of a note
+  md-sticky close <id>                           close a note, it goes to the trash
+  md-sticky export                               print the open notes in the notes.json format
+  md-sticky import                               add the notes from a notes.json or an export on stdin
+
+`-` reads the text from stdin. With md-sticky running the windows update right away,
+otherwise the notes are changed on disk.";
+
+const COMMANDS: [&str; 9] = [
+    "list", "show", "new", "append", "close", "export", "import", "help", "--help",
+];
+
+/// Where the text of `new` and `append` comes from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Text {
+    Arg(String),
+    Stdin,
+}
+
+/// A parsed `md-sticky <command>`, see [`USAGE`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    List,
+    Show(Uuid),
+    New { color: Option<String>, text: Text },
+    Append { id: Uuid, text: Text },
+    Close(Uuid),
+    Export,
+    Import,
+    Help,
+}
+
+/// Whether the arguments ask for a command instead of the app.
+pub fn is_command(args: &[String]) -> bool {
+    args.first()
+        .map_or(false, |command| COMMANDS.contains(&command.as_str()))
+}
+
+/// Parses the arguments after the program name, the error says what is wrong with them.
+pub fn parse(args: &[String]) -> std::result::Result<Command, String> {
+    let (command, rest) = args.split_first().ok_or("no command given")?;
+
+    let command = match command.as_str() {
+        "list" => Command::List,
+        "show" => Command::Show(id(rest)?),
+        "close" => Command::Close(id(rest)?),
+        "export" => Command::Export,
+        "import" => Command::Import,
+        "help" | "--help" => Command::Help,
+        "append" => {
+            let text = rest.get(1).ok_or("append needs the text to add, or -")?;
+            Command::Append {
+                id: id(rest)?,
+                text: text_arg(text),
+            }
+        }
+        "new" => {
+            let mut color = None;
+            let mut text = Text::Arg(String::new());
+
+            let mut options = rest.iter();
+            while let Some(option) = options.next() {
+                let mut value = || {
+                    options
+                        .next()
+                        .ok_or_else(|| format!("{option} needs a value"))
+                };
+
+                match option.as_str() {
+                    "--color" => color = Some(value()?.clone()),
+                    "--text" => text = text_arg(value()?),
+                    _ => return Err(format!("unknown option {option}")),
+                }
+            }
+
+            return Ok(Command::New { color, text });
+        }
+        _ => return Err(format!("unknown command {command}")),
+    };
+
+    let expected = match command {
+        Command::Show(_) | Command::Close(_) => 1,
+        Command::Append { .. } => 2,
+        _ => 0,
+    };
+    if rest.len() > expected {
+        return Err(format!("unexpected argument {}", rest[expected]));
+    }
+
+    Ok(command)
+}
+
+fn id(args: &[String]) -> std::result::Result<Uuid, String> {
+    let id = args.first().ok_or("missing the id of the note")?;
+
+    Uuid::parse_str(id).map_err(|_| format!("{id} is not a note id"))
+}
+
+fn text_arg(arg: &str) -> Text {
+    if arg == "-" {
+        Text::Stdin
+    } else {
+        Text::Arg(arg.to_string())
+    }
+}
+
+/// Runs `md-sticky <command>` on the terminal, returns the exit code.
+pub fn main(args: &[String], app_data_dir: &Path) -> i32 {
+    let command = match parse(args) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("md-sticky: {e}\n\n{USAGE}");
+            return 2;
+        }
+    };
+
+    match run(command, app_data_dir, &mut io::stdin(), &mut io::stdout()) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("md-sticky: {e}");
+            1
+        }
+    }
+}
+
+/// Runs a command, printing what it gives back to `stdout`. The request goes to the running app if there is one,
+/// and to the store in `app_data_dir` if the app isn't running.
+pub fn run(
+    command: Command,
+    app_data_dir: &Path,
+    stdin: &mut dyn Read,
+    stdout: &mut dyn Write,
+) -> Result<()> {
+    let mut read_text = |text: Text| -> Result<String> {
+        match text {
+            Text::Arg(text) => Ok(text),
+            Text::Stdin => {
+                let mut text = String::new();
+                stdin.read_to_string(&mut text)?;
+                Ok(text)
+            }
+        }
+    };
+
+    let output = command_output(&command);
+
+    let request = match command {
+        Command::Help => {
+            writeln!(stdout, "{USAGE}")?;
+            return Ok(());
+        }
+        Command::List | Command::Export => Request::List,
+        Command::Show(id) => Request::Show { id },
+        Command::New { color, text } => Request::New {
+            color,
+            text: read_text(text)?,
+        },
+        Command::Append { id, text } => Request::Append {
+            id,
+            text: read_text(text)?,
+        },
+        Command::Close(id) => Request::Close { id },
+        Command::Import => Request::Import {
+            notes: format::parse(&read_text(Text::Stdin)?)?.notes,
+        },
+    };
+
+    let reply = send(request, app_data_dir)?;
+
+    match (output, reply) {
+        (Output::List, Reply::Notes(notes)) => {
+            for note in notes {
+                writeln!(stdout, "{}\t{}\t{}", note.id, note.color, note.title())?;
+            }
+        }
+        (Output::Export, Reply::Notes(notes)) => {
+            serde_json::to_writer_pretty(&mut *stdout, &SaveFile::new(notes))?;
+            writeln!(stdout)?;
+        }
+        (Output::Markdown, Reply::Note(note)) => {
+            write!(stdout, "{}", markdown::from_contents(&note.contents)?)?
+        }
+        (Output::Id, Reply::Note(Note { id, .. })) => writeln!(stdout, "{id}")?,
+        (Output::Nothing, _) => {}
+        (_, reply) => {
+            return Err(StickyError::Control(format!(
+                "unexpected answer from md-sticky: {}",
+                serde_json::to_string(&reply)?
+            )))
+        }
+    }
+
+    Ok(())
+}
+
+/// How the reply to a command is printed.
+enum Output {
+    List,
+    Export,
+    Markdown,
+    Id,
+    Nothing,
+}
+
+fn command_output(command: &Command) -> Output {
+    match command {
+        Command::List => Output::List,
+        Command::Export => Output::Export,
+        Command::Show(_) => Output::Markdown,
+        Command::New { .. } => Output::Id,
+        _ => Output::Nothing,
+    }
+}
+
+fn send(request: Request, app_data_dir: &Path) -> Result<Reply> {
+    if let Some(mut client) = Client::connect(app_data_dir)? {
+        return client.send(&request);
+    }
+
+    // an app that holds the lock would save its own notes over what is written here,
+    // and holding it keeps the app from starting until the command is done
+    let _instance = InstanceLock::acquire(app_data_dir)?.ok_or_else(|| {
+        StickyError::Control("md-sticky is running but not taking requests".to_string())
+    })?;
+
+    let settings = Settings::load(app_data_dir)?;
+    let mut note_store = store::open(app_data_dir, settings.storage, &settings.backups)?;
+
+    control::execute(request, &mut note_store)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split(' ').map(str::to_string).collect()
+    }
+
+    fn run_line(line: &str, stdin: &str, dir: &Path) -> String {
+        let mut stdout = Vec::new();
+
+        run(
+            parse(&args(line)).unwrap(),
+            dir,
+            &mut stdin.as_bytes(),
+            &mut stdout,
+        )
+        .unwrap();
+
+        String::from_utf8(stdout).unwrap()
+    }
+
+    #[test]
+    fn arguments_are_parsed_into_commands() {
+        let id = Uuid::from_u128(1);
+
+        assert_eq!(parse(&args("list")), Ok(Command::List));
+        assert_eq!(parse(&args(&format!("show {id}"))), Ok(Command::Show(id)));
+        assert_eq!(
+            parse(&args(&format!("append {id} -"))),
+            Ok(Command::Append {
+                id,
+                text: Text::Stdin
+            })
+        );
+        assert_eq!(
+            parse(&args("new --text hi --color #81B7DD")),
+            Ok(Command::New {
+                color: Some("#81B7DD".to_string()),
+                text: Text::Arg("hi".to_string())
+            })
+        );
+
+        assert!(parse(&args("show 12")).is_err());
+        assert!(parse(&args("new --color")).is_err());
+        assert!(parse(&args("list everything")).is_err());
+        assert!(is_command(&args("export")));
+        assert!(!is_command(&args("md-sticky://new")));
+    }
+
+    #[test]
+    fn commands_work_on_the_store_without_the_app() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let id = run_line("new --text -", "# Shopping\n", dir.path());
+        let id = id.trim();
+
+        run_line(&format!("append {id} -"), "- [ ] milk\n", dir.path());
+        assert_eq!(
+            run_line("list", "", dir.path()),
+            format!("{id}\t#fff9b1\tShopping\n")
+        );
+        assert_eq!(
+            run_line(&format!("show {id}"), "", dir.path()),
+            "# Shopping\n- [ ] milk\n"
+        );
+
+        let export = run_line("export", "", dir.path());
+        run_line(&format!("close {id}"), "", dir.path());
+        assert_eq!(run_line("list", "", dir.path()), "");
+
+        // importing an export brings the notes back as they were
+        run_line("import", &export, dir.path());
+        assert_eq!(run_line("export", "", dir.path()), export);
+    }
+
+    #[test]
+    fn the_store_is_left_alone_while_the_app_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        let _app = InstanceLock::acquire(dir.path()).unwrap().unwrap();
+
+        let result = run(
+            Command::New {
+                color: None,
+                text: Text::Arg("hi".to_string()),
+            },
+            dir.path(),
+            &mut io::empty(),
+            &mut Vec::new(),
+        );
+
+        assert!(matches!(result, Err(StickyError::Control(_))));
+    }
+}
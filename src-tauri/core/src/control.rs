@@ -0,0 +1,738 @@
+use crate::delta::Delta;
+use crate::error::{Result, StickyError};
+use crate::link;
+use crate::markdown;
+use crate::model::{self, Note, NoteUpdate};
+use crate::palette;
+use crate::store::NoteStore;
+use crate::trash::TrashedNote;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::io::{BufRead, Write};
+use std::path::Path;
+use uuid::Uuid;
+
+/// The socket the running app takes requests on, in the app data dir.
+pub const SOCKET_FILE: &str = "control.sock";
+/// On windows, the loopback port the running app takes requests on and the token for it, as `<port> <token>`.
+pub const PORT_FILE: &str = "control.port";
+
+/// where a note made outside the app shows up, far enough from the corner to clear menu bars and docks
+const NEW_NOTE_POSITION: u32 = 100;
+
+/// Something to do to the notes, from the command line or another program.
+/// On the control socket it is a JSON object like `{ "command": "show", "id": "..." }`.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Request {
+    /// every open note
+    List,
+    Show {
+        id: Uuid,
+    },
+    /// a note with `text` as its Markdown, in the first palette color unless `color` is set
+    New {
+        #[serde(default)]
+        color: Option<String>,
+        #[serde(default)]
+        text: String,
+    },
+    /// adds `text` as Markdown lines at the end of the note
+    Append {
+        id: Uuid,
+        text: String,
+    },
+    /// replaces what the note says, `contents` is a quill delta like [`Note::contents`]
+    SetContents {
+        id: Uuid,
+        contents: String,
+    },
+    /// brings the note's window to the front
+    Focus {
+        id: Uuid,
+    },
+    SetColor {
+        id: Uuid,
+        color: String,
+    },
+    /// hides every note if any is showing, shows them all otherwise
+    ToggleAllVisible,
+    /// closes the note, it goes to the trash
+    Close {
+        id: Uuid,
+    },
+    /// adds the notes, replacing the open notes that have the same id
+    Import {
+        notes: Vec<Note>,
+    },
+    /// md-sticky was launched again while running, with these arguments, `md-sticky://` links among them are followed
+    Launch {
+        #[serde(default)]
+        args: Vec<String>,
+    },
+}
+
+/// What a [`Request`] gives back, `null`, a note or a list of notes on the control socket.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Reply {
+    Done,
+    Note(Note),
+    Notes(Vec<Note>),
+}
+
+/// One line sent back on the control socket, `{ "ok": reply }` or `{ "error": { "kind", "message" } }`.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Response {
+    Ok(Reply),
+    Error { kind: String, message: String },
+}
+
+impl From<Result<Reply>> for Response {
+    fn from(result: Result<Reply>) -> Response {
+        match result {
+            Ok(reply) => Response::Ok(reply),
+            Err(e) => Response::Error {
+                kind: e.kind().to_string(),
+                message: e.to_string(),
+            },
+        }
+    }
+}
+
+/// The notes a [`Request`] works on: the windows of the running app, or the store when the app isn't running.
+pub trait Notes {
+    /// Every open note.
+    fn list(&mut self) -> Result<Vec<Note>>;
+
+    /// Opens a note that isn't open yet.
+    fn create(&mut self, note: Note) -> Result<()>;
+
+    /// Changes an open note.
+    fn update(&mut self, id: Uuid, update: NoteUpdate) -> Result<()>;
+
+    /// Closes an open note, it goes to the trash.
+    fn close(&mut self, id: Uuid) -> Result<()>;
+
+    /// Brings an open note to the front.
+    fn focus(&mut self, id: Uuid) -> Result<()>;
+
+    /// Hides every note if any is showing, shows them all otherwise.
+    fn toggle_all_visible(&mut self) -> Result<()>;
+
+    fn set_color(&mut self, id: Uuid, color: String) -> Result<()> {
+        let update = NoteUpdate {
+            color: Some(color),
+            ..Default::default()
+        };
+
+        self.update(id, update)
+    }
+}
+
+/// Works on the store directly, for when the app isn't running to take the request.
+impl Notes for Box<dyn NoteStore> {
+    fn list(&mut self) -> Result<Vec<Note>> {
+        self.load_all()
+    }
+
+    fn create(&mut self, note: Note) -> Result<()> {
+        self.upsert(&note)
+    }
+
+    fn update(&mut self, id: Uuid, update: NoteUpdate) -> Result<()> {
+        let mut note = find(self, id)?;
+        note.apply(update);
+
+        self.upsert(&note)
+    }
+
+    fn close(&mut self, id: Uuid) -> Result<()> {
+        let note = find(self, id)?;
+
+        self.upsert_trashed(&TrashedNote {
+            closed_at: Utc::now(),
+            note,
+        })
+    }
+
+    // there is no window to bring to the front
+    fn focus(&mut self, id: Uuid) -> Result<()> {
+        find(self, id).map(|_| ())
+    }
+
+    // or to show or hide
+    fn toggle_all_visible(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Carries out `request` on `notes`.
+pub fn execute(request: Request, notes: &mut dyn Notes) -> Result<Reply> {
+    match request {
+        Request::List => Ok(Reply::Notes(notes.list()?)),
+        Request::Show { id } => Ok(Reply::Note(find(notes, id)?)),
+        Request::New { color, text } => {
+            let note = Note {
+                id: Uuid::new_v4(),
+                color: color.unwrap_or_else(|| palette::DEFAULT_COLORS[0].to_string()),
+                contents: markdown::to_contents(&text)?,
+                x: NEW_NOTE_POSITION,
+                y: NEW_NOTE_POSITION,
+                height: model::DEFAULT_HEIGHT,
+                width: model::DEFAULT_WIDTH,
+                ..Default::default()
+            };
+
+            notes.create(note.clone())?;
+
+            Ok(Reply::Note(note))
+        }
+        Request::Append { id, text } => {
+            let mut delta = Delta::parse(&find(notes, id)?.contents)?;
+            delta.append(markdown::parse(&text));
+
+            let update = NoteUpdate {
+                contents: Some(delta.to_json()?),
+                ..Default::default()
+            };
+            notes.update(id, update)?;
+
+            Ok(Reply::Done)
+        }
+        Request::SetContents { id, contents } => {
+            // make sure the window gets something quill can show
+            Delta::parse(&contents)?;
+
+            let update = NoteUpdate {
+                contents: Some(contents),
+                ..Default::default()
+            };
+            notes.update(id, update)?;
+
+            Ok(Reply::Done)
+        }
+        Request::Focus { id } => {
+            notes.focus(id)?;
+
+            Ok(Reply::Done)
+        }
+        Request::SetColor { id, color } => {
+            notes.set_color(id, color)?;
+
+            Ok(Reply::Done)
+        }
+        Request::ToggleAllVisible => {
+            notes.toggle_all_visible()?;
+
+            Ok(Reply::Done)
+        }
+        Request::Close { id } => {
+            notes.close(id)?;
+
+            Ok(Reply::Done)
+        }
+        Request::Import { notes: imported } => {
+            let open = notes
+                .list()?
+                .iter()
+                .map(|note| note.id)
+                .collect::<HashSet<Uuid>>();
+
+            for note in imported {
+                if open.contains(&note.id) {
+                    let update = NoteUpdate {
+                        contents: Some(note.contents),
+                        color: Some(note.color),
+                        x: Some(note.x),
+                        y: Some(note.y),
+                        height: Some(note.height),
+                        width: Some(note.width),
+                    };
+                    notes.update(note.id, update)?;
+                } else {
+                    notes.create(note)?;
+                }
+            }
+
+            Ok(Reply::Done)
+        }
+        Request::Launch { args } => {
+            let (links, others) = args
+                .into_iter()
+                .partition::<Vec<String>, _>(|arg| link::is_link(arg));
+
+            if !others.is_empty() {
+                log::info!("nothing to do with the arguments of a second launch: {others:?}");
+            }
+
+            // launching the app again brings its notes to the front, opening a link does what the link says
+            if links.is_empty() {
+                for note in notes.list()? {
+                    notes.focus(note.id)?;
+                }
+            }
+            for link in links {
+                execute(link::parse(&link)?, notes)?;
+            }
+
+            Ok(Reply::Done)
+        }
+    }
+}
+
+fn find<N: Notes + ?Sized>(notes: &mut N, id: Uuid) -> Result<Note> {
+    notes
+        .list()?
+        .into_iter()
+        .find(|note| note.id == id)
+        .ok_or(StickyError::NoteNotFound(id))
+}
+
+/// Answers the requests on a connection with `handler`, one JSON object per line each way.
+fn serve(
+    reader: impl BufRead,
+    mut writer: impl Write,
+    handler: &dyn Fn(Request) -> Result<Reply>,
+) -> Result<()> {
+    for line in reader.lines() {
+        let line = line?;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => Response::from(handler(request)),
+            Err(e) => Response::Error {
+                kind: "invalid_request".to_string(),
+                message: format!("invalid request: {e}"),
+            },
+        };
+
+        serde_json::to_writer(&mut writer, &response)?;
+        writer.write_all(b"\n")?;
+    }
+
+    Ok(())
+}
+
+/// Sends `request` on a connection to the app and waits for it to be carried out.
+fn exchange(
+    reader: &mut impl BufRead,
+    writer: &mut impl Write,
+    request: &Request,
+) -> Result<Reply> {
+    serde_json::to_writer(&mut *writer, request)?;
+    writer.write_all(b"\n")?;
+
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Err(StickyError::Control(
+            "md-sticky closed the connection without answering".to_string(),
+        ));
+    }
+
+    match serde_json::from_str(&line)? {
+        Response::Ok(reply) => Ok(reply),
+        Response::Error { message, .. } => Err(StickyError::Control(message)),
+    }
+}
+
+#[cfg(unix)]
+pub use socket::{listen, Client};
+
+#[cfg(not(unix))]
+pub use loopback::{listen, Client};
+
+/// On linux and macos the control socket is a unix domain socket.
+#[cfg(unix)]
+mod socket {
+    use super::*;
+    use crate::error::LogErr;
+    use std::fs;
+    use std::io::{self, BufReader};
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::sync::Arc;
+    use std::thread;
+
+    /// Takes requests on the control socket in the app data dir and answers them with `handler`.
+    /// Every connection gets a thread of its own and can send any number of requests, one JSON object per line.
+    pub fn listen(
+        app_data_dir: &Path,
+        handler: impl Fn(Request) -> Result<Reply> + Send + Sync + 'static,
+    ) -> Result<()> {
+        let path = app_data_dir.join(SOCKET_FILE);
+
+        // left behind by an instance that didn't get to clean up, the instance lock makes sure it isn't in use
+        if path.exists() {
+            fs::remove_file(&path)?;
+        }
+
+        let listener = UnixListener::bind(&path)?;
+        // the socket can change every note, it's nobody else's business
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+
+        let handler = Arc::new(handler);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let handler = handler.clone();
+                        thread::spawn(move || {
+                            stream
+                                .try_clone()
+                                .map_err(StickyError::from)
+                                .and_then(|reader| {
+                                    serve(BufReader::new(reader), stream, handler.as_ref())
+                                })
+                                .log_err("control socket connection failed");
+                        });
+                    }
+                    Err(e) => log::warn!("could not accept a control socket connection: {e}"),
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    /// A connection to the control socket of the running app.
+    #[derive(Debug)]
+    pub struct Client {
+        reader: BufReader<UnixStream>,
+        writer: UnixStream,
+    }
+
+    impl Client {
+        /// Connects to the app using `app_data_dir`, `None` if it isn't running.
+        pub fn connect(app_data_dir: &Path) -> Result<Option<Client>> {
+            match UnixStream::connect(app_data_dir.join(SOCKET_FILE)) {
+                Ok(stream) => Ok(Some(Client {
+                    reader: BufReader::new(stream.try_clone()?),
+                    writer: stream,
+                })),
+                // no socket, or one left behind by an app that isn't running anymore
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused
+                    ) =>
+                {
+                    Ok(None)
+                }
+                Err(e) => Err(e.into()),
+            }
+        }
+
+        /// Sends `request` to the app and waits for it to be carried out.
+        pub fn send(&mut self, request: &Request) -> Result<Reply> {
+            exchange(&mut self.reader, &mut self.writer, request)
+        }
+    }
+}
+
+/// Without unix domain sockets the app takes requests on a loopback port, which [`PORT_FILE`] names.
+/// Any local user can reach the port, so a connection first sends the token from the file, which only this user
+/// can read.
+#[cfg(any(not(unix), test))]
+mod loopback {
+    use super::*;
+    use crate::error::LogErr;
+    use crate::storage;
+    use std::fs;
+    use std::io::{self, BufReader};
+    use std::net::{Ipv4Addr, TcpListener, TcpStream};
+    use std::sync::Arc;
+    use std::thread;
+
+    /// Takes requests on a loopback port and answers them with `handler`, like the control socket.
+    pub fn listen(
+        app_data_dir: &Path,
+        handler: impl Fn(Request) -> Result<Reply> + Send + Sync + 'static,
+    ) -> Result<()> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+        let port = listener.local_addr()?.port();
+        let token = Uuid::new_v4().simple().to_string();
+
+        // replaces the file of an instance that didn't get to clean up, the instance lock makes sure it isn't running
+        storage::write_atomic(
+            &app_data_dir.join(PORT_FILE),
+            format!("{port} {token}").as_bytes(),
+        )?;
+
+        let handler = Arc::new(handler);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let handler = handler.clone();
+                        let token = token.clone();
+                        thread::spawn(move || {
+                            serve_with_token(stream, &token, handler.as_ref())
+                                .log_err("control connection failed");
+                        });
+                    }
+                    Err(e) => log::warn!("could not accept a control connection: {e}"),
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    fn serve_with_token(
+        stream: TcpStream,
+        token: &str,
+        handler: &dyn Fn(Request) -> Result<Reply>,
+    ) -> Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+
+        let mut first_line = String::new();
+        reader.read_line(&mut first_line)?;
+        if first_line.trim() != token {
+            return Err(StickyError::Control(
+                "a control connection didn't have the token".to_string(),
+            ));
+        }
+
+        serve(reader, stream, handler)
+    }
+
+    /// A connection to the running app over its loopback port.
+    #[derive(Debug)]
+    pub struct Client {
+        reader: BufReader<TcpStream>,
+        writer: TcpStream,
+    }
+
+    impl Client {
+        /// Connects to the app using `app_data_dir`, `None` if it isn't running.
+        pub fn connect(app_data_dir: &Path) -> Result<Option<Client>> {
+            let port_file = match fs::read_to_string(app_data_dir.join(PORT_FILE)) {
+                Ok(port_file) => port_file,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+                Err(e) => return Err(e.into()),
+            };
+
+            let (port, token) = port_file
+                .trim()
+                .split_once(' ')
+                .and_then(|(port, token)| Some((port.parse::<u16>().ok()?, token)))
+                .ok_or_else(|| {
+                    StickyError::Control(format!("{PORT_FILE} doesn't say which port to use"))
+                })?;
+
+            let mut writer = match TcpStream::connect((Ipv4Addr::LOCALHOST, port)) {
+                Ok(stream) => stream,
+                // left behind by an app that isn't running anymore
+                Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => return Ok(None),
+                Err(e) => return Err(e.into()),
+            };
+            writer.write_all(format!("{token}\n").as_bytes())?;
+
+            Ok(Some(Client {
+                reader: BufReader::new(writer.try_clone()?),
+                writer,
+            }))
+        }
+
+        /// Sends `request` to the app and waits for it to be carried out.
+        pub fn send(&mut self, request: &Request) -> Result<Reply> {
+            exchange(&mut self.reader, &mut self.writer, request)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backup::BackupPolicy;
+    use crate::storage::Backend;
+    use crate::store::FileStore;
+    use std::fs;
+
+    fn file_store(dir: &Path) -> Box<dyn NoteStore> {
+        Box::new(FileStore::new(dir, Backend::Json, BackupPolicy::default()))
+    }
+
+    fn note(reply: Reply) -> Note {
+        match reply {
+            Reply::Note(note) => note,
+            reply => panic!("expected a note, got {reply:?}"),
+        }
+    }
+
+    #[test]
+    fn requests_work_on_the_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = file_store(dir.path());
+
+        let new = Request::New {
+            color: None,
+            text: "# Shopping".to_string(),
+        };
+        let id = note(execute(new, &mut store).unwrap()).id;
+
+        let append = Request::Append {
+            id,
+            text: "- [ ] milk".to_string(),
+        };
+        execute(append, &mut store).unwrap();
+
+        let set_color = Request::SetColor {
+            id,
+            color: "#81B7DD".to_string(),
+        };
+        execute(set_color, &mut store).unwrap();
+        execute(Request::Focus { id }, &mut store).unwrap();
+
+        let shown = note(execute(Request::Show { id }, &mut file_store(dir.path())).unwrap());
+        assert_eq!(shown.color, "#81B7DD");
+        assert_eq!(
+            markdown::from_contents(&shown.contents).unwrap(),
+            "# Shopping\n- [ ] milk\n"
+        );
+
+        execute(Request::Close { id }, &mut store).unwrap();
+        assert_eq!(
+            execute(Request::List, &mut store).unwrap(),
+            Reply::Notes(Vec::new())
+        );
+        assert_eq!(store.list_trash().unwrap()[0].note.id, id);
+        assert!(matches!(
+            execute(Request::Show { id }, &mut store),
+            Err(StickyError::NoteNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn importing_replaces_notes_with_the_same_id() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = file_store(dir.path());
+
+        let new = Request::New {
+            color: None,
+            text: "old".to_string(),
+        };
+        let mut changed = note(execute(new, &mut store).unwrap());
+        changed.contents = markdown::to_contents("new").unwrap();
+        let added = Note {
+            id: Uuid::from_u128(7),
+            ..changed.clone()
+        };
+
+        let import = Request::Import {
+            notes: vec![changed.clone(), added.clone()],
+        };
+        execute(import, &mut store).unwrap();
+
+        assert_eq!(
+            execute(Request::List, &mut store).unwrap(),
+            Reply::Notes(vec![changed, added])
+        );
+    }
+
+    #[test]
+    fn launching_with_a_link_follows_it() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = file_store(dir.path());
+
+        let launch = Request::Launch {
+            args: vec!["md-sticky://new?text=%23%20Inbox".to_string()],
+        };
+        execute(launch, &mut store).unwrap();
+
+        let notes = match execute(Request::List, &mut store).unwrap() {
+            Reply::Notes(notes) => notes,
+            reply => panic!("expected notes, got {reply:?}"),
+        };
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].title(), "Inbox");
+
+        let open = Request::Launch {
+            args: vec![format!("md-sticky://open/{}", Uuid::nil())],
+        };
+        assert!(matches!(
+            execute(open, &mut store),
+            Err(StickyError::NoteNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn requests_and_replies_are_plain_json() {
+        let id = Uuid::nil();
+
+        assert_eq!(
+            serde_json::to_value(Request::Close { id }).unwrap(),
+            serde_json::json!({ "command": "close", "id": id })
+        );
+        assert_eq!(
+            serde_json::from_str::<Request>(r#"{ "command": "new" }"#).unwrap(),
+            Request::New {
+                color: None,
+                text: String::new()
+            }
+        );
+        assert_eq!(
+            serde_json::to_string(&Response::Ok(Reply::Done)).unwrap(),
+            r#"{"ok":null}"#
+        );
+    }
+
+    #[test]
+    fn requests_are_forwarded_over_the_loopback_port() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(loopback::Client::connect(dir.path()).unwrap().is_none());
+
+        loopback::listen(dir.path(), |request| match request {
+            Request::List => Ok(Reply::Notes(Vec::new())),
+            _ => Err(StickyError::NoteNotFound(Uuid::nil())),
+        })
+        .unwrap();
+
+        let mut client = loopback::Client::connect(dir.path()).unwrap().unwrap();
+        assert_eq!(
+            client.send(&Request::List).unwrap(),
+            Reply::Notes(Vec::new())
+        );
+        assert!(client.send(&Request::Close { id: Uuid::nil() }).is_err());
+
+        // without the token the app doesn't answer
+        let port_file = fs::read_to_string(dir.path().join(PORT_FILE)).unwrap();
+        let (port, _token) = port_file.split_once(' ').unwrap();
+        fs::write(dir.path().join(PORT_FILE), format!("{port} wrong")).unwrap();
+        let mut client = loopback::Client::connect(dir.path()).unwrap().unwrap();
+        assert!(client.send(&Request::List).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn requests_are_forwarded_over_the_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(Client::connect(dir.path()).unwrap().is_none());
+
+        listen(dir.path(), |request| match request {
+            Request::List => Ok(Reply::Notes(Vec::new())),
+            _ => Err(StickyError::NoteNotFound(Uuid::nil())),
+        })
+        .unwrap();
+
+        let mut client = Client::connect(dir.path()).unwrap().unwrap();
+        assert_eq!(
+            client.send(&Request::List).unwrap(),
+            Reply::Notes(Vec::new())
+        );
+
+        let error = client
+            .send(&Request::Close { id: Uuid::nil() })
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            StickyError::NoteNotFound(Uuid::nil()).to_string()
+        );
+    }
+}
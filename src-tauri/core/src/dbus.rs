@@ -0,0 +1,241 @@
+use crate::control::{Reply, Request};
+use crate::error::{Result, StickyError};
+use std::sync::Arc;
+use uuid::Uuid;
+use zbus::blocking::connection::{Builder, Connection};
+use zbus::fdo;
+use zbus::object_server::SignalEmitter;
+
+/// The well-known name md-sticky takes on the session bus.
+pub const BUS_NAME: &str = "io.github.mdsticky";
+pub const OBJECT_PATH: &str = "/io/github/mdsticky";
+pub const INTERFACE: &str = "io.github.mdsticky.Notes";
+
+type Handler = Arc<dyn Fn(Request) -> Result<Reply> + Send + Sync>;
+
+/// md-sticky on the session bus, so window manager keybindings and panel applets can drive it with
+/// `gdbus call --session --dest io.github.mdsticky --object-path /io/github/mdsticky --method io.github.mdsticky.Notes.NewNote ...`.
+pub struct DbusService {
+    connection: Connection,
+}
+
+impl DbusService {
+    /// Registers on the session bus, answering method calls with `handler`.
+    pub fn start(
+        handler: impl Fn(Request) -> Result<Reply> + Send + Sync + 'static,
+    ) -> Result<DbusService> {
+        DbusService::build(Builder::session()?, Arc::new(handler))
+    }
+
+    /// Registers on the bus at `address` instead of the session bus.
+    pub fn start_at(
+        address: &str,
+        handler: impl Fn(Request) -> Result<Reply> + Send + Sync + 'static,
+    ) -> Result<DbusService> {
+        DbusService::build(Builder::address(address)?, Arc::new(handler))
+    }
+
+    fn build(builder: Builder<'_>, handler: Handler) -> Result<DbusService> {
+        let connection = builder
+            .name(BUS_NAME)?
+            .serve_at(OBJECT_PATH, Notes { handler })?
+            .build()?;
+
+        Ok(DbusService { connection })
+    }
+
+    /// Sends the `NotesChanged` signal, for after notes were created, changed or closed.
+    pub fn notes_changed(&self) -> Result<()> {
+        self.connection
+            .emit_signal(None::<&str>, OBJECT_PATH, INTERFACE, "NotesChanged", &())?;
+
+        Ok(())
+    }
+}
+
+struct Notes {
+    handler: Handler,
+}
+
+impl Notes {
+    fn send(&self, request: Request) -> fdo::Result<Reply> {
+        (self.handler)(request).map_err(|e| match e {
+            StickyError::NoteNotFound(_) => fdo::Error::InvalidArgs(e.to_string()),
+            e => fdo::Error::Failed(e.to_string()),
+        })
+    }
+}
+
+fn parse_id(id: &str) -> fdo::Result<Uuid> {
+    Uuid::parse_str(id).map_err(|_| fdo::Error::InvalidArgs(format!("{id} is not a note id")))
+}
+
+#[zbus::interface(name = "io.github.mdsticky.Notes")]
+impl Notes {
+    /// Opens a note with Markdown `text`, an empty `color` picks the first palette color. Returns the note's id.
+    fn new_note(&self, text: String, color: String) -> fdo::Result<String> {
+        let request = Request::New {
+            color: Some(color).filter(|color| !color.is_empty()),
+            text,
+        };
+
+        match self.send(request)? {
+            Reply::Note(note) => Ok(note.id.to_string()),
+            _ => Err(fdo::Error::Failed("no note was created".to_string())),
+        }
+    }
+
+    fn focus_note(&self, id: String) -> fdo::Result<()> {
+        self.send(Request::Focus { id: parse_id(&id)? })?;
+
+        Ok(())
+    }
+
+    /// Every open note as `(id, color, title)`, the title being its first line.
+    fn list_notes(&self) -> fdo::Result<Vec<(String, String, String)>> {
+        match self.send(Request::List)? {
+            Reply::Notes(notes) => Ok(notes
+                .into_iter()
+                .map(|note| (note.id.to_string(), note.color.clone(), note.title()))
+                .collect()),
+            _ => Err(fdo::Error::Failed("no notes were listed".to_string())),
+        }
+    }
+
+    /// Hides every note if any is showing, shows them all otherwise.
+    fn toggle_all_visible(&self) -> fdo::Result<()> {
+        self.send(Request::ToggleAllVisible)?;
+
+        Ok(())
+    }
+
+    fn close_note(&self, id: String) -> fdo::Result<()> {
+        self.send(Request::Close { id: parse_id(&id)? })?;
+
+        Ok(())
+    }
+
+    #[zbus(signal)]
+    async fn notes_changed(emitter: &SignalEmitter<'_>) -> zbus::Result<()>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backup::BackupPolicy;
+    use crate::control;
+    use crate::storage::Backend;
+    use crate::store::{FileStore, NoteStore};
+    use std::fs;
+    use std::io::{BufRead, BufReader};
+    use std::process::{Child, Command, Stdio};
+    use std::sync::Mutex;
+    use zbus::blocking::{MessageIterator, Proxy};
+    use zbus::MatchRule;
+
+    /// A bus of its own, so the test doesn't depend on or disturb the session bus.
+    struct PrivateBus {
+        daemon: Child,
+        address: String,
+    }
+
+    impl PrivateBus {
+        /// Needs `dbus-daemon`, like the app needs a session bus.
+        fn start(dir: &std::path::Path) -> PrivateBus {
+            let config = dir.join("bus.conf");
+            fs::write(
+                &config,
+                format!(
+                    r#"<!DOCTYPE busconfig PUBLIC "-//freedesktop//DTD D-Bus Bus Configuration 1.0//EN"
+ "http://www.freedesktop.org/standards/dbus/1.0/busconfig.dtd">
+<busconfig>
+  <type>session</type>
+  <listen>unix:path={}</listen>
+  <auth>EXTERNAL</auth>
+  <policy context="default">
+    <allow send_destination="*" eavesdrop="true"/>
+    <allow eavesdrop="true"/>
+    <allow own="*"/>
+  </policy>
+</busconfig>"#,
+                    dir.join("bus").display()
+                ),
+            )
+            .unwrap();
+
+            let mut daemon = Command::new("dbus-daemon")
+                .arg(format!("--config-file={}", config.display()))
+                .args(["--nofork", "--print-address"])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .expect("could not start dbus-daemon, is it installed?");
+
+            let mut address = String::new();
+            BufReader::new(daemon.stdout.take().unwrap())
+                .read_line(&mut address)
+                .unwrap();
+
+            PrivateBus {
+                daemon,
+                address: address.trim().to_string(),
+            }
+        }
+    }
+
+    impl Drop for PrivateBus {
+        fn drop(&mut self) {
+            self.daemon.kill().ok();
+            self.daemon.wait().ok();
+        }
+    }
+
+    #[test]
+    fn methods_reach_the_notes_and_changes_are_signalled() {
+        let dir = tempfile::tempdir().unwrap();
+        let bus = PrivateBus::start(dir.path());
+
+        let store: Mutex<Box<dyn NoteStore>> = Mutex::new(Box::new(FileStore::new(
+            dir.path(),
+            Backend::Json,
+            BackupPolicy::default(),
+        )));
+        let service = DbusService::start_at(&bus.address, move |request| {
+            control::execute(request, &mut *store.lock().unwrap())
+        })
+        .unwrap();
+
+        let client = Builder::address(bus.address.as_str())
+            .unwrap()
+            .build()
+            .unwrap();
+        let proxy = Proxy::new(&client, BUS_NAME, OBJECT_PATH, INTERFACE).unwrap();
+
+        let id: String = proxy.call("NewNote", &("# Groceries", "")).unwrap();
+        let notes: Vec<(String, String, String)> = proxy.call("ListNotes", &()).unwrap();
+        assert_eq!(
+            notes,
+            vec![(id.clone(), "#fff9b1".to_string(), "Groceries".to_string())]
+        );
+
+        let rule = MatchRule::builder()
+            .msg_type(zbus::message::Type::Signal)
+            .interface(INTERFACE)
+            .unwrap()
+            .member("NotesChanged")
+            .unwrap()
+            .build();
+        let mut signals = MessageIterator::for_match_rule(rule, &client, None).unwrap();
+        service.notes_changed().unwrap();
+        assert!(signals.next().is_some());
+
+        let () = proxy.call("CloseNote", &(id.as_str(),)).unwrap();
+        let notes: Vec<(String, String, String)> = proxy.call("ListNotes", &()).unwrap();
+        assert!(notes.is_empty());
+
+        assert!(proxy
+            .call::<_, _, ()>("FocusNote", &(id.as_str(),))
+            .is_err());
+        assert!(proxy.call::<_, _, ()>("CloseNote", &("12",)).is_err());
+    }
+}
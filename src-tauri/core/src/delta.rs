@@ -0,0 +1,247 @@
+use crate::error::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// A quill document, as stored in [`crate::model::Note::contents`].
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct Delta {
+    pub ops: Vec<Op>,
+}
+
+/// One insert of a document. Text inserts carry inline formats like bold, and the `\n` that ends a line
+/// carries the formats of the whole line, like header or list.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Op {
+    pub insert: Insert,
+    #[serde(default, skip_serializing_if = "Attributes::is_empty")]
+    pub attributes: Attributes,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Insert {
+    Text(String),
+    /// images, videos and formulas, like `{ "image": "https://..." }`
+    Embed(Map<String, Value>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ListKind {
+    Bullet,
+    Ordered,
+    Checked,
+    Unchecked,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct Attributes {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bold: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub italic: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strike: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub header: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub list: Option<ListKind>,
+    /// how deep a list item is nested, missing for the top level
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub indent: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blockquote: Option<bool>,
+    /// `true` or the language of the block, quill 2 uses `"plain"` when there is none
+    #[serde(
+        default,
+        rename = "code-block",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub code_block: Option<Value>,
+    /// formats markdown has no equivalent for, like underline or color, kept so the delta survives a round trip
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+impl Attributes {
+    pub fn is_empty(&self) -> bool {
+        *self == Attributes::default()
+    }
+
+    /// The formats that apply to a stretch of text, as opposed to a whole line.
+    pub fn inline(&self) -> Attributes {
+        Attributes {
+            bold: self.bold,
+            italic: self.italic,
+            strike: self.strike,
+            code: self.code,
+            link: self.link.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// The language of a code block line, `None` for other lines or blocks without one.
+    pub fn code_language(&self) -> Option<&str> {
+        self.code_block
+            .as_ref()
+            .and_then(Value::as_str)
+            .filter(|language| *language != "plain")
+    }
+}
+
+/// A line of a document: the inserts on it, and the attributes of the `\n` that ends it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Line {
+    pub inserts: Vec<(Insert, Attributes)>,
+    pub attributes: Attributes,
+}
+
+impl Delta {
+    pub fn parse(contents: &str) -> Result<Delta> {
+        Ok(serde_json::from_str(contents)?)
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    /// Adds an insert to the end of the document, merging it into the last one if they are both text with the same formats,
+    /// the same way quill does.
+    pub fn push(&mut self, insert: Insert, attributes: Attributes) {
+        if matches!(&insert, Insert::Text(text) if text.is_empty()) {
+            return;
+        }
+
+        if let (Some(last), Insert::Text(text)) = (self.ops.last_mut(), &insert) {
+            if let Insert::Text(last_text) = &mut last.insert {
+                if last.attributes == attributes {
+                    last_text.push_str(text);
+                    return;
+                }
+            }
+        }
+
+        self.ops.push(Op { insert, attributes });
+    }
+
+    /// Adds the lines of `other` after the last line of the document. A blank document is replaced instead,
+    /// so it doesn't start with an empty line.
+    pub fn append(&mut self, other: Delta) {
+        let blank = self.ops.iter().all(|op| {
+            matches!(&op.insert, Insert::Text(text) if text.chars().all(|c| c == '\n'))
+                && op.attributes.is_empty()
+        });
+
+        if blank {
+            self.ops.clear();
+        } else if !matches!(self.ops.last(), Some(Op { insert: Insert::Text(text), .. }) if text.ends_with('\n'))
+        {
+            self.push(Insert::Text("\n".to_string()), Attributes::default());
+        }
+
+        for op in other.ops {
+            self.push(op.insert, op.attributes);
+        }
+    }
+
+    /// Splits the document into lines. A document that doesn't end in `\n` gets its last line anyway.
+    pub fn lines(&self) -> Vec<Line> {
+        let mut lines = Vec::new();
+        let mut line = Line::default();
+
+        for op in &self.ops {
+            match &op.insert {
+                Insert::Text(text) => {
+                    let mut parts = text.split('\n').peekable();
+
+                    while let Some(part) = parts.next() {
+                        if !part.is_empty() {
+                            line.inserts
+                                .push((Insert::Text(part.to_string()), op.attributes.clone()));
+                        }
+
+                        // every part but the last one was followed by a newline
+                        if parts.peek().is_some() {
+                            line.attributes = op.attributes.clone();
+                            lines.push(std::mem::take(&mut line));
+                        }
+                    }
+                }
+                Insert::Embed(_) => line
+                    .inserts
+                    .push((op.insert.clone(), op.attributes.clone())),
+            }
+        }
+
+        if !line.inserts.is_empty() {
+            lines.push(line);
+        }
+
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_take_the_attributes_of_their_newline() {
+        let delta = Delta::parse(
+            r#"{"ops":[{"insert":"Title"},{"insert":"\n","attributes":{"header":1}},{"insert":"one\ntwo","attributes":{"bold":true}},{"insert":"\n","attributes":{"list":"bullet"}}]}"#,
+        )
+        .unwrap();
+
+        let lines = delta.lines();
+
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0].attributes.header, Some(1));
+        assert_eq!(lines[1].attributes.bold, Some(true));
+        assert_eq!(lines[1].attributes.list, None);
+        assert_eq!(lines[2].attributes.list, Some(ListKind::Bullet));
+        assert_eq!(
+            lines[2].inserts,
+            vec![(
+                Insert::Text("two".to_string()),
+                Attributes {
+                    bold: Some(true),
+                    ..Default::default()
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn unknown_formats_survive_a_round_trip() {
+        let contents = r##"{"ops":[{"insert":"hi","attributes":{"underline":true,"color":"#e60000"}},{"insert":{"image":"cat.png"}},{"insert":"\n","attributes":{"align":"center"}}]}"##;
+
+        let delta = Delta::parse(contents).unwrap();
+
+        assert_eq!(
+            serde_json::from_str::<Value>(&delta.to_json().unwrap()).unwrap(),
+            serde_json::from_str::<Value>(contents).unwrap()
+        );
+    }
+
+    #[test]
+    fn appending_adds_lines_after_the_last_one() {
+        let mut delta = Delta::parse(r#"{"ops":[{"insert":"\n"}]}"#).unwrap();
+        delta.append(Delta::parse(r#"{"ops":[{"insert":"milk\n"}]}"#).unwrap());
+        assert_eq!(delta.to_json().unwrap(), r#"{"ops":[{"insert":"milk\n"}]}"#);
+
+        delta.append(
+            Delta::parse(
+                r#"{"ops":[{"insert":"eggs"},{"insert":"\n","attributes":{"list":"unchecked"}}]}"#,
+            )
+            .unwrap(),
+        );
+        assert_eq!(
+            delta.to_json().unwrap(),
+            r#"{"ops":[{"insert":"milk\neggs"},{"insert":"\n","attributes":{"list":"unchecked"}}]}"#
+        );
+    }
+}
@@ -0,0 +1,297 @@
+use crate::error::{LogErr, Result, StickyError};
+use crate::markdown;
+use crate::mirror;
+use crate::model::Note;
+use crate::watch::FileWatcher;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Child, Command};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// how long the file has to be left alone after a save before it is read, editors can write it in several steps
+const SAVE_QUIET: Duration = Duration::from_millis(50);
+
+/// counts the notes opened in an editor, so every open gets a temp folder of its own
+static OPENED: AtomicUsize = AtomicUsize::new(0);
+
+/// The editor command from the `editor` setting, or `$VISUAL` or `$EDITOR` when it isn't set,
+/// split into the program and its arguments.
+pub fn command(configured: Option<&str>) -> Result<Vec<String>> {
+    resolve_command(configured, |name| env::var(name).ok())
+}
+
+fn resolve_command(
+    configured: Option<&str>,
+    env_var: impl Fn(&str) -> Option<String>,
+) -> Result<Vec<String>> {
+    let command = configured
+        .map(str::to_string)
+        .into_iter()
+        .chain(env_var("VISUAL"))
+        .chain(env_var("EDITOR"))
+        .map(|command| split_command(&command))
+        .find(|words| !words.is_empty());
+
+    command.ok_or(StickyError::NoEditor)
+}
+
+/// Splits a command line on whitespace. Quotes keep a path with spaces in one word, there are no escapes
+/// so windows paths don't need doubled backslashes.
+pub fn split_command(command: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    // a quoted empty string is still a word
+    let mut in_word = false;
+    let mut quote = None;
+
+    for c in command.chars() {
+        match quote {
+            Some(open) if c == open => quote = None,
+            Some(_) => word.push(c),
+            None if c == '"' || c == '\'' => {
+                quote = Some(c);
+                in_word = true;
+            }
+            None if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            None => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+
+    if in_word {
+        words.push(word);
+    }
+
+    words
+}
+
+/// What happened in the editor since the last [`ExternalEditor::poll`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EditorPoll {
+    /// the note's new contents, if the file was saved with changes
+    pub saved: Option<String>,
+    /// the editor was closed, nothing it does from now on comes back to the note
+    pub exited: bool,
+}
+
+/// A note opened as Markdown in another program, like vim or VS Code. The note is written to a temp file
+/// that is read back into the note every time the editor saves it, until the editor exits.
+#[derive(Debug)]
+pub struct ExternalEditor {
+    child: Child,
+    dir: PathBuf,
+    path: PathBuf,
+    // `None` if the file couldn't be watched, it is then read on every poll
+    watcher: Option<FileWatcher>,
+    // what the file held last, so only real changes are reported
+    text: String,
+    exited: bool,
+}
+
+impl ExternalEditor {
+    /// Writes the note to `<title>.md` in a temp folder of its own and opens it with `command`. The folder is new
+    /// for every open, so the same note can be in two editors and closing one leaves the other's file alone.
+    pub fn open(note: &Note, command: &[String]) -> Result<ExternalEditor> {
+        let (program, args) = command.split_first().ok_or(StickyError::NoEditor)?;
+
+        let dir = env::temp_dir().join(format!(
+            "md-sticky-{}-{}-{}",
+            note.id,
+            process::id(),
+            OPENED.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&dir)?;
+
+        let path = dir.join(format!("{}.md", mirror::file_name(&note.title())));
+        let text = markdown::from_contents(&note.contents)?;
+        fs::write(&path, &text)?;
+
+        let watcher = FileWatcher::file(&path).log_err("could not watch the file being edited");
+
+        let child = match Command::new(program).args(args).arg(&path).spawn() {
+            Ok(child) => child,
+            Err(e) => {
+                fs::remove_dir_all(&dir).ok();
+                return Err(e.into());
+            }
+        };
+
+        Ok(ExternalEditor {
+            child,
+            dir,
+            path,
+            watcher,
+            text,
+            exited: false,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Checks whether the editor saved the file or exited. Once it exited the file is read one last time,
+    /// so a save right before quitting isn't lost.
+    pub fn poll(&mut self, now: Instant) -> Result<EditorPoll> {
+        if self.exited {
+            return Ok(EditorPoll {
+                saved: None,
+                exited: true,
+            });
+        }
+
+        let exited = self.child.try_wait()?.is_some();
+
+        let changed = match &self.watcher {
+            Some(watcher) => watcher.take_settled(now, SAVE_QUIET) || exited,
+            None => true,
+        };
+
+        let saved = if changed { self.read()? } else { None };
+
+        self.exited = exited;
+
+        Ok(EditorPoll { saved, exited })
+    }
+
+    // the note's contents if the file changed since it was last read
+    fn read(&mut self) -> Result<Option<String>> {
+        let text = match fs::read_to_string(&self.path) {
+            Ok(text) => text,
+            // editors that save by renaming can leave the file missing for a moment
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        if text == self.text {
+            return Ok(None);
+        }
+
+        let contents = markdown::to_contents(&text)?;
+        self.text = text;
+
+        Ok(Some(contents))
+    }
+}
+
+impl Drop for ExternalEditor {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.dir).log_err("could not remove the file of an external editor");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use uuid::Uuid;
+
+    #[test]
+    fn commands_are_split_into_words() {
+        assert_eq!(split_command("vim"), vec!["vim"]);
+        assert_eq!(split_command("  code  --wait "), vec!["code", "--wait"]);
+        assert_eq!(
+            split_command(r#""C:\Program Files\Notepad++\notepad++.exe" -multiInst"#),
+            vec![r"C:\Program Files\Notepad++\notepad++.exe", "-multiInst"]
+        );
+        assert_eq!(
+            split_command("kitty -e 'nvim' ''"),
+            vec!["kitty", "-e", "nvim", ""]
+        );
+        assert!(split_command("   ").is_empty());
+    }
+
+    #[test]
+    fn the_setting_wins_over_the_environment() {
+        let env_var = |name: &str| match name {
+            "VISUAL" => Some("  ".to_string()),
+            "EDITOR" => Some("nano -w".to_string()),
+            _ => None,
+        };
+
+        assert_eq!(
+            resolve_command(Some("code --wait"), env_var).unwrap(),
+            vec!["code", "--wait"]
+        );
+        // a blank setting or variable is skipped
+        assert_eq!(
+            resolve_command(Some(""), env_var).unwrap(),
+            vec!["nano", "-w"]
+        );
+        assert!(matches!(
+            resolve_command(None, |_| None),
+            Err(StickyError::NoEditor)
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn saves_come_back_until_the_editor_exits() {
+        let note = Note {
+            id: Uuid::from_u128(1),
+            contents:
+                r#"{"ops":[{"insert":"Shopping"},{"attributes":{"header":1},"insert":"\n"}]}"#
+                    .to_string(),
+            ..Default::default()
+        };
+        // the file is passed as `$0`
+        let command = [
+            "sh".to_string(),
+            "-c".to_string(),
+            r##"test "$(cat "$0")" = "# Shopping" && printf '# Shopping\n\n- milk\n' > "$0""##
+                .to_string(),
+        ];
+
+        let mut editor = ExternalEditor::open(&note, &command).unwrap();
+        assert!(editor.path().ends_with("Shopping.md"));
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut saves = Vec::new();
+        loop {
+            let poll = editor.poll(Instant::now()).unwrap();
+            saves.extend(poll.saved);
+
+            if poll.exited || Instant::now() > deadline {
+                break;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+
+        assert_eq!(
+            saves,
+            vec![markdown::to_contents("# Shopping\n\n- milk\n").unwrap()]
+        );
+        assert!(editor.poll(Instant::now()).unwrap().exited);
+
+        let dir = editor.path().parent().unwrap().to_path_buf();
+        drop(editor);
+        assert!(!dir.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn opening_a_note_twice_keeps_the_files_apart() {
+        let note = Note {
+            id: Uuid::from_u128(2),
+            contents: crate::model::EMPTY_CONTENTS.to_string(),
+            ..Default::default()
+        };
+        let command = ["true".to_string()];
+
+        let first = ExternalEditor::open(&note, &command).unwrap();
+        let second = ExternalEditor::open(&note, &command).unwrap();
+        assert_ne!(first.path(), second.path());
+
+        drop(first);
+        assert!(second.path().exists());
+    }
+}
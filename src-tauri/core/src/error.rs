@@ -0,0 +1,123 @@
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+use std::fmt::Display;
+use std::io;
+use uuid::Uuid;
+
+pub type Result<T, E = StickyError> = std::result::Result<T, E>;
+
+/// Everything that can go wrong in md-sticky. Commands return it to the frontend as `{ kind, message }`.
+#[derive(Debug, thiserror::Error)]
+pub enum StickyError {
+    #[error("{0}")]
+    Io(#[from] io::Error),
+    #[error("{0}")]
+    Json(#[from] serde_json::Error),
+    #[error("database error: {0}")]
+    Database(#[from] rusqlite::Error),
+    #[error("could not watch the notes: {0}")]
+    Watch(#[from] notify::Error),
+    #[cfg(target_os = "linux")]
+    #[error("D-Bus error: {0}")]
+    Dbus(#[from] zbus::Error),
+    #[error("invalid save file: {0}")]
+    InvalidSaveFile(String),
+    #[error("there is no note with id {0}")]
+    NoteNotFound(Uuid),
+    #[error("note {0} has no revision {1}")]
+    RevisionNotFound(Uuid, usize),
+    #[error("could not resolve the app data directory")]
+    NoAppDataDir,
+    #[error("invalid keybindings.json: {0}")]
+    InvalidKeybindings(String),
+    #[error("invalid md-sticky link {0}: {1}")]
+    InvalidLink(String, String),
+    #[error("no external editor is set, set \"editor\" in settings.json or $EDITOR")]
+    NoEditor,
+    /// what the running app answered on the control socket
+    #[error("{0}")]
+    Control(String),
+    /// anything tauri or the window system reported
+    #[error("{0}")]
+    Window(String),
+}
+
+impl StickyError {
+    pub fn window(error: impl Display) -> StickyError {
+        StickyError::Window(error.to_string())
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            StickyError::Io(_) => "io",
+            StickyError::Json(_) => "json",
+            StickyError::Database(_) => "database",
+            StickyError::Watch(_) => "watch",
+            #[cfg(target_os = "linux")]
+            StickyError::Dbus(_) => "dbus",
+            StickyError::InvalidSaveFile(_) => "invalid_save_file",
+            StickyError::NoteNotFound(_) => "note_not_found",
+            StickyError::RevisionNotFound(..) => "revision_not_found",
+            StickyError::NoAppDataDir => "no_app_data_dir",
+            StickyError::InvalidKeybindings(_) => "invalid_keybindings",
+            StickyError::InvalidLink(..) => "invalid_link",
+            StickyError::NoEditor => "no_editor",
+            StickyError::Control(_) => "control",
+            StickyError::Window(_) => "window",
+        }
+    }
+}
+
+impl Serialize for StickyError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut error = serializer.serialize_struct("StickyError", 2)?;
+        error.serialize_field("kind", self.kind())?;
+        error.serialize_field("message", &self.to_string())?;
+        error.end()
+    }
+}
+
+/// For errors that have nobody to be returned to, like in menu handlers and background threads.
+pub trait LogErr<T> {
+    /// Logs the error with `context` in front of it and turns the result into an `Option`.
+    fn log_err(self, context: &str) -> Option<T>;
+}
+
+impl<T, E: Display> LogErr<T> for Result<T, E> {
+    fn log_err(self, context: &str) -> Option<T> {
+        match self {
+            Ok(value) => Some(value),
+            Err(e) => {
+                log::error!("{context}: {e}");
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serializes_kind_and_message() {
+        let id = Uuid::nil();
+
+        assert_eq!(
+            serde_json::to_value(StickyError::NoteNotFound(id)).unwrap(),
+            serde_json::json!({
+                "kind": "note_not_found",
+                "message": format!("there is no note with id {id}"),
+            })
+        );
+    }
+
+    #[test]
+    fn log_err_keeps_the_value() {
+        assert_eq!(Ok::<u32, StickyError>(3).log_err("test"), Some(3));
+        assert_eq!(
+            Err::<u32, StickyError>(StickyError::NoAppDataDir).log_err("test"),
+            None
+        );
+    }
+}
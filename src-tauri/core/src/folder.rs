@@ -0,0 +1,557 @@
+use crate::error::{Result, StickyError};
+use crate::format::SaveFile;
+use crate::markdown;
+use crate::model::Note;
+use crate::palette;
+use crate::storage::write_atomic;
+use crate::trash::TrashedNote;
+use chrono::{DateTime, Utc};
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use uuid::Uuid;
+
+/// The Markdown storage backend keeps every note in `notes/<id>.md` in the app data dir.
+pub const NOTES_DIR: &str = "notes";
+/// Closed notes go to `notes/.trash/<id>.md`, with the time they were closed in their front matter.
+pub const TRASH_DIR: &str = ".trash";
+
+// written in this order, anything else comes after them
+const KEYS: [&str; 6] = ["id", "color", "x", "y", "width", "height"];
+const CREATED: &str = "created";
+const MODIFIED: &str = "modified";
+const CLOSED_AT: &str = "closed_at";
+
+/// Reads every note and the trash from the notes folder. Files that aren't UTF-8 or don't parse are renamed to
+/// `<name>.corrupt` so they can be fixed by hand, and don't get deleted by the next save.
+pub fn read(app_data_dir: &Path) -> Result<SaveFile> {
+    read_folder(app_data_dir, true)
+}
+
+/// Like [`read`], but every file is left where it is, the ones that can't be read are only skipped.
+pub fn read_as_is(app_data_dir: &Path) -> Result<SaveFile> {
+    read_folder(app_data_dir, false)
+}
+
+fn read_folder(app_data_dir: &Path, move_aside: bool) -> Result<SaveFile> {
+    let notes_dir = app_data_dir.join(NOTES_DIR);
+
+    let mut save_file = SaveFile::new(Vec::new());
+
+    for path in note_files(&notes_dir)? {
+        if let Some((note, _meta)) = read_file(&path, move_aside) {
+            save_file.notes.push(note);
+        }
+    }
+
+    for path in note_files(&notes_dir.join(TRASH_DIR))? {
+        if let Some((note, meta)) = read_file(&path, move_aside) {
+            let closed_at = meta
+                .get(CLOSED_AT)
+                .and_then(Value::as_str)
+                .and_then(|closed_at| closed_at.parse().ok())
+                .unwrap_or_else(Utc::now);
+
+            save_file.trash.push(TrashedNote { closed_at, note });
+        }
+    }
+
+    save_file.trash.sort_by_key(|trashed| trashed.closed_at);
+
+    Ok(save_file)
+}
+
+/// Writes every note and the trash to the notes folder, and deletes the files of notes that are gone.
+/// Files whose note didn't change are left alone, so their `modified` time stays put.
+pub fn write(save_file: &SaveFile, app_data_dir: &Path, now: DateTime<Utc>) -> Result<()> {
+    let notes_dir = app_data_dir.join(NOTES_DIR);
+    let trash_dir = notes_dir.join(TRASH_DIR);
+    fs::create_dir_all(&trash_dir)?;
+
+    let mut kept = HashSet::new();
+
+    for note in &save_file.notes {
+        let path = note_path(&notes_dir, note.id);
+        write_note(&path, note, Map::new(), now)?;
+        kept.insert(path);
+    }
+
+    for trashed in &save_file.trash {
+        let path = note_path(&trash_dir, trashed.note.id);
+        let mut meta = Map::new();
+        meta.insert(
+            CLOSED_AT.to_string(),
+            Value::String(trashed.closed_at.to_rfc3339()),
+        );
+        write_note(&path, &trashed.note, meta, now)?;
+        kept.insert(path);
+    }
+
+    for dir in [&notes_dir, &trash_dir] {
+        for path in note_files(dir)? {
+            // a file that can't be read isn't a note that is gone, it is left for the user to fix
+            if !kept.contains(&path) && read_note(&path).is_ok() {
+                fs::remove_file(path)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// When any file in the notes folder was last written, `None` if there are none.
+pub fn last_modified(app_data_dir: &Path) -> Option<SystemTime> {
+    let notes_dir = app_data_dir.join(NOTES_DIR);
+
+    [notes_dir.clone(), notes_dir.join(TRASH_DIR)]
+        .iter()
+        .filter_map(|dir| fs::read_dir(dir).ok())
+        .flatten()
+        .filter_map(|entry| entry.ok()?.metadata().ok()?.modified().ok())
+        .max()
+}
+
+fn note_path(dir: &Path, id: Uuid) -> PathBuf {
+    dir.join(format!("{id}.md"))
+}
+
+/// Every `.md` file in `dir`, sorted by name.
+fn note_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut files = Vec::new();
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        if path.is_file()
+            && path
+                .extension()
+                .map_or(false, |extension| extension == "md")
+        {
+            files.push(path);
+        }
+    }
+
+    files.sort();
+
+    Ok(files)
+}
+
+/// Reads a note file, which has to be UTF-8, returning the note and the front matter keys that aren't part of it.
+fn read_note(path: &Path) -> Result<(Note, Map<String, Value>)> {
+    let text = String::from_utf8(fs::read(path)?)
+        .map_err(|_| StickyError::InvalidSaveFile("the file isn't UTF-8 text".to_string()))?;
+
+    parse_note(path, &text)
+}
+
+// `None` for a file that was skipped, which is moved aside unless it might only have failed to read this time
+fn read_file(path: &Path, move_aside: bool) -> Option<(Note, Map<String, Value>)> {
+    match read_note(path) {
+        Ok(parsed) => Some(parsed),
+        Err(StickyError::Io(e)) => {
+            log::error!("could not read {}: {e}", path.display());
+            None
+        }
+        Err(e) if !move_aside => {
+            log::error!("could not read {}, skipping it: {e}", path.display());
+            None
+        }
+        Err(e) => {
+            log::error!("could not read {}: {e}", path.display());
+
+            let mut corrupt = path.as_os_str().to_owned();
+            corrupt.push(".corrupt");
+            if let Err(e) = fs::rename(path, &corrupt) {
+                log::error!("could not move {} out of the way: {e}", path.display());
+            }
+
+            None
+        }
+    }
+}
+
+/// Parses the text of a note file, returning the note and the front matter keys that aren't part of it.
+fn parse_note(path: &Path, text: &str) -> Result<(Note, Map<String, Value>)> {
+    let (mut meta, body) = parse_front_matter(text)?;
+
+    // notes dropped into the folder by hand might not have an id yet, the one made up from the path stays the same
+    // every time the file is read, until a save writes it into the front matter
+    if !meta.contains_key("id") {
+        let id = path
+            .file_stem()
+            .and_then(|stem| Uuid::parse_str(&stem.to_string_lossy()).ok())
+            .unwrap_or_else(|| {
+                Uuid::new_v3(&Uuid::NAMESPACE_URL, path.to_string_lossy().as_bytes())
+            });
+        meta.insert("id".to_string(), Value::String(id.to_string()));
+    }
+
+    let mut file_meta = Map::new();
+    for key in [CREATED, MODIFIED, CLOSED_AT] {
+        if let Some(value) = meta.remove(key) {
+            file_meta.insert(key.to_string(), value);
+        }
+    }
+
+    let mut fields = default_fields()?;
+    fields.extend(meta);
+    fields.insert(
+        "contents".to_string(),
+        Value::String(markdown::to_contents(body)?),
+    );
+
+    Ok((serde_json::from_value(Value::Object(fields))?, file_meta))
+}
+
+// what a note file leaves out gets the same values as a new note
+fn default_fields() -> Result<Map<String, Value>> {
+    let note = Note {
+        color: palette::DEFAULT_COLORS[0].to_string(),
+        width: 300,
+        height: 250,
+        ..Default::default()
+    };
+
+    match serde_json::to_value(note)? {
+        Value::Object(fields) => Ok(fields),
+        _ => Ok(Map::new()),
+    }
+}
+
+fn write_note(
+    path: &Path,
+    note: &Note,
+    mut meta: Map<String, Value>,
+    now: DateTime<Utc>,
+) -> Result<()> {
+    let body = markdown::from_contents(&note.contents)?;
+
+    let mut fields = match serde_json::to_value(note)? {
+        Value::Object(fields) => fields,
+        _ => Map::new(),
+    };
+    fields.remove("contents");
+    fields.extend(meta.clone());
+
+    let existing = fs::read_to_string(path).ok().and_then(|text| {
+        parse_front_matter(&text)
+            .ok()
+            .map(|(meta, body)| (meta, body.to_string()))
+    });
+
+    let created = match &existing {
+        Some((existing_meta, existing_body)) => {
+            let mut unchanged = existing_meta.clone();
+            let created = unchanged.remove(CREATED);
+            unchanged.remove(MODIFIED);
+
+            if unchanged == fields && *existing_body == body {
+                return Ok(());
+            }
+
+            created
+        }
+        None => None,
+    };
+
+    meta.insert(
+        CREATED.to_string(),
+        created.unwrap_or_else(|| Value::String(now.to_rfc3339())),
+    );
+    meta.insert(MODIFIED.to_string(), Value::String(now.to_rfc3339()));
+    fields.extend(meta);
+
+    write_atomic(path, render_front_matter(&fields, &body).as_bytes())?;
+
+    Ok(())
+}
+
+/// Splits a file into its front matter and the Markdown after it. Only the flat `key: value` YAML
+/// that md-sticky writes is understood, values are read as JSON where they can be and as strings otherwise.
+pub fn parse_front_matter(text: &str) -> Result<(Map<String, Value>, &str)> {
+    let mut meta = Map::new();
+
+    let rest = match text
+        .strip_prefix("---\n")
+        .or_else(|| text.strip_prefix("---\r\n"))
+    {
+        Some(rest) => rest,
+        None => return Ok((meta, text)),
+    };
+
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        offset += line.len();
+        let line = line.trim_end();
+
+        if line == "---" {
+            return Ok((meta, &rest[offset..]));
+        }
+
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+
+        let (key, value) = line.split_once(':').ok_or_else(|| {
+            StickyError::InvalidSaveFile(format!("front matter line `{line}` is not `key: value`"))
+        })?;
+
+        meta.insert(key.trim().to_string(), parse_value(value.trim()));
+    }
+
+    Err(StickyError::InvalidSaveFile(
+        "the front matter is never closed with `---`".to_string(),
+    ))
+}
+
+fn parse_value(value: &str) -> Value {
+    if let Some(quoted) = value
+        .strip_prefix('\'')
+        .and_then(|value| value.strip_suffix('\''))
+    {
+        return Value::String(quoted.replace("''", "'"));
+    }
+
+    if value.starts_with('"') {
+        return serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_string()));
+    }
+
+    let value = value.split(" #").next().unwrap_or_default().trim();
+    match value {
+        "" | "~" => Value::Null,
+        _ => serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_string())),
+    }
+}
+
+/// Writes `meta` as YAML front matter in front of `body`.
+pub fn render_front_matter(meta: &Map<String, Value>, body: &str) -> String {
+    let mut keys = KEYS
+        .iter()
+        .map(|key| key.to_string())
+        .filter(|key| meta.contains_key(key))
+        .collect::<Vec<String>>();
+    keys.extend(
+        meta.keys()
+            .filter(|key| !KEYS.contains(&key.as_str()))
+            .cloned(),
+    );
+
+    let mut out = String::from("---\n");
+    for key in keys {
+        out.push_str(&format!("{key}: {}\n", render_value(&meta[&key])));
+    }
+    out.push_str("---\n");
+    out.push_str(body);
+
+    out
+}
+
+fn render_value(value: &Value) -> String {
+    match value {
+        // ids and timestamps read better without quotes
+        Value::String(string)
+            if !string.is_empty()
+                && string
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-:._+".contains(c))
+                && !string.starts_with('-')
+                && serde_json::from_str::<Value>(string).is_err() =>
+        {
+            string.clone()
+        }
+        // json is valid yaml
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, 1, 9, minute, 0).unwrap()
+    }
+
+    fn note(text: &str) -> Note {
+        Note {
+            id: Uuid::new_v4(),
+            color: "#fff9b1".to_string(),
+            contents: markdown::to_contents(text).unwrap(),
+            x: 10,
+            y: 20,
+            height: 250,
+            width: 300,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn notes_round_trip_through_the_folder() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut save_file = SaveFile::new(vec![note("# Groceries\n- [ ] milk\n"), note("hi\n")]);
+        save_file.trash.push(TrashedNote {
+            closed_at: at(5),
+            note: note("old\n"),
+        });
+        save_file.notes.sort_by_key(|note| note.id);
+
+        write(&save_file, dir.path(), at(0)).unwrap();
+
+        assert_eq!(read(dir.path()).unwrap(), save_file);
+    }
+
+    #[test]
+    fn files_look_like_markdown_with_front_matter() {
+        let dir = tempfile::tempdir().unwrap();
+        let note = note("- [x] done\n");
+
+        write(&SaveFile::new(vec![note.clone()]), dir.path(), at(0)).unwrap();
+
+        let text =
+            fs::read_to_string(dir.path().join(NOTES_DIR).join(format!("{}.md", note.id))).unwrap();
+        assert_eq!(
+            text,
+            format!(
+                "---\nid: {}\ncolor: \"#fff9b1\"\nx: 10\ny: 20\nwidth: 300\nheight: 250\ncreated: 2024-03-01T09:00:00+00:00\nmodified: 2024-03-01T09:00:00+00:00\n---\n- [x] done\n",
+                note.id
+            )
+        );
+    }
+
+    #[test]
+    fn only_changed_notes_get_a_new_modified_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut changed, same) = (note("a\n"), note("b\n"));
+
+        write(
+            &SaveFile::new(vec![changed.clone(), same.clone()]),
+            dir.path(),
+            at(0),
+        )
+        .unwrap();
+        changed.x = 99;
+        write(
+            &SaveFile::new(vec![changed.clone(), same.clone()]),
+            dir.path(),
+            at(1),
+        )
+        .unwrap();
+
+        let meta = |note: &Note| {
+            let text = fs::read_to_string(note_path(&dir.path().join(NOTES_DIR), note.id)).unwrap();
+            parse_front_matter(&text).unwrap().0
+        };
+
+        assert_eq!(meta(&changed)[MODIFIED], at(1).to_rfc3339());
+        assert_eq!(meta(&changed)[CREATED], at(0).to_rfc3339());
+        assert_eq!(meta(&same)[MODIFIED], at(0).to_rfc3339());
+    }
+
+    #[test]
+    fn removed_notes_lose_their_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let (kept, removed) = (note("a\n"), note("b\n"));
+
+        write(
+            &SaveFile::new(vec![kept.clone(), removed.clone()]),
+            dir.path(),
+            at(0),
+        )
+        .unwrap();
+        write(&SaveFile::new(vec![kept.clone()]), dir.path(), at(1)).unwrap();
+
+        assert_eq!(read(dir.path()).unwrap().notes, vec![kept]);
+        assert!(!note_path(&dir.path().join(NOTES_DIR), removed.id).exists());
+    }
+
+    #[test]
+    fn hand_written_files_are_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let notes_dir = dir.path().join(NOTES_DIR);
+        fs::create_dir_all(&notes_dir).unwrap();
+        fs::write(
+            notes_dir.join("ideas.md"),
+            "---\n# picked by hand\ncolor: '#81B7DD'\nwidth: 400 # wide\npinned: true\n---\n**big** idea\n",
+        )
+        .unwrap();
+        fs::write(notes_dir.join("plain.md"), "just text\n").unwrap();
+
+        let notes = read(dir.path()).unwrap().notes;
+
+        assert_eq!(notes.len(), 2);
+        assert_eq!(notes[0].color, "#81B7DD");
+        assert_eq!(notes[0].width, 400);
+        assert_eq!(notes[0].extra["pinned"], true);
+        assert_eq!(
+            markdown::from_contents(&notes[0].contents).unwrap(),
+            "**big** idea\n"
+        );
+        assert_eq!(
+            markdown::from_contents(&notes[1].contents).unwrap(),
+            "just text\n"
+        );
+    }
+
+    #[test]
+    fn broken_files_are_moved_aside() {
+        let dir = tempfile::tempdir().unwrap();
+        let notes_dir = dir.path().join(NOTES_DIR);
+        fs::create_dir_all(&notes_dir).unwrap();
+        fs::write(
+            notes_dir.join("broken.md"),
+            "---\ncolor: \"#fff\"\nno end\n",
+        )
+        .unwrap();
+
+        assert!(read(dir.path()).unwrap().notes.is_empty());
+        assert!(notes_dir.join("broken.md.corrupt").exists());
+    }
+
+    #[test]
+    fn files_that_are_not_utf8_are_moved_aside_and_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        let notes_dir = dir.path().join(NOTES_DIR);
+        fs::create_dir_all(&notes_dir).unwrap();
+        let kept = note("a\n");
+        write(&SaveFile::new(vec![kept.clone()]), dir.path(), at(0)).unwrap();
+        // "café" in Latin-1
+        fs::write(notes_dir.join("latin1.md"), b"caf\xe9\n").unwrap();
+
+        // the other notes are still read, and saving doesn't delete the file
+        write(&SaveFile::new(vec![kept.clone()]), dir.path(), at(1)).unwrap();
+        assert!(notes_dir.join("latin1.md").exists());
+        assert_eq!(read(dir.path()).unwrap().notes, vec![kept]);
+        assert!(notes_dir.join("latin1.md.corrupt").exists());
+    }
+
+    #[test]
+    fn reading_as_is_leaves_broken_files_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let notes_dir = dir.path().join(NOTES_DIR);
+        fs::create_dir_all(&notes_dir).unwrap();
+        fs::write(notes_dir.join("broken.md"), "---\nid: [\n---\ntext").unwrap();
+
+        assert!(read_as_is(dir.path()).unwrap().notes.is_empty());
+        assert!(notes_dir.join("broken.md").exists());
+    }
+
+    #[test]
+    fn hand_written_files_keep_their_id() {
+        let dir = tempfile::tempdir().unwrap();
+        let notes_dir = dir.path().join(NOTES_DIR);
+        fs::create_dir_all(&notes_dir).unwrap();
+        fs::write(notes_dir.join("plain.md"), "just text\n").unwrap();
+
+        assert_eq!(
+            read(dir.path()).unwrap().notes[0].id,
+            read(dir.path()).unwrap().notes[0].id
+        );
+    }
+}
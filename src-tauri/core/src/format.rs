@@ -0,0 +1,144 @@
+use crate::error::{Result, StickyError};
+use crate::model::Note;
+use crate::trash::TrashedNote;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use uuid::Uuid;
+
+/// Version of the save file format written by this build.
+pub const CURRENT_VERSION: u64 = 2;
+
+/// `MIGRATIONS[n]` upgrades a version `n` save file to version `n + 1`.
+const MIGRATIONS: [fn(Value) -> Value; CURRENT_VERSION as usize] = [v0_to_v1, v1_to_v2];
+
+/// Top level of `notes.json`.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct SaveFile {
+    pub version: u64,
+    pub notes: Vec<Note>,
+    /// closed notes, oldest first
+    #[serde(default)]
+    pub trash: Vec<TrashedNote>,
+    /// top level fields written by a newer version of the app
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl SaveFile {
+    pub fn new(notes: Vec<Note>) -> SaveFile {
+        SaveFile {
+            version: CURRENT_VERSION,
+            notes,
+            trash: Vec::new(),
+            extra: Map::new(),
+        }
+    }
+}
+
+/// Parses a save file of any version, running it through every migration it is missing.
+/// Files from a newer version are read as they are, relying on `extra` to hold on to what this build doesn't understand.
+pub fn parse(file_content: &str) -> Result<SaveFile> {
+    let mut value: Value = serde_json::from_str(file_content)?;
+
+    let mut version = match &value {
+        Value::Array(_) => 0,
+        Value::Object(object) => object
+            .get("version")
+            .and_then(Value::as_u64)
+            .ok_or_else(|| StickyError::InvalidSaveFile("it has no version".to_string()))?,
+        _ => {
+            return Err(StickyError::InvalidSaveFile(
+                "it is not a list of notes or a versioned save".to_string(),
+            ))
+        }
+    };
+
+    while version < CURRENT_VERSION {
+        value = MIGRATIONS[version as usize](value);
+        version += 1;
+    }
+
+    Ok(serde_json::from_value(value)?)
+}
+
+// 0.4.2 and earlier saved a bare array of notes
+fn v0_to_v1(notes: Value) -> Value {
+    json!({ "version": 1, "notes": notes })
+}
+
+// labels came from a counter that restarted every launch, notes get a stable id instead
+fn v1_to_v2(mut save: Value) -> Value {
+    if let Some(notes) = save["notes"].as_array_mut() {
+        for note in notes.iter_mut().filter_map(Value::as_object_mut) {
+            note.remove("label");
+            note.insert("id".to_string(), json!(Uuid::new_v4()));
+        }
+    }
+
+    save["version"] = json!(2);
+    save
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bare_arrays_are_migrated() {
+        let save = parse(
+            r##"[{"color":"#81B7DD","contents":"{\"ops\":[]}","x":1,"y":2,"height":3,"width":4,"label":"new_sticky_window_1"}]"##,
+        )
+        .unwrap();
+
+        assert_eq!(save.version, CURRENT_VERSION);
+        assert_eq!(save.notes.len(), 1);
+        assert_eq!(save.notes[0].color, "#81B7DD");
+    }
+
+    #[test]
+    fn labels_are_replaced_by_unique_ids() {
+        let save = parse(
+            r#"{"version":1,"notes":[{"color":"","contents":"","x":0,"y":0,"height":0,"width":0,"label":"new_sticky_window_1"},{"color":"","contents":"","x":0,"y":0,"height":0,"width":0,"label":"new_sticky_window_1"}]}"#,
+        )
+        .unwrap();
+
+        assert_ne!(save.notes[0].id, save.notes[1].id);
+        assert!(!save.notes[0].id.is_nil());
+        assert!(save.notes.iter().all(|note| note.extra.is_empty()));
+    }
+
+    #[test]
+    fn current_version_round_trips() {
+        let save = SaveFile::new(vec![Note {
+            id: Uuid::new_v4(),
+            color: "#fff9b1".to_string(),
+            ..Default::default()
+        }]);
+
+        assert_eq!(parse(&serde_json::to_string(&save).unwrap()).unwrap(), save);
+    }
+
+    #[test]
+    fn unknown_fields_survive_a_round_trip() {
+        let newer = r##"{"version":2,"sync":{"peer":"laptop"},"notes":[{"id":"67e55044-10b1-426f-9247-bb680e5fe0c8","color":"#fff9b1","contents":"","x":0,"y":0,"height":0,"width":0,"pinned":true}]}"##;
+
+        let save = parse(newer).unwrap();
+        let written: Value = serde_json::to_value(&save).unwrap();
+
+        assert_eq!(written["sync"]["peer"], "laptop");
+        assert_eq!(written["notes"][0]["pinned"], true);
+    }
+
+    #[test]
+    fn newer_versions_are_read_as_is() {
+        let save = parse(r#"{"version":99,"notes":[]}"#).unwrap();
+
+        assert_eq!(save.version, 99);
+    }
+
+    #[test]
+    fn unversioned_objects_are_rejected() {
+        assert!(parse(r#"{"notes":[]}"#).is_err());
+        assert!(parse("12").is_err());
+    }
+}
@@ -0,0 +1,268 @@
+use std::cmp;
+
+/// Space left between snapped windows, and between a window and the edge of the monitor.
+pub const GAP: i32 = 20;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// Outer position and size of a window, in physical pixels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Position {
+    pub x: i32,
+    pub y: i32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Size {
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Where `window` ends up when it is moved in `direction` until it hits another window or the edge of the monitor.
+/// Only windows that overlap it on the other axis count as obstacles.
+pub fn snap_position(
+    window: Rect,
+    others: &[Rect],
+    monitor: Size,
+    direction: Direction,
+) -> Position {
+    match direction {
+        Direction::Left => Position {
+            x: others
+                .iter()
+                .filter(|other| {
+                    window_overlap(other.y, other.height as i32, window.y, window.height as i32)
+                })
+                .map(|other| other.x + other.width as i32)
+                .filter(|edge| *edge < window.x)
+                .max()
+                .unwrap_or(0)
+                + GAP,
+            y: window.y,
+        },
+
+        Direction::Up => Position {
+            x: window.x,
+            y: others
+                .iter()
+                .filter(|other| {
+                    window_overlap(other.x, other.width as i32, window.x, window.width as i32)
+                })
+                .map(|other| other.y + other.height as i32)
+                .filter(|edge| *edge < window.y)
+                .max()
+                .unwrap_or(0)
+                + GAP,
+        },
+
+        Direction::Right => Position {
+            x: others
+                .iter()
+                .filter(|other| {
+                    window_overlap(other.y, other.height as i32, window.y, window.height as i32)
+                })
+                .map(|other| other.x - window.width as i32)
+                .filter(|edge| *edge > window.x)
+                .min()
+                .unwrap_or(monitor.width as i32 - window.width as i32)
+                - GAP,
+            y: window.y,
+        },
+
+        Direction::Down => Position {
+            x: window.x,
+            y: others
+                .iter()
+                .filter(|other| {
+                    window_overlap(other.x, other.width as i32, window.x, window.width as i32)
+                })
+                .map(|other| other.y - window.height as i32)
+                .filter(|edge| *edge > window.y)
+                .min()
+                .unwrap_or(monitor.height as i32 - window.height as i32)
+                - GAP,
+        },
+    }
+}
+
+/// Where `window` ends up when it is moved in `direction` until one of its edges lines up with the nearest edge of any other window.
+pub fn partial_snap_position(
+    window: Rect,
+    others: &[Rect],
+    monitor: Size,
+    direction: Direction,
+) -> Position {
+    match direction {
+        Direction::Left => Position {
+            x: others
+                .iter()
+                .flat_map(|other| [other.x, other.x + other.width as i32])
+                .filter(|position| *position < window.x)
+                .max()
+                .unwrap_or(GAP),
+            y: window.y,
+        },
+
+        Direction::Up => Position {
+            x: window.x,
+            y: others
+                .iter()
+                .flat_map(|other| [other.y, other.y + other.height as i32])
+                .filter(|position| *position < window.y)
+                .max()
+                .unwrap_or(GAP),
+        },
+
+        Direction::Right => Position {
+            x: others
+                .iter()
+                .flat_map(|other| {
+                    [
+                        (other.x + other.width as i32) - window.width as i32,
+                        other.x - window.width as i32,
+                    ]
+                })
+                .filter(|position| *position > window.x)
+                .min()
+                .unwrap_or(monitor.width as i32 - window.width as i32 - GAP),
+            y: window.y,
+        },
+
+        Direction::Down => Position {
+            x: window.x,
+            y: others
+                .iter()
+                .flat_map(|other| {
+                    [
+                        (other.y + other.height as i32) - window.height as i32,
+                        other.y - window.height as i32,
+                    ]
+                })
+                .filter(|position| *position > window.y)
+                .min()
+                .unwrap_or(monitor.height as i32 - window.height as i32 - GAP),
+        },
+    }
+}
+
+/// Whether two spans on the same axis overlap by more than the gap between windows.
+pub fn window_overlap(start_1: i32, len_1: i32, start_2: i32, len_2: i32) -> bool {
+    let end_1 = start_1 + len_1;
+    let end_2 = start_2 + len_2;
+
+    let overlap_start = cmp::max(start_1, start_2);
+    let overlap_end = cmp::min(end_1, end_2);
+    overlap_end - overlap_start > GAP
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MONITOR: Size = Size {
+        width: 1920,
+        height: 1080,
+    };
+
+    fn rect(x: i32, y: i32, width: u32, height: u32) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn overlap_has_to_be_wider_than_the_gap() {
+        assert!(window_overlap(0, 100, 50, 100));
+        assert!(!window_overlap(0, 100, 80, 100));
+        assert!(!window_overlap(0, 100, 200, 100));
+    }
+
+    #[test]
+    fn snaps_to_the_monitor_edges_when_alone() {
+        let window = rect(500, 400, 300, 250);
+
+        assert_eq!(
+            snap_position(window, &[], MONITOR, Direction::Left),
+            Position { x: 20, y: 400 }
+        );
+        assert_eq!(
+            snap_position(window, &[], MONITOR, Direction::Up),
+            Position { x: 500, y: 20 }
+        );
+        assert_eq!(
+            snap_position(window, &[], MONITOR, Direction::Right),
+            Position { x: 1600, y: 400 }
+        );
+        assert_eq!(
+            snap_position(window, &[], MONITOR, Direction::Down),
+            Position { x: 500, y: 810 }
+        );
+    }
+
+    #[test]
+    fn snaps_next_to_overlapping_windows() {
+        let window = rect(500, 400, 300, 250);
+        let left = rect(100, 450, 200, 200);
+        let right = rect(1200, 300, 200, 200);
+
+        assert_eq!(
+            snap_position(window, &[left, right], MONITOR, Direction::Left),
+            Position { x: 320, y: 400 }
+        );
+        assert_eq!(
+            snap_position(window, &[left, right], MONITOR, Direction::Right),
+            Position { x: 880, y: 400 }
+        );
+    }
+
+    #[test]
+    fn ignores_windows_that_are_not_in_the_way() {
+        let window = rect(500, 400, 300, 250);
+        let above_but_off_to_the_side = rect(900, 100, 200, 200);
+
+        assert_eq!(
+            snap_position(window, &[above_but_off_to_the_side], MONITOR, Direction::Up),
+            Position { x: 500, y: 20 }
+        );
+    }
+
+    #[test]
+    fn partial_snap_lines_up_with_the_nearest_edge() {
+        let window = rect(500, 400, 300, 250);
+        let other = rect(100, 100, 200, 200);
+
+        assert_eq!(
+            partial_snap_position(window, &[other], MONITOR, Direction::Left),
+            Position { x: 300, y: 400 }
+        );
+        assert_eq!(
+            partial_snap_position(window, &[other], MONITOR, Direction::Up),
+            Position { x: 500, y: 300 }
+        );
+        assert_eq!(
+            partial_snap_position(window, &[other], MONITOR, Direction::Right),
+            Position { x: 1600, y: 400 }
+        );
+        assert_eq!(
+            partial_snap_position(rect(0, 0, 100, 100), &[other], MONITOR, Direction::Down),
+            Position { x: 0, y: 200 }
+        );
+    }
+}
@@ -0,0 +1,404 @@
+use crate::error::Result;
+use crate::model::{Note, NoteUpdate};
+use crate::storage::write_atomic;
+use crate::store::NoteStore;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// With the file stores every note keeps its revisions in `history/<id>.json` in the app data dir, oldest first.
+pub const HISTORY_DIR: &str = "history";
+
+/// How many revisions to keep per note, and when to take a new one.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct HistoryPolicy {
+    /// `0` turns history off
+    pub keep: usize,
+    /// how many characters of the contents have to change before a revision is taken right away
+    pub min_change: usize,
+    /// smaller changes get a revision once the note has been left alone for this many seconds
+    pub idle_secs: u32,
+}
+
+impl Default for HistoryPolicy {
+    fn default() -> Self {
+        HistoryPolicy {
+            keep: 50,
+            min_change: 40,
+            idle_secs: 60,
+        }
+    }
+}
+
+/// A snapshot of a note at some point in time.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Revision {
+    pub taken_at: DateTime<Utc>,
+    pub color: String,
+    pub contents: String,
+    pub x: u32,
+    pub y: u32,
+    pub height: u32,
+    pub width: u32,
+}
+
+/// What the `list_revisions` command gives for every revision, the full revision is only sent when previewing it.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct RevisionSummary {
+    pub index: usize,
+    pub taken_at: DateTime<Utc>,
+    pub color: String,
+    pub title: String,
+}
+
+impl Revision {
+    pub fn of(note: &Note, taken_at: DateTime<Utc>) -> Revision {
+        Revision {
+            taken_at,
+            color: note.color.clone(),
+            contents: note.contents.clone(),
+            x: note.x,
+            y: note.y,
+            height: note.height,
+            width: note.width,
+        }
+    }
+
+    /// The update that puts a note back the way it was in this revision.
+    pub fn to_update(&self) -> NoteUpdate {
+        NoteUpdate {
+            contents: Some(self.contents.clone()),
+            color: Some(self.color.clone()),
+            x: Some(self.x),
+            y: Some(self.y),
+            height: Some(self.height),
+            width: Some(self.width),
+        }
+    }
+
+    pub fn summary(&self, index: usize) -> RevisionSummary {
+        let note = Note {
+            contents: self.contents.clone(),
+            ..Default::default()
+        };
+
+        RevisionSummary {
+            index,
+            taken_at: self.taken_at,
+            color: self.color.clone(),
+            title: note.title(),
+        }
+    }
+
+    // moving and resizing a note alone isn't worth a revision
+    fn same_text(&self, note: &Note) -> bool {
+        self.contents == note.contents && self.color == note.color
+    }
+}
+
+fn history_path(app_data_dir: &Path, id: Uuid) -> PathBuf {
+    app_data_dir.join(HISTORY_DIR).join(format!("{id}.json"))
+}
+
+/// Every revision of the note with this id, oldest first.
+pub fn list(app_data_dir: &Path, id: Uuid) -> Result<Vec<Revision>> {
+    let path = history_path(app_data_dir, id);
+
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+}
+
+/// Adds `revision` to the note's history, dropping the oldest revisions beyond what `policy` keeps.
+pub fn push(
+    app_data_dir: &Path,
+    id: Uuid,
+    revision: Revision,
+    policy: &HistoryPolicy,
+) -> Result<()> {
+    let mut revisions = list(app_data_dir, id)?;
+    revisions.push(revision);
+
+    let excess = revisions.len().saturating_sub(policy.keep);
+    revisions.drain(..excess);
+
+    fs::create_dir_all(app_data_dir.join(HISTORY_DIR))?;
+    write_atomic(
+        &history_path(app_data_dir, id),
+        serde_json::to_string(&revisions)?.as_bytes(),
+    )?;
+
+    Ok(())
+}
+
+/// Deletes the history of a note that is gone for good.
+pub fn remove(app_data_dir: &Path, id: Uuid) -> Result<()> {
+    let path = history_path(app_data_dir, id);
+
+    if path.exists() {
+        fs::remove_file(path)?;
+    }
+
+    Ok(())
+}
+
+/// Roughly how many characters differ between `a` and `b`: everything between their common prefix and suffix.
+fn change_size(a: &str, b: &str) -> usize {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+
+    a.len().max(b.len()) - prefix - suffix
+}
+
+/// Decides when the notes get a new revision. Feed it the current notes every now and then with [`HistoryRecorder::record`].
+#[derive(Debug)]
+pub struct HistoryRecorder {
+    policy: HistoryPolicy,
+    // newest revision of every note seen so far, `None` if it has none yet
+    latest: HashMap<Uuid, Option<Revision>>,
+    // notes with small unrecorded changes, and when they last changed
+    pending: HashMap<Uuid, Revision>,
+}
+
+impl HistoryRecorder {
+    pub fn new(policy: HistoryPolicy) -> HistoryRecorder {
+        HistoryRecorder {
+            policy,
+            latest: HashMap::new(),
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Takes a revision of every note that has no history yet, changed a lot since its last revision,
+    /// or had smaller changes and then sat still for `idle_secs`.
+    pub fn record(
+        &mut self,
+        store: &mut dyn NoteStore,
+        notes: &[Note],
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        if self.policy.keep == 0 {
+            return Ok(());
+        }
+
+        let idle = Duration::seconds(self.policy.idle_secs.into());
+
+        for note in notes {
+            let latest = match self.latest.get(&note.id) {
+                Some(latest) => latest.clone(),
+                None => store.history(note.id)?.pop(),
+            };
+
+            let due = match &latest {
+                None => true,
+                Some(latest) if latest.same_text(note) => {
+                    self.pending.remove(&note.id);
+                    false
+                }
+                Some(latest) => {
+                    change_size(&latest.contents, &note.contents) >= self.policy.min_change
+                        || match self.pending.get(&note.id) {
+                            Some(pending) if pending.same_text(note) => {
+                                now - pending.taken_at >= idle
+                            }
+                            _ => {
+                                self.pending.insert(note.id, Revision::of(note, now));
+                                false
+                            }
+                        }
+                }
+            };
+
+            if due {
+                self.snapshot(store, note, now)?;
+            } else {
+                self.latest.insert(note.id, latest);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Takes a revision of `note` right away, unless it is the same as the last one.
+    pub fn snapshot(
+        &mut self,
+        store: &mut dyn NoteStore,
+        note: &Note,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        self.pending.remove(&note.id);
+
+        if self.policy.keep == 0 {
+            return Ok(());
+        }
+
+        let latest = match self.latest.get(&note.id) {
+            Some(latest) => latest.clone(),
+            None => store.history(note.id)?.pop(),
+        };
+
+        if matches!(latest, Some(latest) if latest.same_text(note)) {
+            return Ok(());
+        }
+
+        let revision = Revision::of(note, now);
+        store.push_revision(note.id, &revision, &self.policy)?;
+        self.latest.insert(note.id, Some(revision));
+
+        Ok(())
+    }
+
+    pub fn forget(&mut self, store: &mut dyn NoteStore, id: Uuid) -> Result<()> {
+        self.latest.remove(&id);
+        self.pending.remove(&id);
+
+        store.remove_history(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backup::BackupPolicy;
+    use crate::storage::Backend;
+    use crate::store::FileStore;
+    use chrono::TimeZone;
+
+    fn at(minute: u32, second: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, 1, 9, minute, second).unwrap()
+    }
+
+    fn note(text: &str) -> Note {
+        Note {
+            id: Uuid::from_u128(1),
+            color: "#fff9b1".to_string(),
+            contents: format!(r#"{{"ops":[{{"insert":"{text}\n"}}]}}"#),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn first_sight_of_a_note_is_recorded() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = FileStore::new(dir.path(), Backend::Json, BackupPolicy::default());
+        let mut recorder = HistoryRecorder::new(HistoryPolicy::default());
+
+        recorder
+            .record(&mut store, &[note("milk")], at(0, 0))
+            .unwrap();
+        recorder
+            .record(&mut store, &[note("milk")], at(5, 0))
+            .unwrap();
+
+        let revisions = list(dir.path(), Uuid::from_u128(1)).unwrap();
+        assert_eq!(revisions.len(), 1);
+        assert_eq!(revisions[0].taken_at, at(0, 0));
+    }
+
+    #[test]
+    fn big_changes_are_recorded_right_away() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = FileStore::new(dir.path(), Backend::Json, BackupPolicy::default());
+        let mut recorder = HistoryRecorder::new(HistoryPolicy::default());
+
+        recorder
+            .record(&mut store, &[note("milk")], at(0, 0))
+            .unwrap();
+        recorder
+            .record(
+                &mut store,
+                &[note("milk, eggs, flour, sugar, butter, baking soda")],
+                at(0, 1),
+            )
+            .unwrap();
+
+        assert_eq!(list(dir.path(), Uuid::from_u128(1)).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn small_changes_wait_for_the_note_to_be_idle() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = FileStore::new(dir.path(), Backend::Json, BackupPolicy::default());
+        let mut recorder = HistoryRecorder::new(HistoryPolicy::default());
+
+        recorder
+            .record(&mut store, &[note("milk")], at(0, 0))
+            .unwrap();
+        recorder
+            .record(&mut store, &[note("milk, eggs")], at(0, 10))
+            .unwrap();
+        // still typing
+        recorder
+            .record(&mut store, &[note("milk, eggs, ham")], at(0, 50))
+            .unwrap();
+        recorder
+            .record(&mut store, &[note("milk, eggs, ham")], at(1, 30))
+            .unwrap();
+
+        assert_eq!(list(dir.path(), Uuid::from_u128(1)).unwrap().len(), 1);
+
+        recorder
+            .record(&mut store, &[note("milk, eggs, ham")], at(1, 50))
+            .unwrap();
+
+        let revisions = list(dir.path(), Uuid::from_u128(1)).unwrap();
+        assert_eq!(revisions.len(), 2);
+        assert_eq!(revisions[1].contents, note("milk, eggs, ham").contents);
+    }
+
+    #[test]
+    fn only_the_newest_revisions_are_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        let policy = HistoryPolicy {
+            keep: 2,
+            ..Default::default()
+        };
+
+        for (i, text) in ["a", "b", "c"].into_iter().enumerate() {
+            push(
+                dir.path(),
+                Uuid::from_u128(1),
+                Revision::of(&note(text), at(i as u32, 0)),
+                &policy,
+            )
+            .unwrap();
+        }
+
+        let revisions = list(dir.path(), Uuid::from_u128(1)).unwrap();
+        assert_eq!(revisions.len(), 2);
+        assert_eq!(revisions[0].contents, note("b").contents);
+    }
+
+    #[test]
+    fn restoring_puts_back_every_field() {
+        let mut before = note("milk");
+        before.x = 40;
+        let revision = Revision::of(&before, at(0, 0));
+
+        let mut after = note("eggs");
+        after.apply(revision.to_update());
+
+        assert_eq!(after, before);
+        assert_eq!(revision.summary(3).title, "milk");
+    }
+
+    #[test]
+    fn change_size_ignores_the_common_ends() {
+        assert_eq!(change_size("hello world", "hello there world"), 6);
+        assert_eq!(change_size("abc", "abc"), 0);
+        assert_eq!(change_size("", "abc"), 3);
+    }
+}
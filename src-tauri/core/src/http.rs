@@ -0,0 +1,367 @@
+use crate::control::{Reply, Request};
+use crate::delta::Delta;
+use crate::error::{Result, StickyError};
+use crate::markdown;
+use serde::Deserialize;
+use serde_json::Value;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::thread;
+use tiny_http::{Header, Method, Server};
+use uuid::Uuid;
+
+/// The token every request to the HTTP API has to carry as `Authorization: Bearer <token>`, in the app data dir.
+pub const TOKEN_FILE: &str = "api_token";
+
+/// bodies are notes, anything bigger than this is a mistake
+const MAX_BODY: u64 = 4 * 1024 * 1024;
+
+/// The token from [`TOKEN_FILE`], made up and saved the first time the API is turned on.
+pub fn token(app_data_dir: &Path) -> Result<String> {
+    let path = app_data_dir.join(TOKEN_FILE);
+
+    if path.exists() {
+        let token = fs::read_to_string(&path)?.trim().to_string();
+
+        if !token.is_empty() {
+            return Ok(token);
+        }
+    }
+
+    let token = Uuid::new_v4().simple().to_string();
+
+    let mut options = OpenOptions::new();
+    options.create(true).write(true).truncate(true);
+    // anyone who can read the token can change every note
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(&path)?.write_all(token.as_bytes())?;
+
+    Ok(token)
+}
+
+/// Serves the HTTP API on `127.0.0.1:port`, answering requests with `handler` one at a time on a thread of its own.
+pub fn serve(
+    port: u16,
+    token: String,
+    handler: impl Fn(Request) -> Result<Reply> + Send + 'static,
+) -> Result<()> {
+    let server = Server::http(("127.0.0.1", port))
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+
+    log::info!("HTTP API listening on 127.0.0.1:{port}");
+
+    thread::spawn(move || {
+        for mut request in server.incoming_requests() {
+            let authorization = request
+                .headers()
+                .iter()
+                .find(|header| header.field.equiv("Authorization"))
+                .map(|header| header.value.to_string());
+
+            let response = if !is_authorized(authorization.as_deref(), &token) {
+                Response::error(401, "unauthorized", "missing or wrong API token")
+            } else {
+                let mut body = String::new();
+
+                match request.as_reader().take(MAX_BODY).read_to_string(&mut body) {
+                    Ok(_) => route(request.method(), request.url(), &body, &handler),
+                    Err(e) => Response::error(400, "invalid_body", &e.to_string()),
+                }
+            };
+
+            let content_type = Header::from_bytes("Content-Type", response.content_type)
+                .expect("content types are valid headers");
+            let http_response = tiny_http::Response::from_string(response.body)
+                .with_status_code(response.status)
+                .with_header(content_type);
+
+            if let Err(e) = request.respond(http_response) {
+                log::warn!("could not answer an HTTP API request: {e}");
+            }
+        }
+    });
+
+    Ok(())
+}
+
+// compares every byte, so how long the check takes gives nothing away about the token
+fn is_authorized(authorization: Option<&str>, token: &str) -> bool {
+    let given = match authorization.and_then(|value| value.strip_prefix("Bearer ")) {
+        Some(given) => given.trim().as_bytes(),
+        None => return false,
+    };
+
+    given.len() == token.len()
+        && given
+            .iter()
+            .zip(token.as_bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+#[derive(Debug, PartialEq)]
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    fn json(status: u16, body: &impl serde::Serialize) -> Response {
+        Response {
+            status,
+            content_type: "application/json",
+            body: serde_json::to_string(body).unwrap_or_default(),
+        }
+    }
+
+    fn error(status: u16, kind: &str, message: &str) -> Response {
+        Response::json(
+            status,
+            &serde_json::json!({ "error": { "kind": kind, "message": message } }),
+        )
+    }
+
+    fn from_error(e: StickyError) -> Response {
+        let status = match e {
+            StickyError::NoteNotFound(_) => 404,
+            StickyError::Json(_) => 400,
+            _ => 500,
+        };
+
+        Response::json(status, &serde_json::json!({ "error": e }))
+    }
+}
+
+/// The body of `POST /notes`.
+#[derive(Deserialize)]
+struct NewNote {
+    #[serde(default)]
+    text: String,
+    color: Option<String>,
+}
+
+/// The body of `PATCH /notes/<id>`, every field is optional.
+#[derive(Deserialize)]
+struct NoteChange {
+    /// new contents as Markdown
+    text: Option<String>,
+    /// new contents as a quill delta, an object or a string of one
+    contents: Option<Value>,
+    color: Option<String>,
+}
+
+fn route(
+    method: &Method,
+    url: &str,
+    body: &str,
+    handler: &dyn Fn(Request) -> Result<Reply>,
+) -> Response {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let segments = path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<&str>>();
+
+    let id = match segments.as_slice() {
+        ["notes"] => None,
+        ["notes", id] => match Uuid::parse_str(id) {
+            Ok(id) => Some(id),
+            Err(_) => return Response::error(404, "not_found", &format!("{id} is not a note id")),
+        },
+        _ => return Response::error(404, "not_found", &format!("no such endpoint {path}")),
+    };
+
+    let result = match (method, id) {
+        (Method::Get, None) => handler(Request::List).map(|reply| Response::json(200, &reply)),
+        (Method::Get, Some(id)) => {
+            handler(Request::Show { id }).and_then(|reply| read_note(reply, query))
+        }
+        (Method::Post, None) => serde_json::from_str::<NewNote>(body)
+            .map_err(StickyError::from)
+            .and_then(|new| {
+                handler(Request::New {
+                    color: new.color,
+                    text: new.text,
+                })
+            })
+            .map(|reply| Response::json(201, &reply)),
+        (Method::Patch, Some(id)) => serde_json::from_str::<NoteChange>(body)
+            .map_err(StickyError::from)
+            .and_then(|change| change_note(id, change, handler)),
+        (Method::Delete, Some(id)) => {
+            handler(Request::Close { id }).map(|reply| Response::json(200, &reply))
+        }
+        _ => return Response::error(405, "method_not_allowed", &format!("{method} {path}")),
+    };
+
+    result.unwrap_or_else(Response::from_error)
+}
+
+// `?format=markdown` and `?format=delta` give just the contents, the note as JSON otherwise
+fn read_note(reply: Reply, query: &str) -> Result<Response> {
+    let note = match reply {
+        Reply::Note(note) => note,
+        reply => return Ok(Response::json(200, &reply)),
+    };
+
+    let format = query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("format="))
+        .unwrap_or("json");
+
+    Ok(match format {
+        "markdown" => Response {
+            status: 200,
+            content_type: "text/markdown; charset=utf-8",
+            body: markdown::from_contents(&note.contents)?,
+        },
+        "delta" => Response::json(200, &Delta::parse(&note.contents)?),
+        "json" => Response::json(200, &note),
+        _ => Response::error(
+            400,
+            "invalid_format",
+            &format!("unknown format {format}, use markdown, delta or json"),
+        ),
+    })
+}
+
+fn change_note(
+    id: Uuid,
+    change: NoteChange,
+    handler: &dyn Fn(Request) -> Result<Reply>,
+) -> Result<Response> {
+    let contents = match (change.text, change.contents) {
+        (Some(text), _) => Some(markdown::to_contents(&text)?),
+        (None, Some(Value::String(contents))) => Some(contents),
+        (None, Some(contents)) => Some(contents.to_string()),
+        (None, None) => None,
+    };
+
+    if let Some(contents) = contents {
+        handler(Request::SetContents { id, contents })?;
+    }
+    if let Some(color) = change.color {
+        handler(Request::SetColor { id, color })?;
+    }
+
+    handler(Request::Show { id }).map(|reply| Response::json(200, &reply))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backup::BackupPolicy;
+    use crate::control;
+    use crate::storage::Backend;
+    use crate::store::{FileStore, NoteStore};
+    use std::cell::RefCell;
+
+    fn call(
+        store: &RefCell<Box<dyn NoteStore>>,
+        method: Method,
+        url: &str,
+        body: &str,
+    ) -> Response {
+        route(&method, url, body, &|request| {
+            control::execute(request, &mut *store.borrow_mut())
+        })
+    }
+
+    #[test]
+    fn notes_can_be_created_read_changed_and_closed() {
+        let dir = tempfile::tempdir().unwrap();
+        let store: RefCell<Box<dyn NoteStore>> = RefCell::new(Box::new(FileStore::new(
+            dir.path(),
+            Backend::Json,
+            BackupPolicy::default(),
+        )));
+
+        let created = call(&store, Method::Post, "/notes", r##"{"text": "# Todo"}"##);
+        assert_eq!(created.status, 201);
+        let id = serde_json::from_str::<Value>(&created.body).unwrap()["id"]
+            .as_str()
+            .unwrap()
+            .to_string();
+
+        let changed = call(
+            &store,
+            Method::Patch,
+            &format!("/notes/{id}"),
+            r##"{"text": "# Done", "color": "#81B7DD"}"##,
+        );
+        assert_eq!(changed.status, 200);
+        assert_eq!(
+            serde_json::from_str::<Value>(&changed.body).unwrap()["color"],
+            "#81B7DD"
+        );
+
+        let markdown = call(
+            &store,
+            Method::Get,
+            &format!("/notes/{id}?format=markdown"),
+            "",
+        );
+        assert_eq!(markdown.body, "# Done\n");
+        assert_eq!(markdown.content_type, "text/markdown; charset=utf-8");
+
+        let delta = call(
+            &store,
+            Method::Get,
+            &format!("/notes/{id}?format=delta"),
+            "",
+        );
+        assert_eq!(
+            serde_json::from_str::<Value>(&delta.body).unwrap(),
+            serde_json::json!({ "ops": [{ "insert": "Done" }, { "insert": "\n", "attributes": { "header": 1 } }] })
+        );
+
+        assert_eq!(
+            call(&store, Method::Delete, &format!("/notes/{id}"), "").status,
+            200
+        );
+        assert_eq!(call(&store, Method::Get, "/notes", "").body, "[]");
+        assert_eq!(
+            call(&store, Method::Get, &format!("/notes/{id}"), "").status,
+            404
+        );
+    }
+
+    #[test]
+    fn bad_requests_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let store: RefCell<Box<dyn NoteStore>> = RefCell::new(Box::new(FileStore::new(
+            dir.path(),
+            Backend::Json,
+            BackupPolicy::default(),
+        )));
+
+        assert_eq!(call(&store, Method::Get, "/colors", "").status, 404);
+        assert_eq!(call(&store, Method::Put, "/notes", "").status, 405);
+        assert_eq!(call(&store, Method::Post, "/notes", "{").status, 400);
+        assert_eq!(
+            call(
+                &store,
+                Method::Patch,
+                &format!("/notes/{}", Uuid::nil()),
+                "{}"
+            )
+            .status,
+            404
+        );
+    }
+
+    #[test]
+    fn only_the_token_gets_in() {
+        let dir = tempfile::tempdir().unwrap();
+        let saved = token(dir.path()).unwrap();
+
+        assert_eq!(token(dir.path()).unwrap(), saved);
+        assert!(is_authorized(Some(&format!("Bearer {saved}")), &saved));
+        assert!(!is_authorized(Some(&format!("Bearer {saved}x")), &saved));
+        assert!(!is_authorized(Some(&saved), &saved));
+        assert!(!is_authorized(None, &saved));
+    }
+}
@@ -0,0 +1,138 @@
+use crate::control::{Client, Request};
+use crate::error::{Result, StickyError};
+use fs2::FileExt;
+use std::fs::{File, OpenOptions};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Locked by the md-sticky that is running, in the app data dir.
+pub const LOCK_FILE: &str = "instance.lock";
+
+/// Held for as long as the app runs, so a second launch knows to hand over to this one instead of
+/// opening every note again and saving over the first one. The OS lets go of it if the app crashes.
+#[derive(Debug)]
+pub struct InstanceLock {
+    _file: File,
+}
+
+impl InstanceLock {
+    /// Takes the lock, `None` if another md-sticky is running.
+    pub fn acquire(app_data_dir: &Path) -> Result<Option<InstanceLock>> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(app_data_dir.join(LOCK_FILE))?;
+
+        match file.try_lock_exclusive() {
+            Ok(()) => Ok(Some(InstanceLock { _file: file })),
+            Err(e) if e.raw_os_error() == fs2::lock_contended_error().raw_os_error() => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// How long a second launch waits for the running md-sticky to take requests, it may have only just started too,
+/// or for a command line command to let go of the lock.
+const HAND_OVER_TIMEOUT: Duration = Duration::from_secs(5);
+/// the first wait between tries, it doubles up to a second
+const FIRST_RETRY: Duration = Duration::from_millis(25);
+
+/// Passes the arguments of a second launch to the running md-sticky over the control socket.
+/// The lock might be held by a command line command writing the store instead, which doesn't take requests,
+/// so the lock is tried too: `Some` if it came free first and this launch is the running md-sticky now.
+pub fn hand_over(app_data_dir: &Path, args: Vec<String>) -> Result<Option<InstanceLock>> {
+    let deadline = Instant::now() + HAND_OVER_TIMEOUT;
+    let mut retry = FIRST_RETRY;
+
+    let mut client = loop {
+        if let Some(client) = Client::connect(app_data_dir)? {
+            break client;
+        }
+        if let Some(lock) = InstanceLock::acquire(app_data_dir)? {
+            return Ok(Some(lock));
+        }
+
+        if Instant::now() >= deadline {
+            return Err(StickyError::Control(
+                "md-sticky is already running, but it isn't taking requests".to_string(),
+            ));
+        }
+
+        thread::sleep(retry);
+        retry = (retry * 2).min(Duration::from_secs(1));
+    };
+
+    client.send(&Request::Launch { args })?;
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::control::{self, Reply};
+    use std::sync::{mpsc, Mutex};
+
+    #[test]
+    fn only_one_instance_gets_the_lock() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let lock = InstanceLock::acquire(dir.path()).unwrap();
+        assert!(lock.is_some());
+        assert!(InstanceLock::acquire(dir.path()).unwrap().is_none());
+
+        drop(lock);
+        assert!(InstanceLock::acquire(dir.path()).unwrap().is_some());
+    }
+
+    #[test]
+    fn a_launch_waits_for_the_running_app_to_take_requests() {
+        let dir = tempfile::tempdir().unwrap();
+        let _first = InstanceLock::acquire(dir.path()).unwrap().unwrap();
+
+        // the first launch has the lock, but opens the control socket a moment later
+        let app_data_dir = dir.path().to_path_buf();
+        let (sender, received) = mpsc::channel();
+        let sender = Mutex::new(sender);
+        let first = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            control::listen(&app_data_dir, move |request| {
+                sender.lock().unwrap().send(request).unwrap();
+                Ok(Reply::Done)
+            })
+            .unwrap();
+        });
+
+        assert!(hand_over(dir.path(), vec!["md-sticky://new".to_string()])
+            .unwrap()
+            .is_none());
+        first.join().unwrap();
+
+        assert_eq!(
+            received.recv().unwrap(),
+            Request::Launch {
+                args: vec!["md-sticky://new".to_string()]
+            }
+        );
+    }
+
+    #[test]
+    fn a_launch_while_a_command_holds_the_lock_starts_once_it_is_done() {
+        let dir = tempfile::tempdir().unwrap();
+
+        // `md-sticky new` writing the store, it holds the lock but doesn't take requests
+        let command = InstanceLock::acquire(dir.path()).unwrap().unwrap();
+        let done = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            drop(command);
+        });
+
+        let lock = hand_over(dir.path(), Vec::new()).unwrap();
+        done.join().unwrap();
+
+        assert!(lock.is_some());
+        assert!(InstanceLock::acquire(dir.path()).unwrap().is_none());
+    }
+}
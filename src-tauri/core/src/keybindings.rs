@@ -0,0 +1,296 @@
+use crate::error::{Result, StickyError};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+/// Accelerators for the menu actions, in the app data dir, like `{ "fit_text": "CmdOrCtrl+Shift+F", "snap_up": null }`.
+pub const KEYBINDINGS_FILE: &str = "keybindings.json";
+
+/// the actions for picking a palette color are `color_1`, `color_2`, ...
+const COLOR_PREFIX: &str = "color_";
+
+/// named keys and what else they can be called, letters, digits and `F1` to `F24` are checked on their own
+const NAMED_KEYS: [(&str, &[&str]); 30] = [
+    ("Up", &["ArrowUp"]),
+    ("Down", &["ArrowDown"]),
+    ("Left", &["ArrowLeft"]),
+    ("Right", &["ArrowRight"]),
+    ("Slash", &["/"]),
+    ("Backslash", &["\\"]),
+    ("Comma", &[","]),
+    ("Period", &["."]),
+    ("Minus", &["-"]),
+    ("Equal", &["="]),
+    ("Semicolon", &[";"]),
+    ("Quote", &["'"]),
+    ("Backquote", &["`"]),
+    ("BracketLeft", &["["]),
+    ("BracketRight", &["]"]),
+    ("Space", &[]),
+    ("Tab", &[]),
+    ("Enter", &["Return"]),
+    ("Escape", &["Esc"]),
+    ("Backspace", &[]),
+    ("Delete", &[]),
+    ("Insert", &[]),
+    ("Home", &[]),
+    ("End", &[]),
+    ("PageUp", &[]),
+    ("PageDown", &[]),
+    ("PrintScreen", &[]),
+    ("CapsLock", &[]),
+    ("ScrollLock", &[]),
+    ("NumLock", &[]),
+];
+
+/// The accelerator of every menu action: the defaults, with `keybindings.json` on top.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Keybindings {
+    bindings: BTreeMap<String, String>,
+}
+
+impl Keybindings {
+    /// Just the defaults, `None` leaves an action without a shortcut.
+    pub fn new(defaults: &[(&str, Option<&str>)]) -> Keybindings {
+        Keybindings {
+            bindings: defaults
+                .iter()
+                .filter_map(|(action, accelerator)| {
+                    accelerator.map(|accelerator| (action.to_string(), accelerator.to_string()))
+                })
+                .collect(),
+        }
+    }
+
+    /// The defaults with the bindings from [`KEYBINDINGS_FILE`], if there is one.
+    pub fn load(app_data_dir: &Path, defaults: &[(&str, Option<&str>)]) -> Result<Keybindings> {
+        let path = app_data_dir.join(KEYBINDINGS_FILE);
+
+        if path.exists() {
+            Keybindings::parse(&fs::read_to_string(path)?, defaults)
+        } else {
+            Ok(Keybindings::new(defaults))
+        }
+    }
+
+    /// Reads the bindings in `json` over the defaults. The error lists every unknown action, accelerator that
+    /// doesn't parse and accelerator bound to more than one action.
+    pub fn parse(json: &str, defaults: &[(&str, Option<&str>)]) -> Result<Keybindings> {
+        let changes = serde_json::from_str::<BTreeMap<String, Option<String>>>(json)
+            .map_err(|e| StickyError::InvalidKeybindings(e.to_string()))?;
+
+        let mut keybindings = Keybindings::new(defaults);
+        let mut problems = Vec::new();
+
+        for (action, accelerator) in changes {
+            let known = defaults.iter().any(|(known, _)| *known == action)
+                || action
+                    .strip_prefix(COLOR_PREFIX)
+                    .and_then(|index| index.parse::<usize>().ok())
+                    .map_or(false, |index| index > 0);
+            if !known {
+                problems.push(format!("there is no action {action}"));
+                continue;
+            }
+
+            match accelerator {
+                Some(accelerator) => {
+                    keybindings.bindings.insert(action, accelerator);
+                }
+                // `null` takes the shortcut off
+                None => {
+                    keybindings.bindings.remove(&action);
+                }
+            }
+        }
+
+        let mut actions_by_keys = BTreeMap::<(BTreeSet<&str>, String), Vec<&str>>::new();
+        for (action, accelerator) in &keybindings.bindings {
+            match keys(accelerator) {
+                Ok(keys) => actions_by_keys.entry(keys).or_default().push(action),
+                Err(e) => problems.push(format!("{action}: {e}")),
+            }
+        }
+        for actions in actions_by_keys.values().filter(|actions| actions.len() > 1) {
+            let accelerators = actions
+                .iter()
+                .map(|action| format!("{action} ({})", keybindings.bindings[*action]))
+                .collect::<Vec<String>>();
+            problems.push(format!(
+                "{} have the same shortcut",
+                accelerators.join(" and ")
+            ));
+        }
+
+        if problems.is_empty() {
+            Ok(keybindings)
+        } else {
+            Err(StickyError::InvalidKeybindings(problems.join("; ")))
+        }
+    }
+
+    /// The accelerator of `action`, `None` if it has no shortcut.
+    pub fn get(&self, action: &str) -> Option<&str> {
+        self.bindings.get(action).map(String::as_str)
+    }
+
+    /// The accelerator of the `index`th palette color, counting from 0.
+    pub fn color(&self, index: usize) -> Option<&str> {
+        self.get(&color_action(index))
+    }
+}
+
+/// The action id for picking the `index`th palette color, counting from 0.
+pub fn color_action(index: usize) -> String {
+    format!("{COLOR_PREFIX}{}", index + 1)
+}
+
+/// The modifiers and the key an accelerator like `CmdOrCtrl+Shift+Up` stands for on this platform,
+/// so accelerators written differently can be compared.
+fn keys(accelerator: &str) -> std::result::Result<(BTreeSet<&'static str>, String), String> {
+    let mut parts = accelerator.split('+').map(str::trim).collect::<Vec<&str>>();
+    let key = parts.pop().unwrap_or_default();
+
+    let mut modifiers = BTreeSet::new();
+    for modifier in parts {
+        let modifier = match modifier.to_uppercase().as_str() {
+            "SHIFT" => "Shift",
+            "ALT" | "OPTION" => "Alt",
+            "CTRL" | "CONTROL" => "Ctrl",
+            "CMD" | "COMMAND" | "SUPER" => "Cmd",
+            "CMDORCTRL" | "CMDORCONTROL" | "COMMANDORCTRL" | "COMMANDORCONTROL" => {
+                if cfg!(target_os = "macos") {
+                    "Cmd"
+                } else {
+                    "Ctrl"
+                }
+            }
+            "" => return Err(format!("{accelerator} has an empty part")),
+            _ => {
+                return Err(format!(
+                    "{modifier} in {accelerator} is not a modifier, use Cmd, Ctrl, CmdOrCtrl, Alt or Shift"
+                ))
+            }
+        };
+
+        if !modifiers.insert(modifier) {
+            return Err(format!("{accelerator} has {modifier} twice"));
+        }
+    }
+
+    if key.is_empty() {
+        return Err(format!("{accelerator} has no key"));
+    }
+    let key = key_name(key).ok_or_else(|| {
+        format!(
+            "{key} in {accelerator} is not a key, use a letter, a digit, F1 to F24 or a name like Up or Slash"
+        )
+    })?;
+
+    Ok((modifiers, key))
+}
+
+fn key_name(key: &str) -> Option<String> {
+    let upper = key.to_uppercase();
+
+    // `KeyA` and `Digit1` are what the browser calls them
+    let single = upper
+        .strip_prefix("KEY")
+        .or_else(|| upper.strip_prefix("DIGIT"))
+        .filter(|rest| rest.len() == 1)
+        .unwrap_or(&upper);
+    if single.len() == 1 && single.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Some(single.to_string());
+    }
+
+    if let Some(number) = upper.strip_prefix('F').and_then(|n| n.parse::<u8>().ok()) {
+        if (1..=24).contains(&number) {
+            return Some(upper);
+        }
+    }
+
+    NAMED_KEYS
+        .iter()
+        .find(|(name, aliases)| {
+            name.eq_ignore_ascii_case(key)
+                || aliases.iter().any(|alias| alias.eq_ignore_ascii_case(key))
+        })
+        .map(|(name, _)| name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEFAULTS: [(&str, Option<&str>); 4] = [
+        ("new_note", Some("CmdOrCtrl+N")),
+        ("fit_text", Some("CmdOrCtrl+Shift+F")),
+        ("next_window", Some("CmdOrCtrl+Slash")),
+        ("clear_colors", None),
+    ];
+
+    #[test]
+    fn the_file_goes_over_the_defaults() {
+        let keybindings = Keybindings::parse(
+            r#"{ "fit_text": "Alt+F", "next_window": null, "clear_colors": "CmdOrCtrl+Shift+Backspace", "color_12": "F12" }"#,
+            &DEFAULTS,
+        )
+        .unwrap();
+
+        assert_eq!(keybindings.get("new_note"), Some("CmdOrCtrl+N"));
+        assert_eq!(keybindings.get("fit_text"), Some("Alt+F"));
+        assert_eq!(keybindings.get("next_window"), None);
+        assert_eq!(
+            keybindings.get("clear_colors"),
+            Some("CmdOrCtrl+Shift+Backspace")
+        );
+        assert_eq!(keybindings.color(11), Some("F12"));
+    }
+
+    #[test]
+    fn mistakes_are_all_reported() {
+        let error = Keybindings::parse(
+            r#"{ "find": "CmdOrCtrl+F", "fit_text": "CmdOrCtrl+Hyper+F", "next_window": "CmdOrCtrl+/", "color_0": "F1" }"#,
+            &DEFAULTS,
+        )
+        .unwrap_err()
+        .to_string();
+
+        assert!(error.contains("there is no action find"), "{error}");
+        assert!(error.contains("there is no action color_0"), "{error}");
+        assert!(
+            error.contains("Hyper in CmdOrCtrl+Hyper+F is not a modifier"),
+            "{error}"
+        );
+        assert!(!error.contains("next_window"), "{error}");
+
+        let error = Keybindings::parse(r#"{ "fit_text": "ctrl+n" }"#, &DEFAULTS)
+            .unwrap_err()
+            .to_string();
+        if !cfg!(target_os = "macos") {
+            assert!(
+                error.contains(
+                    "fit_text (ctrl+n) and new_note (CmdOrCtrl+N) have the same shortcut"
+                ),
+                "{error}"
+            );
+        }
+
+        assert!(Keybindings::parse(r#"{ "fit_text": "CmdOrCtrl+" }"#, &DEFAULTS).is_err());
+        assert!(
+            Keybindings::parse(r#"{ "fit_text": "CmdOrCtrl+Shift+Shift+F" }"#, &DEFAULTS).is_err()
+        );
+        assert!(matches!(
+            Keybindings::parse("{ \"fit_text\": ", &DEFAULTS),
+            Err(StickyError::InvalidKeybindings(_))
+        ));
+    }
+
+    #[test]
+    fn accelerators_written_differently_are_the_same_keys() {
+        assert_eq!(keys("Shift+Alt+ArrowUp"), keys("option+shift+up"));
+        assert_eq!(keys("CmdOrCtrl+KeyF"), keys("CommandOrControl+f"));
+        assert_eq!(keys("Ctrl+/"), keys("Control+Slash"));
+        assert!(keys("F25").is_err());
+    }
+}
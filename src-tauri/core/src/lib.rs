@@ -0,0 +1,32 @@
+//! Everything md-sticky does that doesn't need a live tauri window: the note model,
+//! the note stores with their save files, database and note history, the color palette and the snapping geometry.
+
+pub mod backup;
+pub mod capture;
+pub mod cli;
+pub mod control;
+#[cfg(target_os = "linux")]
+pub mod dbus;
+pub mod delta;
+pub mod editor;
+pub mod error;
+pub mod folder;
+pub mod format;
+pub mod geometry;
+pub mod history;
+pub mod http;
+pub mod instance;
+pub mod keybindings;
+pub mod link;
+pub mod logging;
+pub mod markdown;
+pub mod mirror;
+pub mod model;
+pub mod palette;
+pub mod registry;
+pub mod settings;
+pub mod sqlite;
+pub mod storage;
+pub mod store;
+pub mod trash;
+pub mod watch;
@@ -0,0 +1,93 @@
+use crate::control::Request;
+use crate::error::{Result, StickyError};
+use url::Url;
+use uuid::Uuid;
+
+/// The URL scheme md-sticky is registered for, as in `md-sticky://new?text=...` and `md-sticky://open/<id>`.
+pub const SCHEME: &str = "md-sticky";
+
+/// Whether a launch argument is an md-sticky link rather than an option.
+pub fn is_link(arg: &str) -> bool {
+    arg.get(..SCHEME.len() + 1).map_or(false, |prefix| {
+        prefix.eq_ignore_ascii_case(&format!("{SCHEME}:"))
+    })
+}
+
+/// What a link asks for:
+/// - `md-sticky://new?color=%2381B7DD&text=...` opens a note, both parameters are optional and `text` is Markdown
+/// - `md-sticky://open/<id>` brings a note to the front
+pub fn parse(link: &str) -> Result<Request> {
+    let invalid = |reason: &str| StickyError::InvalidLink(link.to_string(), reason.to_string());
+
+    let url = Url::parse(link).map_err(|e| invalid(&e.to_string()))?;
+    if url.scheme() != SCHEME {
+        return Err(invalid(&format!("it isn't a {SCHEME}: link")));
+    }
+
+    match url.host_str().unwrap_or_default() {
+        "new" => {
+            let mut color = None;
+            let mut text = String::new();
+
+            for (key, value) in url.query_pairs() {
+                match key.as_ref() {
+                    "color" => color = Some(value.into_owned()).filter(|color| !color.is_empty()),
+                    "text" => text = value.into_owned(),
+                    // links made for a newer version may carry more, they still open a note
+                    key => log::warn!("ignoring {key} in {link}"),
+                }
+            }
+
+            Ok(Request::New { color, text })
+        }
+        "open" => {
+            let id = url.path().trim_matches('/');
+
+            Uuid::parse_str(id)
+                .map(|id| Request::Focus { id })
+                .map_err(|_| invalid(&format!("{id} is not a note id")))
+        }
+        action => Err(invalid(&format!(
+            "unknown action {action}, use new or open"
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn links_become_requests() {
+        let id = Uuid::from_u128(1);
+
+        assert_eq!(
+            parse("md-sticky://new?color=%2381B7DD&text=%23%20Todo%0A-%20%5B%20%5D%20milk")
+                .unwrap(),
+            Request::New {
+                color: Some("#81B7DD".to_string()),
+                text: "# Todo\n- [ ] milk".to_string()
+            }
+        );
+        assert_eq!(
+            parse("md-sticky://new").unwrap(),
+            Request::New {
+                color: None,
+                text: String::new()
+            }
+        );
+        assert_eq!(
+            parse(&format!("md-sticky://open/{id}")).unwrap(),
+            Request::Focus { id }
+        );
+
+        assert!(parse("md-sticky://open/12").is_err());
+        assert!(parse("md-sticky://delete/12").is_err());
+        assert!(parse("https://new?text=hi").is_err());
+
+        assert!(is_link("md-sticky://new"));
+        assert!(is_link("MD-STICKY://new"));
+        assert!(!is_link("--verbose"));
+        assert!(!is_link("md"));
+    }
+}
@@ -0,0 +1,177 @@
+use chrono::Local;
+use log::{LevelFilter, Log, Metadata, Record};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+pub const LOG_FILE: &str = "md-sticky.log";
+
+/// Logs to `md-sticky.log` in the app data dir. Once the file reaches `max_size` bytes it is moved to
+/// `md-sticky.log.1`, pushing the older logs up to `md-sticky.log.<keep>`, and a new file is started.
+pub struct RotatingLogger {
+    path: PathBuf,
+    max_size: u64,
+    keep: usize,
+    level: LevelFilter,
+    file: Mutex<Option<File>>,
+}
+
+impl RotatingLogger {
+    pub fn new(app_data_dir: &Path, max_size: u64, keep: usize, level: LevelFilter) -> Self {
+        RotatingLogger {
+            path: app_data_dir.join(LOG_FILE),
+            max_size,
+            keep,
+            level,
+            file: Mutex::new(None),
+        }
+    }
+
+    /// Installs this as the logger for the `log` macros.
+    pub fn init(self) -> Result<(), log::SetLoggerError> {
+        log::set_max_level(self.level);
+        log::set_boxed_logger(Box::new(self))
+    }
+
+    fn rotated_path(&self, n: usize) -> PathBuf {
+        self.path.with_file_name(format!("{LOG_FILE}.{n}"))
+    }
+
+    fn rotate(&self) -> io::Result<()> {
+        for n in (1..self.keep).rev() {
+            let from = self.rotated_path(n);
+            if from.exists() {
+                fs::rename(from, self.rotated_path(n + 1))?;
+            }
+        }
+
+        if self.keep > 0 {
+            fs::rename(&self.path, self.rotated_path(1))
+        } else {
+            fs::remove_file(&self.path)
+        }
+    }
+
+    fn write(&self, line: &str) -> io::Result<()> {
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+
+        if let Some(open) = file.as_ref() {
+            if open.metadata()?.len() + line.len() as u64 > self.max_size {
+                *file = None;
+                self.rotate()?;
+            }
+        }
+
+        if file.is_none() {
+            *file = Some(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.path)?,
+            );
+        }
+
+        match file.as_mut() {
+            Some(open) => open.write_all(line.as_bytes()),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Log for RotatingLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let line = format!(
+            "{} {:<5} {}: {}\n",
+            Local::now().format("%Y-%m-%d %H:%M:%S%.3f"),
+            record.level(),
+            record.target(),
+            record.args()
+        );
+
+        if cfg!(debug_assertions) {
+            eprint!("{line}");
+        }
+
+        // there is nowhere left to report a failure to log
+        let _ = self.write(&line);
+    }
+
+    fn flush(&self) {
+        if let Some(file) = self.file.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+            let _ = file.flush();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::Level;
+
+    fn log(logger: &RotatingLogger, message: &str) {
+        logger.log(
+            &Record::builder()
+                .args(format_args!("{message}"))
+                .level(Level::Info)
+                .target("test")
+                .build(),
+        );
+    }
+
+    #[test]
+    fn writes_formatted_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let logger = RotatingLogger::new(dir.path(), 1024, 2, LevelFilter::Info);
+
+        log(&logger, "hello");
+
+        let contents = fs::read_to_string(dir.path().join(LOG_FILE)).unwrap();
+        assert!(contents.ends_with("INFO  test: hello\n"));
+    }
+
+    #[test]
+    fn rotates_and_keeps_a_limited_number_of_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let logger = RotatingLogger::new(dir.path(), 60, 2, LevelFilter::Info);
+
+        for i in 0..4 {
+            log(&logger, &format!("message number {i}"));
+        }
+
+        let mut names = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<String>>();
+        names.sort();
+
+        assert_eq!(
+            names,
+            vec!["md-sticky.log", "md-sticky.log.1", "md-sticky.log.2"]
+        );
+        assert!(fs::read_to_string(dir.path().join(LOG_FILE))
+            .unwrap()
+            .contains("message number 3"));
+        assert!(fs::read_to_string(dir.path().join("md-sticky.log.2"))
+            .unwrap()
+            .contains("message number 1"));
+    }
+
+    #[test]
+    fn ignores_levels_below_the_filter() {
+        let dir = tempfile::tempdir().unwrap();
+        let logger = RotatingLogger::new(dir.path(), 1024, 2, LevelFilter::Warn);
+
+        log(&logger, "hello");
+
+        assert!(!dir.path().join(LOG_FILE).exists());
+    }
+}
@@ -0,0 +1,1124 @@
+use crate::delta::{Attributes, Delta, Insert, Line, ListKind};
+use crate::error::Result;
+use serde_json::{Map, Value};
+
+/// Renders a note's contents as GitHub-flavored Markdown.
+pub fn from_contents(contents: &str) -> Result<String> {
+    Ok(render(&Delta::parse(contents)?))
+}
+
+/// Renders a delta as GitHub-flavored Markdown, one line of the note per line of Markdown.
+/// Formats Markdown can't express, like underline or colors, are dropped.
+pub fn render(delta: &Delta) -> String {
+    let lines = delta.lines();
+
+    let mut out = String::new();
+    let mut lists = ListState::default();
+    let mut previous: Option<&Attributes> = None;
+
+    let mut i = 0;
+    while i < lines.len() {
+        let attributes = &lines[i].attributes;
+
+        if attributes.code_block.is_some() {
+            let language = attributes.code_language();
+            let start = i;
+            while i < lines.len() && lines[i].attributes.code_block.is_some() {
+                i += 1;
+            }
+
+            out.push_str(&code_block(&lines[start..i], language));
+            lists = ListState::default();
+            previous = Some(attributes);
+            continue;
+        }
+
+        let text = inline(&lines[i]);
+
+        match attributes.list {
+            Some(kind) => out.push_str(&lists.item(kind, attributes.indent.unwrap_or(0).into())),
+            None => {
+                lists = ListState::default();
+
+                // a plain line right after a list item or a quote would be read as part of it
+                let lazy = previous.map_or(false, |previous| {
+                    previous.list.is_some() || previous.blockquote.is_some()
+                });
+                if lazy && attributes.header.is_none() && attributes.blockquote.is_none() {
+                    out.push('\n');
+                }
+
+                if let Some(level) = attributes.header {
+                    out.push_str(&"#".repeat(level.clamp(1, 6).into()));
+                    out.push(' ');
+                } else if attributes.blockquote.is_some() {
+                    out.push_str("> ");
+                }
+            }
+        }
+
+        if attributes.header.is_some() {
+            out.push_str(&text);
+        } else {
+            out.push_str(&escape_line_start(&text));
+        }
+
+        // an empty quote line is just `>`
+        if attributes.blockquote.is_some() && text.is_empty() {
+            out.pop();
+        }
+
+        out.push('\n');
+        previous = Some(attributes);
+        i += 1;
+    }
+
+    out
+}
+
+/// Keeps track of the lists above the current line, so nested items line up with their parent's text
+/// and ordered items get their numbers.
+#[derive(Default)]
+struct ListState {
+    // column where the text of the last item on every level starts
+    text_columns: Vec<usize>,
+    // number of the last ordered item on every level
+    numbers: Vec<usize>,
+}
+
+impl ListState {
+    fn item(&mut self, kind: ListKind, indent: usize) -> String {
+        // an item can't be nested deeper than one level below the one before it
+        let level = indent.min(self.text_columns.len());
+        let column = match level {
+            0 => 0,
+            _ => self.text_columns[level - 1],
+        };
+
+        self.numbers.resize(level + 1, 0);
+        self.numbers[level] = match kind {
+            ListKind::Ordered => self.numbers[level] + 1,
+            _ => 0,
+        };
+
+        let marker = match kind {
+            ListKind::Bullet => "- ".to_string(),
+            ListKind::Ordered => format!("{}. ", self.numbers[level]),
+            ListKind::Checked => "- [x] ".to_string(),
+            ListKind::Unchecked => "- [ ] ".to_string(),
+        };
+
+        // the checkbox is part of the item's text as far as nesting goes
+        let marker_width = match kind {
+            ListKind::Checked | ListKind::Unchecked => 2,
+            _ => marker.len(),
+        };
+
+        self.text_columns.truncate(level);
+        self.text_columns.push(column + marker_width);
+
+        format!("{}{marker}", " ".repeat(column))
+    }
+}
+
+fn code_block(lines: &[Line], language: Option<&str>) -> String {
+    let code = lines
+        .iter()
+        .map(|line| {
+            line.inserts
+                .iter()
+                .filter_map(|(insert, _)| match insert {
+                    Insert::Text(text) => Some(text.as_str()),
+                    Insert::Embed(_) => None,
+                })
+                .collect::<String>()
+        })
+        .collect::<Vec<String>>();
+
+    let fence = "`".repeat(longest_run(&code.concat(), '`').max(2) + 1);
+
+    format!(
+        "{fence}{}\n{}\n{fence}\n",
+        language.unwrap_or_default(),
+        code.join("\n")
+    )
+}
+
+#[derive(Clone, PartialEq)]
+enum Mark {
+    Link(String),
+    Bold,
+    Italic,
+    Strike,
+}
+
+impl Mark {
+    fn open(&self) -> &'static str {
+        match self {
+            Mark::Link(_) => "[",
+            Mark::Bold => "**",
+            Mark::Italic => "*",
+            Mark::Strike => "~~",
+        }
+    }
+
+    fn close(&self) -> String {
+        match self {
+            Mark::Link(url) if url.contains([' ', '(', ')']) => format!("](<{url}>)"),
+            Mark::Link(url) => format!("]({url})"),
+            Mark::Bold => "**".to_string(),
+            Mark::Italic => "*".to_string(),
+            Mark::Strike => "~~".to_string(),
+        }
+    }
+}
+
+// always in the same order, so formats that carry on from one insert to the next stay open
+fn marks(attributes: &Attributes) -> Vec<Mark> {
+    let mut marks = Vec::new();
+
+    if let Some(url) = &attributes.link {
+        marks.push(Mark::Link(url.clone()));
+    }
+    if attributes.bold == Some(true) {
+        marks.push(Mark::Bold);
+    }
+    if attributes.italic == Some(true) {
+        marks.push(Mark::Italic);
+    }
+    if attributes.strike == Some(true) {
+        marks.push(Mark::Strike);
+    }
+
+    marks
+}
+
+/// The text of a line with its inline formats.
+fn inline(line: &Line) -> String {
+    let mut out = String::new();
+    let mut open: Vec<Mark> = Vec::new();
+
+    for (insert, attributes) in &line.inserts {
+        let (text, leading, trailing) = match insert {
+            Insert::Text(text) if attributes.code == Some(true) => (code_span(text), "", ""),
+            Insert::Text(text) => {
+                let trimmed = text.trim();
+
+                // whitespace can't start or end emphasis, so it goes outside of the markers
+                if trimmed.is_empty() {
+                    out.push_str(text);
+                    continue;
+                }
+
+                let start = text.find(trimmed).unwrap_or(0);
+                (
+                    escape(trimmed),
+                    &text[..start],
+                    &text[start + trimmed.len()..],
+                )
+            }
+            Insert::Embed(embed) => match embed.get("image").and_then(Value::as_str) {
+                Some(url) => (format!("![]({url})"), "", ""),
+                None => continue,
+            },
+        };
+
+        let wanted = marks(attributes);
+        let common = open
+            .iter()
+            .zip(&wanted)
+            .take_while(|(open, wanted)| open == wanted)
+            .count();
+
+        close_marks(&mut out, &mut open, common);
+
+        out.push_str(leading);
+        for mark in &wanted[common..] {
+            out.push_str(mark.open());
+        }
+        open = wanted;
+
+        out.push_str(&text);
+        out.push_str(trailing);
+    }
+
+    close_marks(&mut out, &mut open, 0);
+
+    out
+}
+
+fn close_marks(out: &mut String, open: &mut Vec<Mark>, keep: usize) {
+    if open.len() <= keep {
+        return;
+    }
+
+    let trimmed_len = out.trim_end().len();
+    let trailing = out.split_off(trimmed_len);
+
+    for mark in open.drain(keep..).rev() {
+        out.push_str(&mark.close());
+    }
+
+    out.push_str(&trailing);
+}
+
+fn code_span(text: &str) -> String {
+    let fence = "`".repeat(longest_run(text, '`') + 1);
+
+    // a span that starts or ends with a backtick needs some room between it and the fence
+    if text.starts_with('`') || text.ends_with('`') {
+        format!("{fence} {text} {fence}")
+    } else {
+        format!("{fence}{text}{fence}")
+    }
+}
+
+fn longest_run(text: &str, c: char) -> usize {
+    text.split(|other| other != c)
+        .map(str::len)
+        .max()
+        .unwrap_or(0)
+}
+
+/// Escapes everything in plain text that Markdown would read as formatting.
+pub(crate) fn escape(text: &str) -> String {
+    let chars = text.chars().collect::<Vec<char>>();
+    let mut out = String::with_capacity(text.len());
+
+    for (i, &c) in chars.iter().enumerate() {
+        let escaped = match c {
+            '\\' | '`' | '*' | '[' | ']' | '~' | '<' => true,
+            // `snake_case` can't start emphasis
+            '_' => {
+                let before = i.checked_sub(1).map(|i| chars[i]);
+                let after = chars.get(i + 1);
+                !(before.map_or(false, char::is_alphanumeric)
+                    && after.map_or(false, |c| c.is_alphanumeric()))
+            }
+            _ => false,
+        };
+
+        if escaped {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+
+    out
+}
+
+/// Escapes what would turn a plain line into a heading, quote, list item or rule.
+fn escape_line_start(text: &str) -> String {
+    let digits = text.chars().take_while(char::is_ascii_digit).count();
+
+    if text.starts_with(['#', '>', '-', '+', '=']) {
+        format!("\\{text}")
+    } else if digits > 0 && text[digits..].starts_with(['.', ')']) {
+        format!("{}\\{}", &text[..digits], &text[digits..])
+    } else {
+        text.to_string()
+    }
+}
+
+/// Reads GitHub-flavored Markdown into a note's contents, ready for `quill.setContents`.
+pub fn to_contents(markdown: &str) -> Result<String> {
+    parse(markdown).to_json()
+}
+
+/// Reads GitHub-flavored Markdown into a delta, one line of Markdown per line of the note.
+/// Headings, emphasis, code, lists, task items and quotes become quill formats,
+/// anything else quill can't show, like tables or rules, stays as plain text.
+pub fn parse(markdown: &str) -> Delta {
+    let mut delta = Delta::default();
+    let mut lists = Vec::new();
+    // whether the last line was a list item or a quote, see the blank line `render` puts after those
+    let mut after_block = false;
+
+    let mut lines = markdown.lines();
+    while let Some(line) = lines.next() {
+        let line = line.replace('\t', "    ");
+        let indent = line.len() - line.trim_start().len();
+        let rest = line.trim_start();
+
+        if let Some((fence, language)) = open_fence(rest) {
+            let code_block = Attributes {
+                code_block: Some(Value::String(language.unwrap_or("plain").to_string())),
+                ..Default::default()
+            };
+
+            for code in lines.by_ref() {
+                if is_closing_fence(code.trim_start(), &fence) {
+                    break;
+                }
+
+                let code_indent = code.len() - code.trim_start_matches(' ').len();
+                delta.push(
+                    Insert::Text(code[code_indent.min(indent)..].to_string()),
+                    Attributes::default(),
+                );
+                delta.push(Insert::Text("\n".to_string()), code_block.clone());
+            }
+
+            lists.clear();
+            after_block = false;
+            continue;
+        }
+
+        let (text, attributes) = if rest.is_empty() {
+            if after_block {
+                after_block = false;
+                continue;
+            }
+
+            lists.clear();
+            ("", Attributes::default())
+        } else if let Some((level, text)) = heading(rest) {
+            lists.clear();
+            after_block = false;
+            (
+                text,
+                Attributes {
+                    header: Some(level),
+                    ..Default::default()
+                },
+            )
+        } else if rest.starts_with('>') {
+            // nested quotes become a single one
+            let text = rest.trim_start_matches(['>', ' ']);
+
+            lists.clear();
+            after_block = true;
+            (
+                text,
+                Attributes {
+                    blockquote: Some(true),
+                    ..Default::default()
+                },
+            )
+        } else if let Some((kind, marker_width, text)) = list_item(rest) {
+            // the item is nested in the ones above it whose text it lines up with
+            while matches!(lists.last(), Some(&text_column) if indent < text_column) {
+                lists.pop();
+            }
+            let level = lists.len();
+            lists.push(indent + marker_width);
+
+            after_block = true;
+            (
+                text,
+                Attributes {
+                    list: Some(kind),
+                    indent: (level > 0).then(|| level.min(8) as u8),
+                    ..Default::default()
+                },
+            )
+        } else {
+            // text indented under a list item would be another paragraph of it, quill has no room for that
+            let continues_item =
+                matches!(lists.first(), Some(&text_column) if indent >= text_column);
+            if !continues_item {
+                lists.clear();
+            }
+
+            after_block = false;
+            (
+                if continues_item { rest } else { line.as_str() },
+                Attributes::default(),
+            )
+        };
+
+        for (insert, inline) in parse_inline(text) {
+            delta.push(insert, inline);
+        }
+        delta.push(Insert::Text("\n".to_string()), attributes);
+    }
+
+    // quill documents always end with a newline
+    if delta.ops.is_empty() {
+        delta.push(Insert::Text("\n".to_string()), Attributes::default());
+    }
+
+    delta
+}
+
+/// The fence and language of a line that opens a fenced code block.
+fn open_fence(line: &str) -> Option<(String, Option<&str>)> {
+    let c = line.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let length = line.len() - line.trim_start_matches(c).len();
+
+    let info = line[length..].trim();
+    if length < 3 || (c == '`' && info.contains('`')) {
+        return None;
+    }
+
+    Some((line[..length].to_string(), info.split_whitespace().next()))
+}
+
+fn is_closing_fence(line: &str, fence: &str) -> bool {
+    let c = fence.chars().next().unwrap_or('`');
+    let rest = line.trim_start_matches(c);
+
+    line.len() - rest.len() >= fence.len() && rest.trim().is_empty()
+}
+
+/// The level and text of an ATX heading like `## Title ##`.
+fn heading(line: &str) -> Option<(u8, &str)> {
+    let level = line.len() - line.trim_start_matches('#').len();
+    let rest = &line[level..];
+
+    if !(1..=6).contains(&level) || !(rest.is_empty() || rest.starts_with(' ')) {
+        return None;
+    }
+
+    let text = rest.trim();
+    let without_closing = text.trim_end_matches('#');
+    let text = if without_closing.is_empty() || without_closing.ends_with(' ') {
+        without_closing.trim_end()
+    } else {
+        text
+    };
+
+    Some((level as u8, text))
+}
+
+/// The kind, marker width and text of a list item like `- [ ] milk` or `2. eggs`.
+fn list_item(line: &str) -> Option<(ListKind, usize, &str)> {
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+
+    let marker = if line.starts_with(['-', '*', '+']) {
+        1
+    } else if (1..10).contains(&digits) && line[digits..].starts_with(['.', ')']) {
+        digits + 1
+    } else {
+        return None;
+    };
+
+    let text = &line[marker..];
+    if !(text.is_empty() || text.starts_with(' ')) {
+        return None;
+    }
+    let text = text.strip_prefix(' ').unwrap_or(text);
+    let marker_width = marker + 1;
+
+    if digits > 0 {
+        return Some((ListKind::Ordered, marker_width, text));
+    }
+
+    for (checkbox, kind) in [
+        ("[ ]", ListKind::Unchecked),
+        ("[x]", ListKind::Checked),
+        ("[X]", ListKind::Checked),
+    ] {
+        if let Some(rest) = text.strip_prefix(checkbox) {
+            if rest.is_empty() || rest.starts_with(' ') {
+                return Some((kind, marker_width, rest.strip_prefix(' ').unwrap_or(rest)));
+            }
+        }
+    }
+
+    Some((ListKind::Bullet, marker_width, text))
+}
+
+enum Token {
+    Text(String, Attributes),
+    Embed(Map<String, Value>, Attributes),
+    /// a run of `*`, `_` or `~` that might open or close emphasis
+    Delimiter {
+        c: char,
+        length: usize,
+        left: usize,
+        open: bool,
+        close: bool,
+        attributes: Attributes,
+    },
+}
+
+/// The inserts of a line of Markdown, following the CommonMark rules for emphasis closely enough for notes.
+fn parse_inline(text: &str) -> Vec<(Insert, Attributes)> {
+    let chars = text.chars().collect::<Vec<char>>();
+
+    let mut tokens = Vec::new();
+    tokenize(&chars, &Attributes::default(), &mut tokens);
+    match_emphasis(&mut tokens);
+
+    tokens
+        .into_iter()
+        .filter_map(|token| match token {
+            Token::Text(text, attributes) if !text.is_empty() => {
+                Some((Insert::Text(text), attributes))
+            }
+            Token::Text(..) => None,
+            Token::Embed(embed, attributes) => Some((Insert::Embed(embed), attributes)),
+            Token::Delimiter {
+                c,
+                left,
+                attributes,
+                ..
+            } if left > 0 => Some((Insert::Text(c.to_string().repeat(left)), attributes)),
+            Token::Delimiter { .. } => None,
+        })
+        .collect()
+}
+
+fn is_punctuation(c: char) -> bool {
+    c.is_ascii_punctuation() || !(c.is_alphanumeric() || c.is_whitespace())
+}
+
+fn tokenize(chars: &[char], attributes: &Attributes, tokens: &mut Vec<Token>) {
+    let mut text = String::new();
+    let flush = |text: &mut String, tokens: &mut Vec<Token>| {
+        if !text.is_empty() {
+            tokens.push(Token::Text(std::mem::take(text), attributes.clone()));
+        }
+    };
+
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let run = chars[i..].iter().take_while(|other| **other == c).count();
+
+        match c {
+            '\\' if chars.get(i + 1).map_or(false, char::is_ascii_punctuation) => {
+                text.push(chars[i + 1]);
+                i += 2;
+            }
+            '`' => {
+                let closing = (i + run..chars.len()).find(|&j| {
+                    chars[j] == '`'
+                        && (j == 0 || chars[j - 1] != '`')
+                        && chars[j..].iter().take_while(|other| **other == '`').count() == run
+                });
+
+                match closing {
+                    Some(j) => {
+                        flush(&mut text, tokens);
+
+                        let mut code = chars[i + run..j].iter().collect::<String>();
+                        if code.len() > 2
+                            && code.starts_with(' ')
+                            && code.ends_with(' ')
+                            && !code.trim().is_empty()
+                        {
+                            code = code[1..code.len() - 1].to_string();
+                        }
+
+                        tokens.push(Token::Text(
+                            code,
+                            Attributes {
+                                code: Some(true),
+                                ..attributes.clone()
+                            },
+                        ));
+                        i = j + run;
+                    }
+                    None => {
+                        text.push_str(&"`".repeat(run));
+                        i += run;
+                    }
+                }
+            }
+            '*' | '_' | '~' if c != '~' || run == 2 => {
+                flush(&mut text, tokens);
+
+                let before = i.checked_sub(1).map_or(' ', |i| chars[i]);
+                let after = chars.get(i + run).copied().unwrap_or(' ');
+
+                let left_flanking = !after.is_whitespace()
+                    && (!is_punctuation(after) || before.is_whitespace() || is_punctuation(before));
+                let right_flanking = !before.is_whitespace()
+                    && (!is_punctuation(before) || after.is_whitespace() || is_punctuation(after));
+
+                let (open, close) = if c == '_' {
+                    (
+                        left_flanking && (!right_flanking || is_punctuation(before)),
+                        right_flanking && (!left_flanking || is_punctuation(after)),
+                    )
+                } else {
+                    (left_flanking, right_flanking)
+                };
+
+                tokens.push(Token::Delimiter {
+                    c,
+                    length: run,
+                    left: run,
+                    open,
+                    close,
+                    attributes: attributes.clone(),
+                });
+                i += run;
+            }
+            '!' if chars.get(i + 1) == Some(&'[') => match link(chars, i + 1) {
+                Some((_label_end, url, end)) => {
+                    flush(&mut text, tokens);
+
+                    let mut embed = Map::new();
+                    embed.insert("image".to_string(), Value::String(url));
+                    tokens.push(Token::Embed(embed, attributes.clone()));
+                    i = end;
+                }
+                None => {
+                    text.push(c);
+                    i += 1;
+                }
+            },
+            '[' => match link(chars, i) {
+                Some((label_end, url, end)) => {
+                    flush(&mut text, tokens);
+
+                    let linked = Attributes {
+                        link: Some(url),
+                        ..attributes.clone()
+                    };
+                    tokenize(&chars[i + 1..label_end], &linked, tokens);
+                    i = end;
+                }
+                None => {
+                    text.push(c);
+                    i += 1;
+                }
+            },
+            '<' => {
+                let url = chars[i + 1..]
+                    .iter()
+                    .position(|c| *c == '>')
+                    .map(|length| chars[i + 1..i + 1 + length].iter().collect::<String>())
+                    .filter(|url| {
+                        ["http://", "https://", "mailto:"]
+                            .iter()
+                            .any(|scheme| url.starts_with(scheme))
+                            && !url.contains(char::is_whitespace)
+                    });
+
+                match url {
+                    Some(url) => {
+                        flush(&mut text, tokens);
+
+                        i += url.chars().count() + 2;
+                        tokens.push(Token::Text(
+                            url.clone(),
+                            Attributes {
+                                link: Some(url),
+                                ..attributes.clone()
+                            },
+                        ));
+                    }
+                    None => {
+                        text.push(c);
+                        i += 1;
+                    }
+                }
+            }
+            _ => {
+                text.push(c);
+                i += 1;
+            }
+        }
+    }
+
+    flush(&mut text, tokens);
+}
+
+/// For a `[` at `start`, the index of its `]`, the link's url and the index after the `)` that ends it.
+fn link(chars: &[char], start: usize) -> Option<(usize, String, usize)> {
+    let mut depth = 0;
+    let mut i = start;
+    let label_end = loop {
+        match chars.get(i)? {
+            '\\' => i += 1,
+            '[' => depth += 1,
+            ']' => {
+                depth -= 1;
+                if depth == 0 {
+                    break i;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    };
+
+    if chars.get(label_end + 1) != Some(&'(') {
+        return None;
+    }
+
+    let mut i = label_end + 2;
+    let mut url = String::new();
+
+    if chars.get(i) == Some(&'<') {
+        i += 1;
+        while *chars.get(i)? != '>' {
+            url.push(chars[i]);
+            i += 1;
+        }
+        i += 1;
+    } else {
+        let mut parens = 0;
+        while let Some(&c) = chars.get(i) {
+            if c.is_whitespace() || (c == ')' && parens == 0) {
+                break;
+            }
+            match c {
+                '(' => parens += 1,
+                ')' => parens -= 1,
+                _ => {}
+            }
+            url.push(c);
+            i += 1;
+        }
+    }
+
+    // skip the title, it has nowhere to go
+    while chars.get(i).map_or(false, |c| *c != ')') {
+        i += 1;
+    }
+
+    chars.get(i)?;
+    Some((label_end, url, i + 1))
+}
+
+/// Pairs up the delimiter runs into bold, italic and strikethrough, and formats the tokens between them.
+fn match_emphasis(tokens: &mut [Token]) {
+    let mut closer = 0;
+
+    while closer < tokens.len() {
+        let (c, length, left, open, close) = match &tokens[closer] {
+            Token::Delimiter {
+                c,
+                length,
+                left,
+                open,
+                close,
+                ..
+            } => (*c, *length, *left, *open, *close),
+            _ => {
+                closer += 1;
+                continue;
+            }
+        };
+
+        if !close || left == 0 {
+            closer += 1;
+            continue;
+        }
+
+        let opener = (0..closer).rev().find(|&j| match &tokens[j] {
+            Token::Delimiter {
+                c: opener_c,
+                length: opener_length,
+                left: opener_left,
+                open: true,
+                close: opener_close,
+                ..
+            } => {
+                // the rule of 3 keeps `**a*b***` from pairing the first `*` with `**`
+                let both = *opener_close || open;
+                *opener_c == c
+                    && *opener_left > 0
+                    && !(both
+                        && (opener_length + length) % 3 == 0
+                        && !(opener_length % 3 == 0 && length % 3 == 0))
+            }
+            _ => false,
+        });
+
+        let opener = match opener {
+            Some(opener) => opener,
+            None => {
+                closer += 1;
+                continue;
+            }
+        };
+
+        let opener_left = match &tokens[opener] {
+            Token::Delimiter { left, .. } => *left,
+            _ => 0,
+        };
+        let used = if c == '~' || (left >= 2 && opener_left >= 2) {
+            2
+        } else {
+            1
+        };
+
+        for token in &mut tokens[opener + 1..closer] {
+            let attributes = match token {
+                Token::Text(_, attributes) | Token::Embed(_, attributes) => attributes,
+                Token::Delimiter {
+                    attributes,
+                    open,
+                    close,
+                    ..
+                } => {
+                    // whatever is left of these can't pair up with anything outside anymore
+                    *open = false;
+                    *close = false;
+                    attributes
+                }
+            };
+
+            match (c, used) {
+                ('~', _) => attributes.strike = Some(true),
+                (_, 2) => attributes.bold = Some(true),
+                _ => attributes.italic = Some(true),
+            }
+        }
+
+        for index in [opener, closer] {
+            if let Token::Delimiter { left, .. } = &mut tokens[index] {
+                *left -= used;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Notes saved by the app, next to the Markdown they should turn into.
+    const CORPUS: [(&str, &str); 4] = [
+        (
+            include_str!("../corpus/groceries.json"),
+            include_str!("../corpus/groceries.md"),
+        ),
+        (
+            include_str!("../corpus/meeting.json"),
+            include_str!("../corpus/meeting.md"),
+        ),
+        (
+            include_str!("../corpus/snippets.json"),
+            include_str!("../corpus/snippets.md"),
+        ),
+        (
+            include_str!("../corpus/plain.json"),
+            include_str!("../corpus/plain.md"),
+        ),
+    ];
+
+    fn text(text: &str, attributes: Attributes) -> Line {
+        Line {
+            inserts: vec![(Insert::Text(text.to_string()), attributes)],
+            attributes: Attributes::default(),
+        }
+    }
+
+    #[test]
+    fn corpus_renders_to_markdown() {
+        for (contents, markdown) in CORPUS {
+            assert_eq!(from_contents(contents.trim()).unwrap(), markdown);
+        }
+    }
+
+    #[test]
+    fn corpus_deltas_survive_a_round_trip() {
+        for (contents, _markdown) in CORPUS {
+            let delta = Delta::parse(contents).unwrap();
+
+            assert_eq!(
+                serde_json::from_str::<Value>(&delta.to_json().unwrap()).unwrap(),
+                serde_json::from_str::<Value>(contents).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn corpus_markdown_reads_back_into_the_same_delta() {
+        for (contents, markdown) in CORPUS {
+            assert_eq!(parse(markdown), Delta::parse(contents).unwrap());
+            assert_eq!(render(&parse(markdown)), markdown);
+        }
+    }
+
+    fn ops(markdown: &str) -> Vec<(Insert, Attributes)> {
+        let mut delta = Delta::default();
+        for (insert, attributes) in parse_inline(markdown) {
+            delta.push(insert, attributes);
+        }
+
+        delta
+            .ops
+            .into_iter()
+            .map(|op| (op.insert, op.attributes))
+            .collect()
+    }
+
+    fn styled(text: &str, f: fn(&mut Attributes)) -> (Insert, Attributes) {
+        let mut attributes = Attributes::default();
+        f(&mut attributes);
+        (Insert::Text(text.to_string()), attributes)
+    }
+
+    #[test]
+    fn emphasis_is_read_like_commonmark() {
+        assert_eq!(
+            ops("**a*b***"),
+            vec![
+                styled("a", |a| a.bold = Some(true)),
+                styled("b", |a| {
+                    a.bold = Some(true);
+                    a.italic = Some(true)
+                }),
+            ]
+        );
+        assert_eq!(
+            ops("_it_ and ~~gone~~"),
+            vec![
+                styled("it", |a| a.italic = Some(true)),
+                styled(" and ", |_| {}),
+                styled("gone", |a| a.strike = Some(true)),
+            ]
+        );
+        assert_eq!(
+            ops("snake_case_name"),
+            vec![styled("snake_case_name", |_| {})]
+        );
+        assert_eq!(ops("2 * 3 * 4"), vec![styled("2 * 3 * 4", |_| {})]);
+    }
+
+    #[test]
+    fn links_code_and_images() {
+        assert_eq!(
+            ops("see [the **docs**](https://example.com/a_(b) \"title\") or `x*y`"),
+            vec![
+                styled("see ", |_| {}),
+                styled("the ", |a| a.link =
+                    Some("https://example.com/a_(b)".to_string())),
+                styled("docs", |a| {
+                    a.link = Some("https://example.com/a_(b)".to_string());
+                    a.bold = Some(true)
+                }),
+                styled(" or ", |_| {}),
+                styled("x*y", |a| a.code = Some(true)),
+            ]
+        );
+        assert_eq!(
+            ops("<https://example.com>"),
+            vec![styled("https://example.com", |a| a.link =
+                Some("https://example.com".to_string()))]
+        );
+        assert!(
+            matches!(&ops("![cat](cat.png)")[0].0, Insert::Embed(embed) if embed["image"] == "cat.png")
+        );
+        assert_eq!(
+            ops("[not a link] `open"),
+            vec![styled("[not a link] `open", |_| {})]
+        );
+    }
+
+    #[test]
+    fn unsupported_blocks_stay_as_text() {
+        let markdown = "| a | b |\n|---|---|\n\n***\n<div>hi</div>\n";
+
+        let text = parse(markdown)
+            .lines()
+            .iter()
+            .map(|line| {
+                line.inserts
+                    .iter()
+                    .map(|(insert, _)| match insert {
+                        Insert::Text(text) => text.clone(),
+                        Insert::Embed(_) => String::new(),
+                    })
+                    .collect::<String>()
+            })
+            .collect::<Vec<String>>();
+
+        assert_eq!(
+            text,
+            vec!["| a | b |", "|---|---|", "", "***", "<div>hi</div>"]
+        );
+    }
+
+    #[test]
+    fn lists_nest_by_indentation() {
+        let lines = parse("* a\n    1) b\n       + [X] c\n- d\n  more\n").lines();
+
+        let formats = lines
+            .iter()
+            .map(|line| (line.attributes.list, line.attributes.indent))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            formats,
+            vec![
+                (Some(ListKind::Bullet), None),
+                (Some(ListKind::Ordered), Some(1)),
+                (Some(ListKind::Checked), Some(2)),
+                (Some(ListKind::Bullet), None),
+                (None, None),
+            ]
+        );
+        assert_eq!(lines[4].inserts[0].0, Insert::Text("more".to_string()));
+    }
+
+    #[test]
+    fn empty_markdown_is_an_empty_note() {
+        assert_eq!(to_contents("").unwrap(), crate::model::EMPTY_CONTENTS);
+    }
+
+    #[test]
+    fn whitespace_goes_outside_of_emphasis() {
+        let line = Line {
+            inserts: vec![
+                (
+                    Insert::Text("very ".to_string()),
+                    Attributes {
+                        bold: Some(true),
+                        ..Default::default()
+                    },
+                ),
+                (Insert::Text("important".to_string()), Attributes::default()),
+            ],
+            attributes: Attributes::default(),
+        };
+
+        assert_eq!(inline(&line), "**very** important");
+    }
+
+    #[test]
+    fn nested_emphasis_shares_markers() {
+        let line = Line {
+            inserts: vec![
+                (
+                    Insert::Text("a".to_string()),
+                    Attributes {
+                        bold: Some(true),
+                        ..Default::default()
+                    },
+                ),
+                (
+                    Insert::Text("b".to_string()),
+                    Attributes {
+                        bold: Some(true),
+                        italic: Some(true),
+                        ..Default::default()
+                    },
+                ),
+            ],
+            attributes: Attributes::default(),
+        };
+
+        assert_eq!(inline(&line), "**a*b***");
+    }
+
+    #[test]
+    fn code_spans_outgrow_their_backticks() {
+        let code = Attributes {
+            code: Some(true),
+            ..Default::default()
+        };
+
+        assert_eq!(inline(&text("a`b", code.clone())), "``a`b``");
+        assert_eq!(inline(&text("`", code)), "`` ` ``");
+    }
+
+    #[test]
+    fn markdown_characters_are_escaped() {
+        assert_eq!(
+            escape("2*3 [x] snake_case _x_"),
+            "2\\*3 \\[x\\] snake_case \\_x\\_"
+        );
+        assert_eq!(escape_line_start("# not a title"), "\\# not a title");
+        assert_eq!(escape_line_start("1. not a list"), "1\\. not a list");
+        assert_eq!(escape_line_start("plain"), "plain");
+    }
+}
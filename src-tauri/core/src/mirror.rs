@@ -0,0 +1,513 @@
+use crate::error::Result;
+use crate::folder::{parse_front_matter, render_front_matter};
+use crate::markdown;
+use crate::model::Note;
+use crate::storage::write_atomic;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// Remembers what was last mirrored to the export folder, so edits made while the app was closed are picked up.
+pub const MIRROR_FILE: &str = "mirror.json";
+
+// longer titles are cut off in file names
+const MAX_NAME_LEN: usize = 80;
+const UNTITLED: &str = "Untitled";
+
+/// Something that changed in the export folder outside the app.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MirrorChange {
+    /// the file was edited, the note gets its contents and color
+    Edited {
+        id: Uuid,
+        contents: String,
+        color: Option<String>,
+    },
+    /// the file was deleted, the note gets closed
+    Deleted(Uuid),
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+struct MirroredFile {
+    /// relative to the export folder, it can be renamed or moved into a subfolder
+    path: PathBuf,
+    /// the title the file was named after, the file is only renamed when it changes
+    title: String,
+    /// of the file as it was last written or read
+    file_hash: u64,
+    /// of the note's contents and color the file matches
+    note_hash: u64,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct MirrorState {
+    export_dir: PathBuf,
+    files: HashMap<Uuid, MirroredFile>,
+}
+
+/// Keeps a `<title>.md` file for every open note in the export folder, like an Obsidian vault or a git repo,
+/// with the note's id and color in its front matter. The files are matched to their notes by that id, so they
+/// can be renamed and moved around. Files without an id are never touched.
+#[derive(Debug)]
+pub struct Mirror {
+    export_dir: PathBuf,
+    state_path: PathBuf,
+    files: HashMap<Uuid, MirroredFile>,
+}
+
+impl Mirror {
+    pub fn open(export_dir: &Path, app_data_dir: &Path) -> Result<Mirror> {
+        fs::create_dir_all(export_dir)?;
+
+        let state_path = app_data_dir.join(MIRROR_FILE);
+        let state = match fs::read_to_string(&state_path) {
+            Ok(text) => serde_json::from_str::<MirrorState>(&text)?,
+            Err(_) => MirrorState::default(),
+        };
+
+        // what was mirrored to another folder doesn't say anything about this one
+        let mut files = if state.export_dir == export_dir {
+            state.files
+        } else {
+            HashMap::new()
+        };
+
+        // files that have an id but aren't known, like when the state was lost, are taken over instead of duplicated
+        for (path, text) in markdown_files(export_dir)? {
+            if let Some(id) = file_id(&text) {
+                files.entry(id).or_insert_with(|| MirroredFile {
+                    path,
+                    file_hash: hash(text.as_bytes()),
+                    ..Default::default()
+                });
+            }
+        }
+
+        Ok(Mirror {
+            export_dir: export_dir.to_path_buf(),
+            state_path,
+            files,
+        })
+    }
+
+    pub fn export_dir(&self) -> &Path {
+        &self.export_dir
+    }
+
+    /// Writes the files of the notes that changed since they were last mirrored, renames the ones whose title changed,
+    /// and deletes the files of notes that are gone.
+    pub fn write(&mut self, notes: &[Note]) -> Result<()> {
+        fs::create_dir_all(&self.export_dir)?;
+
+        let mut assigned = HashSet::new();
+
+        for note in notes {
+            let title = note.title();
+            let note_hash = note_hash(note);
+            let existing = self.files.get(&note.id).cloned();
+
+            let path = match &existing {
+                Some(file) if file.title == title && self.export_dir.join(&file.path).exists() => {
+                    file.path.clone()
+                }
+                _ => self.free_path(&title, existing.as_ref(), &assigned),
+            };
+            assigned.insert(path.clone());
+
+            if let Some(file) = &existing {
+                let old_path = self.export_dir.join(&file.path);
+
+                if file.path == path && file.note_hash == note_hash && old_path.exists() {
+                    continue;
+                }
+
+                if file.path != path && old_path.exists() {
+                    log::info!("renaming {} to {}", file.path.display(), path.display());
+                    fs::rename(&old_path, self.export_dir.join(&path))?;
+                }
+            }
+
+            let text = render(note)?;
+            write_atomic(&self.export_dir.join(&path), text.as_bytes())?;
+
+            self.files.insert(
+                note.id,
+                MirroredFile {
+                    path,
+                    title,
+                    file_hash: hash(text.as_bytes()),
+                    note_hash,
+                },
+            );
+        }
+
+        let open = notes.iter().map(|note| note.id).collect::<HashSet<_>>();
+        let gone = self
+            .files
+            .keys()
+            .filter(|id| !open.contains(id))
+            .copied()
+            .collect::<Vec<_>>();
+
+        for id in gone {
+            if let Some(file) = self.files.remove(&id) {
+                let path = self.export_dir.join(&file.path);
+
+                // only if it is still the file of that note
+                let ours = fs::read_to_string(&path)
+                    .ok()
+                    .and_then(|text| file_id(&text))
+                    == Some(id);
+                if ours {
+                    fs::remove_file(path)?;
+                }
+            }
+        }
+
+        self.save_state()
+    }
+
+    /// Looks for files that were edited, renamed or deleted outside the app since they were last written or read.
+    /// Renames are only remembered, edits and deletions are returned for the app to apply to its notes.
+    pub fn changes(&mut self) -> Result<Vec<MirrorChange>> {
+        // an unmounted drive or a folder that was moved away isn't the same as every file being deleted
+        if !self.export_dir.is_dir() {
+            return Ok(Vec::new());
+        }
+
+        let mut found = HashMap::new();
+        for (path, text) in markdown_files(&self.export_dir)? {
+            if let Some(id) = file_id(&text) {
+                found.entry(id).or_insert((path, text));
+            }
+        }
+
+        let mut changes = Vec::new();
+        let mut ids = self.files.keys().copied().collect::<Vec<_>>();
+        ids.sort();
+
+        for id in ids {
+            let (path, text) = match found.remove(&id) {
+                Some(found) => found,
+                None => {
+                    log::info!("the export file of note {id} was deleted");
+                    self.files.remove(&id);
+                    changes.push(MirrorChange::Deleted(id));
+                    continue;
+                }
+            };
+
+            let file = self.files.entry(id).or_default();
+            file.path = path;
+
+            let file_hash = hash(text.as_bytes());
+            if file.file_hash == file_hash {
+                continue;
+            }
+
+            let (meta, body) = match parse_front_matter(&text) {
+                Ok(parsed) => parsed,
+                Err(e) => {
+                    log::warn!("could not read {}: {e}", file.path.display());
+                    continue;
+                }
+            };
+
+            let contents = markdown::to_contents(body)?;
+            let color = meta.get("color").and_then(Value::as_str).map(String::from);
+
+            file.file_hash = file_hash;
+            file.note_hash = hash_parts(color.as_deref().unwrap_or_default(), &contents);
+            file.title = Note {
+                contents: contents.clone(),
+                ..Default::default()
+            }
+            .title();
+
+            changes.push(MirrorChange::Edited {
+                id,
+                contents,
+                color,
+            });
+        }
+
+        self.save_state()?;
+
+        Ok(changes)
+    }
+
+    // a file name for `title` that no other file in the export folder has
+    fn free_path(
+        &self,
+        title: &str,
+        current: Option<&MirroredFile>,
+        assigned: &HashSet<PathBuf>,
+    ) -> PathBuf {
+        let name = file_name(title);
+        // a file moved into a subfolder stays there
+        let dir = current
+            .and_then(|file| file.path.parent())
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+
+        (1..)
+            .map(|n| match n {
+                1 => dir.join(format!("{name}.md")),
+                n => dir.join(format!("{name} {n}.md")),
+            })
+            .find(|path| {
+                let is_current = current.map_or(false, |file| file.path == *path);
+                !assigned.contains(path) && (is_current || !self.export_dir.join(path).exists())
+            })
+            .unwrap_or_default()
+    }
+
+    fn save_state(&self) -> Result<()> {
+        let state = MirrorState {
+            export_dir: self.export_dir.clone(),
+            files: self.files.clone(),
+        };
+
+        write_atomic(&self.state_path, serde_json::to_string(&state)?.as_bytes())?;
+
+        Ok(())
+    }
+}
+
+fn render(note: &Note) -> Result<String> {
+    let mut meta = Map::new();
+    meta.insert("id".to_string(), Value::String(note.id.to_string()));
+    meta.insert("color".to_string(), Value::String(note.color.clone()));
+
+    Ok(render_front_matter(
+        &meta,
+        &markdown::from_contents(&note.contents)?,
+    ))
+}
+
+fn file_id(text: &str) -> Option<Uuid> {
+    let (meta, _) = parse_front_matter(text).ok()?;
+
+    Uuid::parse_str(meta.get("id")?.as_str()?).ok()
+}
+
+/// A title made safe to use as a file name on every platform.
+pub(crate) fn file_name(title: &str) -> String {
+    let name = title
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => ' ',
+            c if c.is_control() => ' ',
+            c => c,
+        })
+        .take(MAX_NAME_LEN)
+        .collect::<String>();
+
+    // leading dots hide files, trailing ones and spaces upset windows
+    let name = name
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .trim_matches('.')
+        .trim()
+        .to_string();
+
+    if name.is_empty() {
+        UNTITLED.to_string()
+    } else {
+        name
+    }
+}
+
+/// Every `.md` file in `dir` and its subfolders with its text, relative to `dir`. Hidden folders like `.git`
+/// and `.obsidian` are skipped.
+fn markdown_files(dir: &Path) -> Result<Vec<(PathBuf, String)>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![PathBuf::new()];
+
+    while let Some(relative) = dirs.pop() {
+        for entry in fs::read_dir(dir.join(&relative))? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let path = relative.join(&name);
+
+            if name.to_string_lossy().starts_with('.') {
+                continue;
+            }
+
+            if entry.file_type()?.is_dir() {
+                dirs.push(path);
+            } else if path
+                .extension()
+                .map_or(false, |extension| extension == "md")
+            {
+                // a file that can't be read as text isn't one of ours
+                if let Ok(text) = fs::read_to_string(dir.join(&path)) {
+                    files.push((path, text));
+                }
+            }
+        }
+    }
+
+    files.sort();
+
+    Ok(files)
+}
+
+fn note_hash(note: &Note) -> u64 {
+    hash_parts(&note.color, &note.contents)
+}
+
+fn hash_parts(color: &str, contents: &str) -> u64 {
+    hash(format!("{color}\0{contents}").as_bytes())
+}
+
+/// FNV-1a, it only has to stay the same between runs.
+fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(n: u128, text: &str) -> Note {
+        Note {
+            id: Uuid::from_u128(n),
+            color: "#fff9b1".to_string(),
+            contents: format!(r#"{{"ops":[{{"insert":"{text}\n"}}]}}"#),
+            width: 300,
+            height: 250,
+            ..Default::default()
+        }
+    }
+
+    fn setup() -> (tempfile::TempDir, Mirror) {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("app")).unwrap();
+        let mirror = Mirror::open(&dir.path().join("vault"), &dir.path().join("app")).unwrap();
+
+        (dir, mirror)
+    }
+
+    #[test]
+    fn notes_are_written_as_title_md() {
+        let (dir, mut mirror) = setup();
+        let vault = dir.path().join("vault");
+
+        mirror
+            .write(&[note(1, "Groceries"), note(2, "Groceries"), note(3, "")])
+            .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(vault.join("Groceries.md")).unwrap(),
+            "---\nid: 00000000-0000-0000-0000-000000000001\ncolor: \"#fff9b1\"\n---\nGroceries\n"
+        );
+        assert!(vault.join("Groceries 2.md").exists());
+        assert!(vault.join("Untitled.md").exists());
+        assert!(mirror.changes().unwrap().is_empty());
+    }
+
+    #[test]
+    fn title_changes_rename_the_file_and_closed_notes_are_deleted() {
+        let (dir, mut mirror) = setup();
+        let vault = dir.path().join("vault");
+
+        mirror
+            .write(&[note(1, "Groceries"), note(2, "Todo")])
+            .unwrap();
+        mirror.write(&[note(1, "Shopping")]).unwrap();
+
+        assert!(!vault.join("Groceries.md").exists());
+        assert!(vault.join("Shopping.md").exists());
+        assert!(!vault.join("Todo.md").exists());
+    }
+
+    #[test]
+    fn outside_edits_renames_and_deletions_come_back() {
+        let (dir, mut mirror) = setup();
+        let vault = dir.path().join("vault");
+
+        mirror
+            .write(&[note(1, "Groceries"), note(2, "Todo")])
+            .unwrap();
+
+        let text = fs::read_to_string(vault.join("Groceries.md")).unwrap();
+        fs::create_dir(vault.join("lists")).unwrap();
+        fs::write(
+            vault.join("lists/Food.md"),
+            text.replace("#fff9b1", "#ff7eb9")
+                .replace("Groceries", "- milk"),
+        )
+        .unwrap();
+        fs::remove_file(vault.join("Groceries.md")).unwrap();
+        fs::remove_file(vault.join("Todo.md")).unwrap();
+
+        let changes = mirror.changes().unwrap();
+        assert_eq!(changes.len(), 2);
+        assert!(matches!(
+            &changes[0],
+            MirrorChange::Edited { id, color: Some(color), contents }
+                if *id == Uuid::from_u128(1) && color == "#ff7eb9" && contents.contains(r#""list":"bullet""#)
+        ));
+        assert_eq!(changes[1], MirrorChange::Deleted(Uuid::from_u128(2)));
+
+        // the renamed file keeps its name, and isn't rewritten just to normalize it
+        let edited = Note {
+            color: "#ff7eb9".to_string(),
+            contents: match &changes[0] {
+                MirrorChange::Edited { contents, .. } => contents.clone(),
+                _ => unreachable!(),
+            },
+            ..note(1, "")
+        };
+        let before = fs::read_to_string(vault.join("lists/Food.md")).unwrap();
+        mirror.write(&[edited]).unwrap();
+        assert_eq!(
+            fs::read_to_string(vault.join("lists/Food.md")).unwrap(),
+            before
+        );
+    }
+
+    #[test]
+    fn state_survives_a_restart_and_other_files_are_left_alone() {
+        let (dir, mut mirror) = setup();
+        let vault = dir.path().join("vault");
+        fs::write(vault.join("Groceries.md"), "my own notes\n").unwrap();
+
+        mirror.write(&[note(1, "Groceries")]).unwrap();
+        assert_eq!(
+            fs::read_to_string(vault.join("Groceries.md")).unwrap(),
+            "my own notes\n"
+        );
+
+        let text = fs::read_to_string(vault.join("Groceries 2.md")).unwrap();
+        fs::write(
+            vault.join("Groceries 2.md"),
+            text.replace("Groceries", "Groceries\n\nmilk"),
+        )
+        .unwrap();
+
+        let mut mirror = Mirror::open(&vault, &dir.path().join("app")).unwrap();
+        assert!(matches!(
+            mirror.changes().unwrap().as_slice(),
+            [MirrorChange::Edited { .. }]
+        ));
+
+        mirror.write(&[]).unwrap();
+        assert!(!vault.join("Groceries 2.md").exists());
+        assert!(vault.join("Groceries.md").exists());
+    }
+
+    #[test]
+    fn titles_become_safe_file_names() {
+        assert_eq!(file_name("a/b: c?"), "a b c");
+        assert_eq!(file_name("...hidden"), "hidden");
+        assert_eq!(file_name("   "), UNTITLED);
+    }
+}
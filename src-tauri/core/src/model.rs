@@ -0,0 +1,218 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use uuid::Uuid;
+
+const LABEL_PREFIX: &str = "note-";
+
+/// titles longer than this many characters are cut in menus
+const MENU_TITLE_LENGTH: usize = 40;
+
+/// What `quill.getContents()` gives for an empty editor.
+pub const EMPTY_CONTENTS: &str = r#"{"ops":[{"insert":"\n"}]}"#;
+
+/// Size of the window of a new note.
+pub const DEFAULT_WIDTH: u32 = 300;
+pub const DEFAULT_HEIGHT: u32 = 250;
+
+/// A single sticky note, as saved to disk and sent to the window in the `init` event.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct Note {
+    /// stays the same across restarts, the window label is derived from it
+    pub id: Uuid,
+    pub color: String,
+    /// `JSON.stringify(quill.getContents())` from the editor
+    pub contents: String,
+    pub x: u32,
+    pub y: u32,
+    pub height: u32,
+    pub width: u32,
+    /// fields written by a newer version of the app, kept so that saving with this version doesn't drop them
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// Part of a note that changed in the editor, sent with the `update_note` command.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct NoteUpdate {
+    pub contents: Option<String>,
+    pub color: Option<String>,
+    pub x: Option<u32>,
+    pub y: Option<u32>,
+    pub height: Option<u32>,
+    pub width: Option<u32>,
+}
+
+impl Note {
+    pub fn label(&self) -> String {
+        window_label(self.id)
+    }
+
+    /// Applies the fields that are set in `update`, returns whether anything actually changed.
+    pub fn apply(&mut self, update: NoteUpdate) -> bool {
+        let before = self.clone();
+
+        if let Some(contents) = update.contents {
+            self.contents = contents;
+        }
+        if let Some(color) = update.color {
+            self.color = color;
+        }
+        if let Some(x) = update.x {
+            self.x = x;
+        }
+        if let Some(y) = update.y {
+            self.y = y;
+        }
+        if let Some(height) = update.height {
+            self.height = height;
+        }
+        if let Some(width) = update.width {
+            self.width = width;
+        }
+
+        *self != before
+    }
+
+    /// The text of the note without any formatting, embeds like images are left out.
+    pub fn plain_text(&self) -> String {
+        plain_text(&self.contents)
+    }
+
+    /// The first line of the note that has any text in it.
+    pub fn title(&self) -> String {
+        self.plain_text()
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    /// The title cut down to fit in a menu, "Untitled" for a note without text.
+    pub fn menu_title(&self) -> String {
+        let title = self.title();
+
+        if title.is_empty() {
+            "Untitled".to_string()
+        } else if title.chars().count() > MENU_TITLE_LENGTH {
+            let cut = title
+                .chars()
+                .take(MENU_TITLE_LENGTH - 1)
+                .collect::<String>();
+            format!("{}…", cut.trim_end())
+        } else {
+            title
+        }
+    }
+}
+
+/// Joins the text inserts of a quill delta, anything that doesn't parse as one gives an empty string.
+pub fn plain_text(contents: &str) -> String {
+    let delta = match serde_json::from_str::<Value>(contents) {
+        Ok(delta) => delta,
+        Err(_) => return String::new(),
+    };
+
+    delta["ops"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|op| op["insert"].as_str())
+        .collect()
+}
+
+/// Label of the window showing the note with this id.
+pub fn window_label(id: Uuid) -> String {
+    format!("{LABEL_PREFIX}{id}")
+}
+
+/// Id of the note shown in the window with this label, `None` for windows that don't show a note.
+pub fn note_id(label: &str) -> Option<Uuid> {
+    label
+        .strip_prefix(LABEL_PREFIX)
+        .and_then(|id| Uuid::parse_str(id).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn labels_map_back_to_ids() {
+        let id = Uuid::new_v4();
+
+        assert_eq!(note_id(&window_label(id)), Some(id));
+    }
+
+    #[test]
+    fn apply_only_touches_the_fields_that_are_set() {
+        let mut note = Note {
+            color: "#fff9b1".to_string(),
+            x: 10,
+            ..Default::default()
+        };
+
+        let changed = note.apply(NoteUpdate {
+            x: Some(20),
+            ..Default::default()
+        });
+
+        assert!(changed);
+        assert_eq!(note.x, 20);
+        assert_eq!(note.color, "#fff9b1");
+    }
+
+    #[test]
+    fn apply_reports_no_change_for_the_same_values() {
+        let mut note = Note {
+            color: "#fff9b1".to_string(),
+            ..Default::default()
+        };
+
+        assert!(!note.apply(NoteUpdate {
+            color: Some("#fff9b1".to_string()),
+            ..Default::default()
+        }));
+        assert!(!note.apply(NoteUpdate::default()));
+    }
+
+    #[test]
+    fn title_is_the_first_line_with_text() {
+        let note = Note {
+            contents: r#"{"ops":[{"insert":"\n  "},{"insert":{"image":"x.png"}},{"insert":"Groceries","attributes":{"bold":true}},{"insert":"\nmilk\n"}]}"#.to_string(),
+            ..Default::default()
+        };
+
+        assert_eq!(note.plain_text(), "\n  Groceries\nmilk\n");
+        assert_eq!(note.title(), "Groceries");
+    }
+
+    #[test]
+    fn menu_titles_are_short() {
+        let note = |text: &str| Note {
+            contents: serde_json::json!({ "ops": [{ "insert": format!("{text}\n") }] }).to_string(),
+            ..Default::default()
+        };
+
+        assert_eq!(note("Groceries").menu_title(), "Groceries");
+        assert_eq!(note("").menu_title(), "Untitled");
+        assert_eq!(
+            note("Things to bring up at the next planning meeting").menu_title(),
+            "Things to bring up at the next planning…"
+        );
+    }
+
+    #[test]
+    fn broken_contents_have_no_text() {
+        assert_eq!(plain_text("not json"), "");
+        assert_eq!(plain_text(EMPTY_CONTENTS), "\n");
+    }
+
+    #[test]
+    fn other_windows_have_no_id() {
+        assert_eq!(note_id("main"), None);
+        assert_eq!(note_id("note-"), None);
+        assert_eq!(note_id("new_sticky_window_1"), None);
+    }
+}
@@ -0,0 +1,90 @@
+use crate::error::Result;
+use std::fs;
+use std::path::Path;
+
+pub const COLORS_FILE: &str = "colors.json";
+
+pub const DEFAULT_COLORS: [&str; 7] = [
+    "#fff9b1", "#81B7DD", "#65A65B", "#AAD2CA", "#98C260", "#E1A1B1", "#B98CB3",
+];
+
+/// The saved color palette, or the default one if no colors have been added yet.
+pub fn get_colors(app_data_dir: &Path) -> Result<Vec<String>> {
+    let path_buf = app_data_dir.join(COLORS_FILE);
+
+    let file_path = path_buf.as_path();
+
+    if file_path.exists() {
+        let file_content = fs::read_to_string(file_path)?;
+        Ok(serde_json::from_str(&file_content)?)
+    } else {
+        Ok(default_colors())
+    }
+}
+
+pub fn default_colors() -> Vec<String> {
+    DEFAULT_COLORS.iter().map(|s| s.to_string()).collect()
+}
+
+/// Appends `color` to the palette, unless it is already in there.
+pub fn add_color(color: &str, app_data_dir: &Path) -> Result<()> {
+    let path_buf = app_data_dir.join(COLORS_FILE);
+
+    let mut colors = get_colors(app_data_dir)?;
+
+    if !colors.iter().any(|c| *c == color) {
+        colors.push(color.to_string());
+        fs::write(path_buf.as_path(), serde_json::to_string(&colors)?)?;
+    }
+
+    Ok(())
+}
+
+/// Resets the palette back to the default colors.
+pub fn clear_colors(app_data_dir: &Path) -> Result<()> {
+    let path_buf = app_data_dir.join(COLORS_FILE);
+
+    let file_path = path_buf.as_path();
+
+    if file_path.exists() {
+        fs::remove_file(file_path)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_without_a_save_file() {
+        let dir = tempfile::tempdir().unwrap();
+
+        assert_eq!(get_colors(dir.path()).unwrap(), DEFAULT_COLORS.to_vec());
+    }
+
+    #[test]
+    fn added_colors_are_appended_once() {
+        let dir = tempfile::tempdir().unwrap();
+
+        add_color("#123456", dir.path()).unwrap();
+        add_color("#123456", dir.path()).unwrap();
+        add_color(DEFAULT_COLORS[0], dir.path()).unwrap();
+
+        let colors = get_colors(dir.path()).unwrap();
+        assert_eq!(colors.len(), DEFAULT_COLORS.len() + 1);
+        assert_eq!(colors.last().unwrap(), "#123456");
+    }
+
+    #[test]
+    fn clearing_restores_the_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+
+        add_color("#123456", dir.path()).unwrap();
+        clear_colors(dir.path()).unwrap();
+        clear_colors(dir.path()).unwrap();
+
+        assert_eq!(get_colors(dir.path()).unwrap(), DEFAULT_COLORS.to_vec());
+    }
+}
@@ -0,0 +1,333 @@
+use crate::format::SaveFile;
+use crate::model::{Note, NoteUpdate};
+use crate::trash::{TrashPolicy, TrashedNote};
+use chrono::{DateTime, Utc};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// How long the notes have to stay unchanged before they get written to disk,
+/// and how long a write can be put off while they keep changing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Debounce {
+    pub quiet: Duration,
+    pub max_wait: Duration,
+}
+
+impl Default for Debounce {
+    fn default() -> Self {
+        Debounce {
+            quiet: Duration::from_millis(500),
+            max_wait: Duration::from_secs(5),
+        }
+    }
+}
+
+/// The current state of every open note, kept up to date by the windows, along with whether it has been saved yet.
+#[derive(Debug, Default)]
+pub struct NoteRegistry {
+    notes: Vec<Note>,
+    // oldest first
+    trash: Vec<TrashedNote>,
+    // first and latest change since the last save
+    dirty_since: Option<Instant>,
+    changed_at: Option<Instant>,
+}
+
+impl NoteRegistry {
+    /// A registry holding notes that were just read from disk, so there is nothing to save yet.
+    pub fn new(notes: Vec<Note>, trash: Vec<TrashedNote>) -> NoteRegistry {
+        NoteRegistry {
+            notes,
+            trash,
+            ..Default::default()
+        }
+    }
+
+    pub fn notes(&self) -> &[Note] {
+        &self.notes
+    }
+
+    pub fn trash(&self) -> &[TrashedNote] {
+        &self.trash
+    }
+
+    pub fn get(&self, id: Uuid) -> Option<&Note> {
+        self.notes.iter().find(|note| note.id == id)
+    }
+
+    /// Adds a note, or replaces the note with the same id.
+    pub fn insert(&mut self, note: Note) {
+        match self
+            .notes
+            .iter_mut()
+            .find(|existing| existing.id == note.id)
+        {
+            Some(existing) => *existing = note,
+            None => self.notes.push(note),
+        }
+
+        self.mark_dirty();
+    }
+
+    /// Applies `update` to the note with this id. Returns `false` if there is no such note,
+    /// and only marks the registry dirty if the note actually changed.
+    pub fn update(&mut self, id: Uuid, update: NoteUpdate) -> bool {
+        let changed = match self.notes.iter_mut().find(|note| note.id == id) {
+            Some(note) => note.apply(update),
+            None => return false,
+        };
+
+        if changed {
+            self.mark_dirty();
+        }
+
+        true
+    }
+
+    pub fn remove(&mut self, id: Uuid) -> Option<Note> {
+        let index = self.notes.iter().position(|note| note.id == id)?;
+
+        self.mark_dirty();
+
+        Some(self.notes.remove(index))
+    }
+
+    /// Moves the note with this id to the trash, returns `false` if there is no such note.
+    pub fn close(&mut self, id: Uuid, now: DateTime<Utc>) -> bool {
+        match self.remove(id) {
+            Some(note) => {
+                self.trash.push(TrashedNote {
+                    closed_at: now,
+                    note,
+                });
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Takes the most recently closed note out of the trash and opens it again.
+    pub fn reopen_last(&mut self) -> Option<Note> {
+        let note = self.trash.pop()?.note;
+
+        self.insert(note.clone());
+
+        Some(note)
+    }
+
+    /// Replaces the whole trash, like when it was changed on disk.
+    pub fn set_trash(&mut self, trash: Vec<TrashedNote>) {
+        self.trash = trash;
+        self.mark_dirty();
+    }
+
+    /// Deletes the notes that have been in the trash for longer than `policy` keeps them, and returns them.
+    pub fn purge_trash(&mut self, now: DateTime<Utc>, policy: &TrashPolicy) -> Vec<TrashedNote> {
+        let (purged, kept) = self
+            .trash
+            .drain(..)
+            .partition(|trashed| policy.expired(trashed.closed_at, now));
+
+        self.trash = kept;
+
+        if !purged.is_empty() {
+            self.mark_dirty();
+        }
+
+        purged
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty_since.is_some()
+    }
+
+    pub fn mark_dirty(&mut self) {
+        let now = Instant::now();
+
+        self.dirty_since.get_or_insert(now);
+        self.changed_at = Some(now);
+    }
+
+    /// If there are unsaved changes and `debounce` says it is time to write them, marks the registry clean
+    /// and returns what to save. Call [`NoteRegistry::mark_dirty`] if saving it fails.
+    pub fn take_due(&mut self, now: Instant, debounce: Debounce) -> Option<SaveFile> {
+        let dirty_since = self.dirty_since?;
+        let changed_at = self.changed_at.unwrap_or(dirty_since);
+
+        let quiet = now.saturating_duration_since(changed_at) >= debounce.quiet;
+        let waited_too_long = now.saturating_duration_since(dirty_since) >= debounce.max_wait;
+
+        if quiet || waited_too_long {
+            self.take()
+        } else {
+            None
+        }
+    }
+
+    /// Marks the registry clean and returns what to save, if there are unsaved changes.
+    pub fn take(&mut self) -> Option<SaveFile> {
+        self.dirty_since?;
+
+        self.dirty_since = None;
+        self.changed_at = None;
+
+        Some(self.save_file())
+    }
+
+    /// Everything the registry holds, as it should be written to disk.
+    pub fn save_file(&self) -> SaveFile {
+        SaveFile {
+            trash: self.trash.clone(),
+            ..SaveFile::new(self.notes.clone())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note() -> Note {
+        Note {
+            id: Uuid::new_v4(),
+            color: "#fff9b1".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn contents(contents: &str) -> NoteUpdate {
+        NoteUpdate {
+            contents: Some(contents.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn loaded_notes_are_clean() {
+        let mut registry = NoteRegistry::new(vec![note()], Vec::new());
+
+        assert!(!registry.is_dirty());
+        assert_eq!(
+            registry.take_due(
+                Instant::now() + Duration::from_secs(60),
+                Debounce::default()
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn updates_that_change_nothing_stay_clean() {
+        let note = note();
+        let mut registry = NoteRegistry::new(vec![note.clone()], Vec::new());
+
+        assert!(registry.update(
+            note.id,
+            NoteUpdate {
+                color: Some(note.color.clone()),
+                ..Default::default()
+            }
+        ));
+
+        assert!(!registry.is_dirty());
+    }
+
+    #[test]
+    fn unknown_notes_are_not_updated() {
+        let mut registry = NoteRegistry::new(vec![note()], Vec::new());
+
+        assert!(!registry.update(Uuid::new_v4(), contents("hi")));
+        assert!(!registry.is_dirty());
+    }
+
+    #[test]
+    fn writes_wait_for_a_quiet_period() {
+        let note = note();
+        let mut registry = NoteRegistry::new(vec![note.clone()], Vec::new());
+        let debounce = Debounce::default();
+
+        registry.update(note.id, contents("hi"));
+
+        assert_eq!(registry.take_due(Instant::now(), debounce), None);
+
+        let saved = registry
+            .take_due(Instant::now() + debounce.quiet, debounce)
+            .unwrap();
+        assert_eq!(saved.notes[0].contents, "hi");
+        assert!(!registry.is_dirty());
+    }
+
+    #[test]
+    fn constant_changes_still_get_written_eventually() {
+        let note = note();
+        let mut registry = NoteRegistry::new(vec![note.clone()], Vec::new());
+        let debounce = Debounce {
+            quiet: Duration::from_secs(60),
+            max_wait: Duration::from_millis(10),
+        };
+
+        registry.update(note.id, contents("a"));
+        std::thread::sleep(Duration::from_millis(20));
+        registry.update(note.id, contents("ab"));
+
+        assert!(registry.take_due(Instant::now(), debounce).is_some());
+    }
+
+    #[test]
+    fn insert_replaces_and_remove_drops() {
+        let mut note = note();
+        let mut registry = NoteRegistry::default();
+
+        registry.insert(note.clone());
+        note.color = "#81B7DD".to_string();
+        registry.insert(note.clone());
+
+        assert_eq!(registry.notes(), &[note.clone()]);
+
+        registry.take();
+        assert_eq!(registry.remove(note.id), Some(note));
+        assert!(registry.is_dirty());
+        assert!(registry.notes().is_empty());
+    }
+
+    #[test]
+    fn closed_notes_go_to_the_trash_and_come_back() {
+        let (first, second) = (note(), note());
+        let mut registry = NoteRegistry::new(vec![first.clone(), second.clone()], Vec::new());
+
+        assert!(registry.close(first.id, Utc::now()));
+        assert!(registry.close(second.id, Utc::now()));
+        assert!(!registry.close(second.id, Utc::now()));
+
+        assert!(registry.notes().is_empty());
+        assert_eq!(registry.take().unwrap().trash.len(), 2);
+
+        assert_eq!(registry.reopen_last(), Some(second.clone()));
+        assert_eq!(registry.notes(), &[second]);
+        assert_eq!(registry.trash().len(), 1);
+        assert!(registry.is_dirty());
+    }
+
+    #[test]
+    fn old_trash_is_purged() {
+        let now = Utc::now();
+        let (old, recent) = (note(), note());
+        let mut registry = NoteRegistry::new(
+            Vec::new(),
+            vec![
+                TrashedNote {
+                    closed_at: now - chrono::Duration::days(40),
+                    note: old,
+                },
+                TrashedNote {
+                    closed_at: now,
+                    note: recent.clone(),
+                },
+            ],
+        );
+
+        assert_eq!(registry.purge_trash(now, &TrashPolicy::default()).len(), 1);
+        assert_eq!(registry.trash()[0].note, recent);
+        assert!(registry.is_dirty());
+    }
+}
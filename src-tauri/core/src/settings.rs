@@ -0,0 +1,71 @@
+use crate::backup::BackupPolicy;
+use crate::capture::CapturePolicy;
+use crate::error::Result;
+use crate::history::HistoryPolicy;
+use crate::storage::Backend;
+use crate::trash::TrashPolicy;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const SETTINGS_FILE: &str = "settings.json";
+
+/// User settings from `settings.json` in the app data dir. Anything left out of the file keeps its default.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Settings {
+    pub storage: Backend,
+    pub backups: BackupPolicy,
+    pub trash: TrashPolicy,
+    pub history: HistoryPolicy,
+    /// a folder every open note is mirrored to as `<title>.md`, edits made there come back to the notes
+    pub export_dir: Option<PathBuf>,
+    /// the command Window > Open in External Editor runs with the note's file, `$VISUAL` or `$EDITOR` when unset
+    pub editor: Option<String>,
+    /// the localhost port of the HTTP API, which is off when unset
+    pub http_port: Option<u16>,
+    /// the quick capture shortcut and the inbox note it adds to
+    pub capture: CapturePolicy,
+}
+
+impl Settings {
+    pub fn load(app_data_dir: &Path) -> Result<Settings> {
+        let path_buf = app_data_dir.join(SETTINGS_FILE);
+
+        let file_path = path_buf.as_path();
+
+        if file_path.exists() {
+            let file_content = fs::read_to_string(file_path)?;
+            Ok(serde_json::from_str(&file_content)?)
+        } else {
+            Ok(Settings::default())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_without_a_settings_file() {
+        let dir = tempfile::tempdir().unwrap();
+
+        assert_eq!(Settings::load(dir.path()).unwrap(), Settings::default());
+    }
+
+    #[test]
+    fn missing_keys_keep_their_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join(SETTINGS_FILE),
+            r#"{ "backups": { "hourly": 3 } }"#,
+        )
+        .unwrap();
+
+        let settings = Settings::load(dir.path()).unwrap();
+
+        assert_eq!(settings.backups.hourly, 3);
+        assert_eq!(settings.backups.daily, BackupPolicy::default().daily);
+    }
+}
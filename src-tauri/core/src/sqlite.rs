@@ -0,0 +1,499 @@
+use crate::backup::{self, BackupPolicy};
+use crate::error::Result;
+use crate::folder;
+use crate::format::SaveFile;
+use crate::history::{self, HistoryPolicy, Revision};
+use crate::model::Note;
+use crate::storage::{self, Backend, NOTES_FILE};
+use crate::store::NoteStore;
+use crate::trash::TrashedNote;
+use chrono::{DateTime, Utc};
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, Row};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// The database in the app data dir, used with `"storage": "sqlite"`.
+pub const DATABASE_FILE: &str = "notes.db";
+
+/// Bumped with every change to the tables, `0` is a database that was just created.
+const SCHEMA_VERSION: i64 = 1;
+
+const SCHEMA: &str = "
+CREATE TABLE notes (
+    id TEXT PRIMARY KEY,
+    color TEXT NOT NULL,
+    contents TEXT NOT NULL,
+    x INTEGER NOT NULL,
+    y INTEGER NOT NULL,
+    height INTEGER NOT NULL,
+    width INTEGER NOT NULL,
+    -- fields written by a newer version of the app, as a JSON object
+    extra TEXT NOT NULL,
+    -- set for the notes in the trash
+    closed_at TEXT
+);
+CREATE TABLE revisions (
+    note_id TEXT NOT NULL,
+    taken_at TEXT NOT NULL,
+    color TEXT NOT NULL,
+    contents TEXT NOT NULL,
+    x INTEGER NOT NULL,
+    y INTEGER NOT NULL,
+    height INTEGER NOT NULL,
+    width INTEGER NOT NULL
+);
+CREATE INDEX revisions_by_note ON revisions (note_id);
+";
+
+const NOTE_COLUMNS: &str = "id, color, contents, x, y, height, width, extra, closed_at";
+
+/// Every note is a row, and a save only writes the rows of the notes that changed since the last one.
+/// The first time the database is opened it takes over the notes, trash and history of the JSON or Markdown files.
+#[derive(Debug)]
+pub struct SqliteStore {
+    app_data_dir: PathBuf,
+    backups: BackupPolicy,
+    conn: Connection,
+    // every row as it is in the database, with when the note was closed if it is in the trash
+    saved: HashMap<Uuid, (Note, Option<DateTime<Utc>>)>,
+}
+
+impl SqliteStore {
+    pub fn open(app_data_dir: &Path, backups: BackupPolicy) -> Result<SqliteStore> {
+        let mut conn = Connection::open(app_data_dir.join(DATABASE_FILE))?;
+
+        let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+
+        if version == 0 {
+            let tx = conn.transaction()?;
+            tx.execute_batch(SCHEMA)?;
+            migrate_files(&tx, app_data_dir)?;
+            tx.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+            tx.commit()?;
+        }
+
+        Ok(SqliteStore {
+            app_data_dir: app_data_dir.to_path_buf(),
+            backups,
+            conn,
+            saved: HashMap::new(),
+        })
+    }
+
+    fn rows(&mut self) -> Result<Vec<(Note, Option<DateTime<Utc>>)>> {
+        let mut statement = self
+            .conn
+            .prepare(&format!("SELECT {NOTE_COLUMNS} FROM notes ORDER BY rowid"))?;
+        let rows = statement
+            .query_map([], read_note)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        drop(statement);
+
+        self.saved = rows
+            .iter()
+            .map(|(note, closed_at)| (note.id, (note.clone(), *closed_at)))
+            .collect();
+
+        Ok(rows)
+    }
+}
+
+/// Imports the save file and history next to a database that was just created.
+fn migrate_files(conn: &Connection, app_data_dir: &Path) -> Result<()> {
+    if !app_data_dir.join(NOTES_FILE).exists() && folder::last_modified(app_data_dir).is_none() {
+        return Ok(());
+    }
+
+    // this picks whichever of `notes.json` and the Markdown folder was written last
+    let save_file = storage::read_contents(app_data_dir, Backend::Json)?;
+
+    for note in &save_file.notes {
+        write_note(conn, note, None)?;
+    }
+    for trashed in &save_file.trash {
+        write_note(conn, &trashed.note, Some(trashed.closed_at))?;
+    }
+
+    let ids = save_file
+        .notes
+        .iter()
+        .chain(save_file.trash.iter().map(|trashed| &trashed.note))
+        .map(|note| note.id);
+
+    for id in ids {
+        for revision in history::list(app_data_dir, id)? {
+            write_revision(conn, id, &revision)?;
+        }
+    }
+
+    log::info!(
+        "moved {} notes and {} closed notes from the save files into {DATABASE_FILE}",
+        save_file.notes.len(),
+        save_file.trash.len()
+    );
+
+    Ok(())
+}
+
+/// Turns a column that didn't parse into the error rusqlite gives for columns of the wrong type.
+fn column<T, E>(index: usize, parsed: std::result::Result<T, E>) -> rusqlite::Result<T>
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    parsed.map_err(|e| rusqlite::Error::FromSqlConversionFailure(index, Type::Text, Box::new(e)))
+}
+
+fn read_time(row: &Row, index: usize) -> rusqlite::Result<Option<DateTime<Utc>>> {
+    row.get::<_, Option<String>>(index)?
+        .map(|time| column(index, DateTime::parse_from_rfc3339(&time)))
+        .transpose()
+        .map(|time| time.map(|time| time.with_timezone(&Utc)))
+}
+
+fn read_note(row: &Row) -> rusqlite::Result<(Note, Option<DateTime<Utc>>)> {
+    let note = Note {
+        id: column(0, Uuid::parse_str(&row.get::<_, String>(0)?))?,
+        color: row.get(1)?,
+        contents: row.get(2)?,
+        x: row.get(3)?,
+        y: row.get(4)?,
+        height: row.get(5)?,
+        width: row.get(6)?,
+        extra: column(7, serde_json::from_str(&row.get::<_, String>(7)?))?,
+    };
+
+    Ok((note, read_time(row, 8)?))
+}
+
+fn write_note(conn: &Connection, note: &Note, closed_at: Option<DateTime<Utc>>) -> Result<()> {
+    // an upsert keeps the rowid, and with it the order the notes are loaded in
+    conn.execute(
+        &format!(
+            "INSERT INTO notes ({NOTE_COLUMNS}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+            ON CONFLICT (id) DO UPDATE SET color = ?2, contents = ?3, x = ?4, y = ?5,
+                height = ?6, width = ?7, extra = ?8, closed_at = ?9"
+        ),
+        params![
+            note.id.to_string(),
+            note.color,
+            note.contents,
+            note.x,
+            note.y,
+            note.height,
+            note.width,
+            serde_json::to_string(&note.extra)?,
+            closed_at.map(|closed_at| closed_at.to_rfc3339()),
+        ],
+    )?;
+
+    Ok(())
+}
+
+fn delete_note(conn: &Connection, id: Uuid) -> Result<()> {
+    conn.execute("DELETE FROM notes WHERE id = ?1", [id.to_string()])?;
+
+    Ok(())
+}
+
+fn write_revision(conn: &Connection, id: Uuid, revision: &Revision) -> Result<()> {
+    conn.execute(
+        "INSERT INTO revisions (note_id, taken_at, color, contents, x, y, height, width)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![
+            id.to_string(),
+            revision.taken_at.to_rfc3339(),
+            revision.color,
+            revision.contents,
+            revision.x,
+            revision.y,
+            revision.height,
+            revision.width,
+        ],
+    )?;
+
+    Ok(())
+}
+
+impl NoteStore for SqliteStore {
+    fn load_all(&mut self) -> Result<Vec<Note>> {
+        Ok(self
+            .rows()?
+            .into_iter()
+            .filter(|(_, closed_at)| closed_at.is_none())
+            .map(|(note, _)| note)
+            .collect())
+    }
+
+    fn list_trash(&mut self) -> Result<Vec<TrashedNote>> {
+        let mut trash = self
+            .rows()?
+            .into_iter()
+            .filter_map(|(note, closed_at)| {
+                closed_at.map(|closed_at| TrashedNote { closed_at, note })
+            })
+            .collect::<Vec<_>>();
+        trash.sort_by_key(|trashed| trashed.closed_at);
+
+        Ok(trash)
+    }
+
+    fn upsert(&mut self, note: &Note) -> Result<()> {
+        write_note(&self.conn, note, None)?;
+        self.saved.insert(note.id, (note.clone(), None));
+
+        Ok(())
+    }
+
+    fn upsert_trashed(&mut self, trashed: &TrashedNote) -> Result<()> {
+        write_note(&self.conn, &trashed.note, Some(trashed.closed_at))?;
+        self.saved.insert(
+            trashed.note.id,
+            (trashed.note.clone(), Some(trashed.closed_at)),
+        );
+
+        Ok(())
+    }
+
+    fn delete(&mut self, id: Uuid) -> Result<()> {
+        delete_note(&self.conn, id)?;
+        self.saved.remove(&id);
+
+        Ok(())
+    }
+
+    fn save(&mut self, save_file: SaveFile) -> Result<()> {
+        let rows = save_file
+            .notes
+            .iter()
+            .map(|note| (note, None))
+            .chain(
+                save_file
+                    .trash
+                    .iter()
+                    .map(|trashed| (&trashed.note, Some(trashed.closed_at))),
+            )
+            .collect::<Vec<_>>();
+
+        let tx = self.conn.transaction()?;
+
+        for (note, closed_at) in &rows {
+            let unchanged = matches!(
+                self.saved.get(&note.id),
+                Some((saved, saved_closed_at)) if saved == *note && saved_closed_at == closed_at
+            );
+
+            if !unchanged {
+                write_note(&tx, note, *closed_at)?;
+            }
+        }
+
+        let gone = self
+            .saved
+            .keys()
+            .filter(|id| !rows.iter().any(|(note, _)| note.id == **id))
+            .copied()
+            .collect::<Vec<_>>();
+
+        for id in &gone {
+            delete_note(&tx, *id)?;
+        }
+
+        tx.commit()?;
+
+        self.saved = rows
+            .into_iter()
+            .map(|(note, closed_at)| (note.id, (note.clone(), closed_at)))
+            .collect();
+
+        backup::make_backups(
+            &self.app_data_dir,
+            &serde_json::to_string(&save_file)?,
+            Utc::now(),
+            &self.backups,
+        )
+    }
+
+    fn history(&mut self, id: Uuid) -> Result<Vec<Revision>> {
+        let mut statement = self.conn.prepare(
+            "SELECT taken_at, color, contents, x, y, height, width FROM revisions
+            WHERE note_id = ?1 ORDER BY rowid",
+        )?;
+
+        let revisions = statement
+            .query_map([id.to_string()], |row| {
+                Ok(Revision {
+                    // the column is never null
+                    taken_at: read_time(row, 0)?.unwrap_or_default(),
+                    color: row.get(1)?,
+                    contents: row.get(2)?,
+                    x: row.get(3)?,
+                    y: row.get(4)?,
+                    height: row.get(5)?,
+                    width: row.get(6)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(revisions)
+    }
+
+    fn push_revision(
+        &mut self,
+        id: Uuid,
+        revision: &Revision,
+        policy: &HistoryPolicy,
+    ) -> Result<()> {
+        let tx = self.conn.transaction()?;
+
+        write_revision(&tx, id, revision)?;
+        tx.execute(
+            "DELETE FROM revisions WHERE note_id = ?1 AND rowid NOT IN
+            (SELECT rowid FROM revisions WHERE note_id = ?1 ORDER BY rowid DESC LIMIT ?2)",
+            params![id.to_string(), policy.keep as i64],
+        )?;
+
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    fn remove_history(&mut self, id: Uuid) -> Result<()> {
+        self.conn
+            .execute("DELETE FROM revisions WHERE note_id = ?1", [id.to_string()])?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn note(n: u128, text: &str) -> Note {
+        Note {
+            id: Uuid::from_u128(n),
+            color: "#fff9b1".to_string(),
+            contents: format!(r#"{{"ops":[{{"insert":"{text}\n"}}]}}"#),
+            x: 10,
+            y: 20,
+            height: 250,
+            width: 300,
+            ..Default::default()
+        }
+    }
+
+    // how many rows the connection has written since it was opened
+    fn rows_written(store: &SqliteStore) -> u64 {
+        store.conn.total_changes()
+    }
+
+    fn at(minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, 1, 9, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn notes_and_trash_survive_reopening() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut save_file = SaveFile::new(vec![note(1, "milk"), note(2, "eggs")]);
+        save_file.trash.push(TrashedNote {
+            closed_at: at(0),
+            note: note(3, "ham"),
+        });
+        save_file.notes[1]
+            .extra
+            .insert("pinned".to_string(), true.into());
+
+        let mut store = SqliteStore::open(dir.path(), BackupPolicy::default()).unwrap();
+        store.save(save_file.clone()).unwrap();
+        drop(store);
+
+        let mut store = SqliteStore::open(dir.path(), BackupPolicy::default()).unwrap();
+        assert_eq!(store.load_all().unwrap(), save_file.notes);
+        assert_eq!(store.list_trash().unwrap(), save_file.trash);
+    }
+
+    #[test]
+    fn saves_only_write_the_notes_that_changed() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = SqliteStore::open(dir.path(), BackupPolicy::default()).unwrap();
+
+        let notes = (1..=5).map(|n| note(n, "milk")).collect::<Vec<_>>();
+        store.save(SaveFile::new(notes.clone())).unwrap();
+        let written = rows_written(&store);
+
+        let mut changed = notes.clone();
+        changed[2] = note(3, "eggs");
+        changed.remove(4);
+        store.save(SaveFile::new(changed.clone())).unwrap();
+
+        // one update and one delete
+        assert_eq!(rows_written(&store) - written, 2);
+        assert_eq!(store.load_all().unwrap(), changed);
+    }
+
+    #[test]
+    fn first_open_moves_the_json_notes_and_history_in() {
+        let dir = tempfile::tempdir().unwrap();
+        let save_file = SaveFile::new(vec![note(1, "milk")]);
+        let policy = HistoryPolicy::default();
+
+        storage::save_contents(
+            save_file.clone(),
+            dir.path(),
+            Backend::Json,
+            &BackupPolicy::default(),
+        )
+        .unwrap();
+        history::push(
+            dir.path(),
+            Uuid::from_u128(1),
+            Revision::of(&note(1, "mil"), at(0)),
+            &policy,
+        )
+        .unwrap();
+
+        let mut store = SqliteStore::open(dir.path(), BackupPolicy::default()).unwrap();
+        assert_eq!(store.load_all().unwrap(), save_file.notes);
+        assert_eq!(
+            store.history(Uuid::from_u128(1)).unwrap(),
+            vec![Revision::of(&note(1, "mil"), at(0))]
+        );
+
+        // only once, notes deleted from the database don't come back
+        store.save(SaveFile::new(Vec::new())).unwrap();
+        drop(store);
+        let mut store = SqliteStore::open(dir.path(), BackupPolicy::default()).unwrap();
+        assert!(store.load_all().unwrap().is_empty());
+    }
+
+    #[test]
+    fn only_the_newest_revisions_are_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = SqliteStore::open(dir.path(), BackupPolicy::default()).unwrap();
+        let policy = HistoryPolicy {
+            keep: 2,
+            ..Default::default()
+        };
+
+        for (i, text) in ["a", "b", "c"].into_iter().enumerate() {
+            store
+                .push_revision(
+                    Uuid::from_u128(1),
+                    &Revision::of(&note(1, text), at(i as u32)),
+                    &policy,
+                )
+                .unwrap();
+        }
+
+        let revisions = store.history(Uuid::from_u128(1)).unwrap();
+        assert_eq!(revisions.len(), 2);
+        assert_eq!(revisions[0].contents, note(1, "b").contents);
+        assert!(store.revision(Uuid::from_u128(1), 2).is_err());
+
+        store.remove_history(Uuid::from_u128(1)).unwrap();
+        assert!(store.history(Uuid::from_u128(1)).unwrap().is_empty());
+    }
+}
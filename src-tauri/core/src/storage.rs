@@ -0,0 +1,440 @@
+use crate::backup::{self, BackupPolicy};
+use crate::error::{Result, StickyError};
+use crate::folder;
+use crate::format::{self, SaveFile};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+pub const NOTES_FILE: &str = "notes.json";
+/// Where an unreadable `notes.json` is moved before it gets replaced, so it can still be fixed by hand.
+pub const CORRUPT_NOTES_FILE: &str = "notes.json.corrupt";
+
+/// Where the notes are saved, chosen with the `storage` setting.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// everything in `notes.json`
+    Json,
+    /// a Markdown file per note in the `notes` folder, see [`folder`]
+    Markdown,
+    /// an SQLite database, see [`crate::sqlite`]
+    Sqlite,
+}
+
+impl Default for Backend {
+    fn default() -> Self {
+        Backend::Json
+    }
+}
+
+/// Reads the saved notes and trash with `backend`. Right after switching backends the other layout is newer,
+/// so the notes are read from that one instead, and the next save carries them over.
+/// The database isn't a file layout, for it this reads `notes.json` the way [`crate::sqlite`] migrates from it.
+pub fn read_contents(app_data_dir: &Path, backend: Backend) -> Result<SaveFile> {
+    read(app_data_dir, backend, true)
+}
+
+/// Like [`read_contents`], but a save file that can't be read is an error and is left as it is, instead of being
+/// moved aside for a backup, and note files that can't be read are skipped without being moved aside.
+/// For notes that changed while the app runs, where the app still has them all.
+pub fn read_contents_as_is(app_data_dir: &Path, backend: Backend) -> Result<SaveFile> {
+    read(app_data_dir, backend, false)
+}
+
+fn read(app_data_dir: &Path, backend: Backend, recover: bool) -> Result<SaveFile> {
+    let read_folder = if recover {
+        folder::read
+    } else {
+        folder::read_as_is
+    };
+
+    let json_modified = fs::metadata(app_data_dir.join(NOTES_FILE))
+        .ok()
+        .filter(|metadata| metadata.len() > 0)
+        .and_then(|metadata| metadata.modified().ok());
+    let folder_modified = folder::last_modified(app_data_dir);
+
+    match backend {
+        Backend::Json | Backend::Sqlite if folder_modified > json_modified => {
+            log::info!(
+                "the {} folder is newer than {NOTES_FILE}, reading it instead",
+                folder::NOTES_DIR
+            );
+            read_folder(app_data_dir)
+        }
+        Backend::Json | Backend::Sqlite => read_json(app_data_dir, recover),
+        Backend::Markdown if json_modified > folder_modified => {
+            log::info!(
+                "{NOTES_FILE} is newer than the {} folder, reading it instead",
+                folder::NOTES_DIR
+            );
+            read_json(app_data_dir, recover)
+        }
+        Backend::Markdown => read_folder(app_data_dir),
+    }
+}
+
+/// Reads the saved notes and trash from `notes.json` in the app data dir, creating an empty save file if there is none.
+/// If the save file can't be parsed it is moved out of the way, and the notes come from the newest backup that can.
+/// Without `recover`, a save file that is missing, empty or can't be parsed is an error instead.
+fn read_json(app_data_dir: &Path, recover: bool) -> Result<SaveFile> {
+    let path_buf = app_data_dir.join(NOTES_FILE);
+
+    let file_path = path_buf.as_path();
+
+    if !recover {
+        let file_content = fs::read_to_string(file_path)?;
+        if file_content.is_empty() {
+            return Err(StickyError::InvalidSaveFile(format!(
+                "{NOTES_FILE} is empty"
+            )));
+        }
+
+        return format::parse(&file_content);
+    }
+
+    let file_content = if file_path.exists() {
+        fs::read_to_string(file_path)?
+    } else {
+        fs::File::create(file_path)?;
+        String::new()
+    };
+
+    // saves are never empty, so an empty file is either a fresh install or a write that got cut off
+    if file_content.is_empty() {
+        return Ok(match backup::newest_valid_backup(app_data_dir) {
+            Some((path, save_file)) => {
+                log::warn!("{NOTES_FILE} is empty, restored {}", path.display());
+                save_file
+            }
+            None => SaveFile::new(Vec::new()),
+        });
+    }
+
+    match format::parse(&file_content) {
+        Ok(save_file) => Ok(save_file),
+        Err(e) => {
+            log::error!("could not parse {NOTES_FILE}: {e}");
+
+            fs::rename(file_path, app_data_dir.join(CORRUPT_NOTES_FILE))?;
+            log::warn!("moved the unreadable save file to {CORRUPT_NOTES_FILE}");
+
+            match backup::newest_valid_backup(app_data_dir) {
+                Some((path, save_file)) => {
+                    log::warn!("restored {}", path.display());
+                    Ok(save_file)
+                }
+                None => Err(e),
+            }
+        }
+    }
+}
+
+/// Saves `save_file` with `backend`, then takes any backups that are due.
+/// The backups are always in the `notes.json` format, whichever backend wrote the notes.
+/// The database saves itself, for it this writes `notes.json`.
+pub fn save_contents(
+    mut save_file: SaveFile,
+    app_data_dir: &Path,
+    backend: Backend,
+    backups: &BackupPolicy,
+) -> Result<()> {
+    let path_buf = app_data_dir.join(NOTES_FILE);
+
+    // hold on to whatever a newer version of the app put in the save file
+    if let Some(existing) = fs::read_to_string(path_buf.as_path())
+        .ok()
+        .filter(|_| backend != Backend::Markdown)
+        .and_then(|file_content| format::parse(&file_content).ok())
+    {
+        save_file.version = save_file.version.max(existing.version);
+        save_file.extra = existing.extra;
+    }
+
+    let contents = serde_json::to_string(&save_file)?;
+
+    match backend {
+        Backend::Json | Backend::Sqlite => write_atomic(path_buf.as_path(), contents.as_bytes())?,
+        Backend::Markdown => folder::write(&save_file, app_data_dir, Utc::now())?,
+    }
+
+    backup::make_backups(app_data_dir, &contents, Utc::now(), backups)
+}
+
+/// Writes `contents` to a temp file next to `path`, fsyncs it and renames it over `path`,
+/// so a crash halfway through leaves either the old file or the new one, never a mix of both.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+    let tmp_path = path.with_file_name(format!(".{}.tmp", file_name.to_string_lossy()));
+
+    {
+        let mut file = fs::File::create(&tmp_path)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+    }
+
+    fs::rename(&tmp_path, path)?;
+
+    // the rename only survives a power loss once the directory entry is on disk too
+    #[cfg(unix)]
+    if let Some(parent) = path.parent() {
+        fs::File::open(parent)?.sync_all()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Note;
+    use crate::trash::TrashedNote;
+    use uuid::Uuid;
+
+    fn note() -> Note {
+        Note {
+            id: Uuid::new_v4(),
+            color: "#fff9b1".to_string(),
+            contents: r#"{"ops":[{"insert":"hello\n"}]}"#.to_string(),
+            x: 10,
+            y: 20,
+            height: 250,
+            width: 300,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn missing_file_is_created_empty() {
+        let dir = tempfile::tempdir().unwrap();
+
+        assert_eq!(
+            read_contents(dir.path(), Backend::Json).unwrap().notes,
+            Vec::new()
+        );
+        assert!(dir.path().join(NOTES_FILE).exists());
+    }
+
+    #[test]
+    fn save_then_read_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let notes = vec![note(), note()];
+
+        save_contents(
+            SaveFile::new(notes.clone()),
+            dir.path(),
+            Backend::Json,
+            &BackupPolicy::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            read_contents(dir.path(), Backend::Json).unwrap().notes,
+            notes
+        );
+    }
+
+    #[test]
+    fn switching_backends_carries_the_notes_over() {
+        let dir = tempfile::tempdir().unwrap();
+        let save_file = SaveFile::new(vec![Note {
+            contents: r#"{"ops":[{"insert":"hello\n"}]}"#.to_string(),
+            ..note()
+        }]);
+        let policy = BackupPolicy::default();
+
+        save_contents(save_file.clone(), dir.path(), Backend::Json, &policy).unwrap();
+        assert_eq!(
+            read_contents(dir.path(), Backend::Markdown).unwrap(),
+            save_file
+        );
+
+        // file times aren't precise enough to tell two saves right after each other apart
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        save_contents(save_file.clone(), dir.path(), Backend::Markdown, &policy).unwrap();
+        assert!(dir.path().join(folder::NOTES_DIR).exists());
+        assert_eq!(read_contents(dir.path(), Backend::Json).unwrap(), save_file);
+        assert_eq!(
+            read_contents(dir.path(), Backend::Markdown).unwrap(),
+            save_file
+        );
+    }
+
+    #[test]
+    fn trash_is_saved_with_the_notes() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut save_file = SaveFile::new(vec![note()]);
+        save_file.trash.push(TrashedNote {
+            closed_at: Utc::now(),
+            note: note(),
+        });
+
+        save_contents(
+            save_file.clone(),
+            dir.path(),
+            Backend::Json,
+            &BackupPolicy::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            read_contents(dir.path(), Backend::Json).unwrap().trash,
+            save_file.trash
+        );
+    }
+
+    #[test]
+    fn atomic_write_replaces_the_file_and_cleans_up() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(NOTES_FILE);
+        fs::write(&path, "old").unwrap();
+
+        write_atomic(&path, b"new").unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn corrupt_file_falls_back_to_the_newest_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let notes = vec![note()];
+        save_contents(
+            SaveFile::new(notes.clone()),
+            dir.path(),
+            Backend::Json,
+            &BackupPolicy::default(),
+        )
+        .unwrap();
+
+        // a write that got cut off halfway
+        fs::write(dir.path().join(NOTES_FILE), "[{\"color\":").unwrap();
+
+        assert_eq!(
+            read_contents(dir.path(), Backend::Json).unwrap().notes,
+            notes
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join(CORRUPT_NOTES_FILE)).unwrap(),
+            "[{\"color\":"
+        );
+    }
+
+    #[test]
+    fn reading_as_is_leaves_a_corrupt_file_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        save_contents(
+            SaveFile::new(vec![note()]),
+            dir.path(),
+            Backend::Json,
+            &BackupPolicy::default(),
+        )
+        .unwrap();
+
+        for cut_off in ["[{\"color\":", ""] {
+            fs::write(dir.path().join(NOTES_FILE), cut_off).unwrap();
+
+            assert!(read_contents_as_is(dir.path(), Backend::Json).is_err());
+            assert_eq!(
+                fs::read_to_string(dir.path().join(NOTES_FILE)).unwrap(),
+                cut_off
+            );
+            assert!(!dir.path().join(CORRUPT_NOTES_FILE).exists());
+        }
+    }
+
+    #[test]
+    fn truncated_file_falls_back_to_the_newest_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let notes = vec![note()];
+        save_contents(
+            SaveFile::new(notes.clone()),
+            dir.path(),
+            Backend::Json,
+            &BackupPolicy::default(),
+        )
+        .unwrap();
+
+        fs::write(dir.path().join(NOTES_FILE), "").unwrap();
+
+        assert_eq!(
+            read_contents(dir.path(), Backend::Json).unwrap().notes,
+            notes
+        );
+    }
+
+    #[test]
+    fn reads_the_existing_save_format() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join(NOTES_FILE),
+            r##"[{"color":"#81B7DD","contents":"{\"ops\":[]}","x":1,"y":2,"height":3,"width":4,"label":"new_sticky_window_1"}]"##,
+        )
+        .unwrap();
+
+        let notes = read_contents(dir.path(), Backend::Json).unwrap().notes;
+
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].color, "#81B7DD");
+        assert!(!notes[0].id.is_nil());
+    }
+
+    #[test]
+    fn saves_are_versioned() {
+        let dir = tempfile::tempdir().unwrap();
+
+        save_contents(
+            SaveFile::new(vec![note()]),
+            dir.path(),
+            Backend::Json,
+            &BackupPolicy::default(),
+        )
+        .unwrap();
+
+        let written = fs::read_to_string(dir.path().join(NOTES_FILE)).unwrap();
+        assert_eq!(
+            format::parse(&written).unwrap().version,
+            format::CURRENT_VERSION
+        );
+        assert!(written.starts_with("{"));
+    }
+
+    #[test]
+    fn saving_keeps_fields_from_a_newer_version() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join(NOTES_FILE),
+            r##"{"version":7,"sync":"on","notes":[{"id":"67e55044-10b1-426f-9247-bb680e5fe0c8","color":"#fff9b1","contents":"","x":0,"y":0,"height":0,"width":0,"pinned":true}]}"##,
+        )
+        .unwrap();
+
+        let save_file = read_contents(dir.path(), Backend::Json).unwrap();
+        save_contents(
+            save_file,
+            dir.path(),
+            Backend::Json,
+            &BackupPolicy::default(),
+        )
+        .unwrap();
+
+        let written =
+            format::parse(&fs::read_to_string(dir.path().join(NOTES_FILE)).unwrap()).unwrap();
+        assert_eq!(written.version, 7);
+        assert_eq!(written.extra["sync"], "on");
+        assert_eq!(written.notes[0].extra["pinned"], true);
+    }
+
+    #[test]
+    fn corrupt_file_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(NOTES_FILE), "[{\"color\":").unwrap();
+
+        assert!(read_contents(dir.path(), Backend::Json).is_err());
+        assert!(dir.path().join(CORRUPT_NOTES_FILE).exists());
+    }
+}
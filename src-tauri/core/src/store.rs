@@ -0,0 +1,254 @@
+use crate::backup::BackupPolicy;
+use crate::error::{Result, StickyError};
+use crate::format::SaveFile;
+use crate::history::{self, HistoryPolicy, Revision};
+use crate::model::Note;
+use crate::sqlite::SqliteStore;
+use crate::storage::{self, Backend};
+use crate::trash::TrashedNote;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// Somewhere to keep the notes, the trash and the note history between runs.
+/// Every note is either open or in the trash, saving it as one takes it out of the other.
+pub trait NoteStore: Send {
+    /// Every open note, read from disk.
+    fn load_all(&mut self) -> Result<Vec<Note>>;
+
+    /// Every closed note that can still be reopened, oldest first.
+    fn list_trash(&mut self) -> Result<Vec<TrashedNote>>;
+
+    /// Saves `note` as open.
+    fn upsert(&mut self, note: &Note) -> Result<()>;
+
+    /// Saves a closed note.
+    fn upsert_trashed(&mut self, trashed: &TrashedNote) -> Result<()>;
+
+    /// Deletes a note for good, open or closed. Its history is left alone.
+    fn delete(&mut self, id: Uuid) -> Result<()>;
+
+    /// Brings the store in line with `save_file`, writing as little as the store allows.
+    fn save(&mut self, save_file: SaveFile) -> Result<()>;
+
+    /// Every revision of the note with this id, oldest first.
+    fn history(&mut self, id: Uuid) -> Result<Vec<Revision>>;
+
+    /// Adds `revision` to the note's history, dropping the oldest revisions beyond what `policy` keeps.
+    fn push_revision(
+        &mut self,
+        id: Uuid,
+        revision: &Revision,
+        policy: &HistoryPolicy,
+    ) -> Result<()>;
+
+    /// Deletes the history of a note that is gone for good.
+    fn remove_history(&mut self, id: Uuid) -> Result<()>;
+
+    /// The open notes and the trash together.
+    fn load(&mut self) -> Result<SaveFile> {
+        let notes = self.load_all()?;
+
+        Ok(SaveFile {
+            trash: self.list_trash()?,
+            ..SaveFile::new(notes)
+        })
+    }
+
+    /// Like [`NoteStore::load`], for while the app runs: nothing that can't be read is moved aside or replaced with
+    /// a backup the app's notes would then be rolled back to. A save file that can't be read is an error,
+    /// a note file that can't be read is skipped.
+    fn reload(&mut self) -> Result<SaveFile> {
+        self.load()
+    }
+
+    fn revision(&mut self, id: Uuid, index: usize) -> Result<Revision> {
+        self.history(id)?
+            .into_iter()
+            .nth(index)
+            .ok_or(StickyError::RevisionNotFound(id, index))
+    }
+}
+
+/// Opens the store the `storage` setting picks.
+pub fn open(
+    app_data_dir: &Path,
+    backend: Backend,
+    backups: &BackupPolicy,
+) -> Result<Box<dyn NoteStore>> {
+    Ok(match backend {
+        Backend::Json | Backend::Markdown => {
+            Box::new(FileStore::new(app_data_dir, backend, backups.clone()))
+        }
+        Backend::Sqlite => Box::new(SqliteStore::open(app_data_dir, backups.clone())?),
+    })
+}
+
+/// `notes.json` or the Markdown folder, see [`storage`]. Every change rewrites the save file,
+/// the Markdown folder skips the notes that didn't change.
+#[derive(Debug)]
+pub struct FileStore {
+    app_data_dir: PathBuf,
+    backend: Backend,
+    backups: BackupPolicy,
+    // what was last read or written, the single-note methods change this and write it back
+    saved: Option<SaveFile>,
+}
+
+impl FileStore {
+    pub fn new(app_data_dir: &Path, backend: Backend, backups: BackupPolicy) -> FileStore {
+        FileStore {
+            app_data_dir: app_data_dir.to_path_buf(),
+            backend,
+            backups,
+            saved: None,
+        }
+    }
+
+    fn saved(&mut self) -> Result<&mut SaveFile> {
+        let save_file = match self.saved.take() {
+            Some(save_file) => save_file,
+            None => storage::read_contents(&self.app_data_dir, self.backend)?,
+        };
+
+        Ok(self.saved.insert(save_file))
+    }
+
+    fn change(&mut self, change: impl FnOnce(&mut SaveFile)) -> Result<()> {
+        let mut save_file = self.saved()?.clone();
+        change(&mut save_file);
+
+        self.save(save_file)
+    }
+}
+
+impl NoteStore for FileStore {
+    fn load_all(&mut self) -> Result<Vec<Note>> {
+        self.saved = None;
+
+        Ok(self.saved()?.notes.clone())
+    }
+
+    fn list_trash(&mut self) -> Result<Vec<TrashedNote>> {
+        Ok(self.saved()?.trash.clone())
+    }
+
+    fn reload(&mut self) -> Result<SaveFile> {
+        self.saved = None;
+        let save_file = storage::read_contents_as_is(&self.app_data_dir, self.backend)?;
+
+        Ok(self.saved.insert(save_file).clone())
+    }
+
+    fn upsert(&mut self, note: &Note) -> Result<()> {
+        self.change(|save_file| {
+            save_file.trash.retain(|trashed| trashed.note.id != note.id);
+
+            match save_file.notes.iter_mut().find(|saved| saved.id == note.id) {
+                Some(saved) => *saved = note.clone(),
+                None => save_file.notes.push(note.clone()),
+            }
+        })
+    }
+
+    fn upsert_trashed(&mut self, trashed: &TrashedNote) -> Result<()> {
+        self.change(|save_file| {
+            save_file.notes.retain(|note| note.id != trashed.note.id);
+            save_file
+                .trash
+                .retain(|saved| saved.note.id != trashed.note.id);
+            save_file.trash.push(trashed.clone());
+        })
+    }
+
+    fn delete(&mut self, id: Uuid) -> Result<()> {
+        self.change(|save_file| {
+            save_file.notes.retain(|note| note.id != id);
+            save_file.trash.retain(|trashed| trashed.note.id != id);
+        })
+    }
+
+    fn save(&mut self, save_file: SaveFile) -> Result<()> {
+        storage::save_contents(
+            save_file.clone(),
+            &self.app_data_dir,
+            self.backend,
+            &self.backups,
+        )?;
+        self.saved = Some(save_file);
+
+        Ok(())
+    }
+
+    fn history(&mut self, id: Uuid) -> Result<Vec<Revision>> {
+        history::list(&self.app_data_dir, id)
+    }
+
+    fn push_revision(
+        &mut self,
+        id: Uuid,
+        revision: &Revision,
+        policy: &HistoryPolicy,
+    ) -> Result<()> {
+        history::push(&self.app_data_dir, id, revision.clone(), policy)
+    }
+
+    fn remove_history(&mut self, id: Uuid) -> Result<()> {
+        history::remove(&self.app_data_dir, id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::folder;
+    use chrono::{TimeZone, Utc};
+    use std::fs;
+
+    fn note(n: u128) -> Note {
+        Note {
+            id: Uuid::from_u128(n),
+            color: "#fff9b1".to_string(),
+            contents: r#"{"ops":[{"insert":"hello\n"}]}"#.to_string(),
+            width: 300,
+            height: 250,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn single_note_changes_are_written_through() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = FileStore::new(dir.path(), Backend::Json, BackupPolicy::default());
+
+        store.upsert(&note(1)).unwrap();
+        store.upsert(&note(2)).unwrap();
+        store
+            .upsert_trashed(&TrashedNote {
+                closed_at: Utc.with_ymd_and_hms(2024, 3, 1, 9, 0, 0).unwrap(),
+                note: note(1),
+            })
+            .unwrap();
+
+        let mut reopened = FileStore::new(dir.path(), Backend::Json, BackupPolicy::default());
+        assert_eq!(reopened.load_all().unwrap(), vec![note(2)]);
+        assert_eq!(reopened.list_trash().unwrap()[0].note, note(1));
+
+        reopened.delete(Uuid::from_u128(1)).unwrap();
+        assert_eq!(store.load_all().unwrap(), vec![note(2)]);
+        assert!(store.list_trash().unwrap().is_empty());
+    }
+
+    #[test]
+    fn a_broken_note_file_does_not_fail_saves() {
+        let dir = tempfile::tempdir().unwrap();
+        let notes_dir = dir.path().join(folder::NOTES_DIR);
+        fs::create_dir_all(&notes_dir).unwrap();
+        fs::write(notes_dir.join("latin1.md"), b"caf\xe9\n").unwrap();
+        let mut store = FileStore::new(dir.path(), Backend::Markdown, BackupPolicy::default());
+
+        store.save(SaveFile::new(vec![note(1)])).unwrap();
+
+        assert_eq!(store.reload().unwrap().notes, vec![note(1)]);
+        assert!(notes_dir.join("latin1.md").exists());
+    }
+}
@@ -0,0 +1,54 @@
+use crate::model::Note;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+/// A closed note, kept in the `trash` of the save file so it can be reopened.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct TrashedNote {
+    pub closed_at: DateTime<Utc>,
+    pub note: Note,
+}
+
+/// How long closed notes stay in the trash before they are deleted for good.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TrashPolicy {
+    /// `0` keeps them forever
+    pub keep_days: u32,
+}
+
+impl Default for TrashPolicy {
+    fn default() -> Self {
+        TrashPolicy { keep_days: 30 }
+    }
+}
+
+impl TrashPolicy {
+    /// Whether a note closed at `closed_at` should be purged by `now`.
+    pub fn expired(&self, closed_at: DateTime<Utc>, now: DateTime<Utc>) -> bool {
+        self.keep_days > 0 && now - closed_at >= Duration::days(self.keep_days.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn notes_expire_after_keep_days() {
+        let closed_at = Utc.with_ymd_and_hms(2024, 3, 1, 9, 0, 0).unwrap();
+        let policy = TrashPolicy { keep_days: 2 };
+
+        assert!(!policy.expired(closed_at, closed_at + Duration::days(1)));
+        assert!(policy.expired(closed_at, closed_at + Duration::days(2)));
+    }
+
+    #[test]
+    fn zero_keeps_notes_forever() {
+        let closed_at = Utc.with_ymd_and_hms(2024, 3, 1, 9, 0, 0).unwrap();
+        let policy = TrashPolicy { keep_days: 0 };
+
+        assert!(!policy.expired(closed_at, closed_at + Duration::days(10_000)));
+    }
+}
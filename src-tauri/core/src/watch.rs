@@ -0,0 +1,527 @@
+use crate::error::Result;
+use crate::folder;
+use crate::format::SaveFile;
+use crate::model::Note;
+use crate::registry::NoteRegistry;
+use crate::sqlite::DATABASE_FILE;
+use crate::storage::{Backend, NOTES_FILE};
+use crate::trash::TrashedNote;
+use chrono::{DateTime, Utc};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Notices when files change on disk, whoever changed them. For a note store, [`ExternalEdits`] tells
+/// the app's own saves apart from everything else.
+#[derive(Debug)]
+pub struct FileWatcher {
+    _watcher: RecommendedWatcher,
+    // when the files last changed, cleared once the change is picked up
+    changed_at: Arc<Mutex<Option<Instant>>>,
+}
+
+impl FileWatcher {
+    /// Watches the files of the store `backend` picks.
+    pub fn store(app_data_dir: &Path, backend: Backend) -> Result<FileWatcher> {
+        let watched = app_data_dir.to_path_buf();
+        // the log file and backups live in the app data dir too
+        let is_store_file = move |path: &Path| is_store_file(path, &watched, backend);
+
+        let mut watches = vec![(app_data_dir.to_path_buf(), RecursiveMode::NonRecursive)];
+
+        if backend == Backend::Markdown {
+            let notes_dir = app_data_dir.join(folder::NOTES_DIR);
+            fs::create_dir_all(&notes_dir)?;
+            watches.push((notes_dir, RecursiveMode::Recursive));
+        }
+
+        FileWatcher::new(&watches, is_store_file)
+    }
+
+    /// Watches the Markdown files in `dir` and its subfolders.
+    pub fn markdown_folder(dir: &Path) -> Result<FileWatcher> {
+        FileWatcher::new(&[(dir.to_path_buf(), RecursiveMode::Recursive)], |path| {
+            path.extension()
+                .map_or(false, |extension| extension == "md")
+        })
+    }
+
+    /// Watches a single file, including editors saving it by writing a new file and renaming it over the old one.
+    pub fn file(path: &Path) -> Result<FileWatcher> {
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        let name = path.file_name().map(|name| name.to_os_string());
+
+        FileWatcher::new(
+            &[(dir.to_path_buf(), RecursiveMode::NonRecursive)],
+            move |changed| changed.file_name().map(|name| name.to_os_string()) == name,
+        )
+    }
+
+    fn new(
+        watches: &[(PathBuf, RecursiveMode)],
+        is_watched: impl Fn(&Path) -> bool + Send + 'static,
+    ) -> Result<FileWatcher> {
+        let changed_at = Arc::new(Mutex::new(None));
+
+        let sender = changed_at.clone();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+            match event {
+                // reading the files shouldn't look like a change to them
+                Ok(event) if matches!(event.kind, EventKind::Access(_)) => {}
+                Ok(event) if event.paths.iter().any(|path| is_watched(path)) => {
+                    *sender.lock().unwrap_or_else(PoisonError::into_inner) = Some(Instant::now());
+                }
+                Ok(_) => {}
+                Err(e) => log::warn!("error watching files: {e}"),
+            }
+        })?;
+
+        for (path, mode) in watches {
+            watcher.watch(path, *mode)?;
+        }
+
+        Ok(FileWatcher {
+            _watcher: watcher,
+            changed_at,
+        })
+    }
+
+    /// Whether the files changed and the change hasn't been taken with [`FileWatcher::take_settled`] yet.
+    /// Only looks, the change stays pending.
+    pub fn is_pending(&self) -> bool {
+        self.changed_at().is_some()
+    }
+
+    /// Whether the files changed and then were left alone for `quiet`, so whoever wrote them is likely done.
+    /// Clears the change if so.
+    pub fn take_settled(&self, now: Instant, quiet: Duration) -> bool {
+        let mut changed_at = self
+            .changed_at
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        match *changed_at {
+            Some(at) if now.saturating_duration_since(at) >= quiet => {
+                *changed_at = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn changed_at(&self) -> Option<Instant> {
+        *self
+            .changed_at
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+fn is_store_file(path: &Path, app_data_dir: &Path, backend: Backend) -> bool {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    match backend {
+        Backend::Json => file_name == NOTES_FILE,
+        Backend::Markdown => {
+            path.starts_with(app_data_dir.join(folder::NOTES_DIR)) && file_name.ends_with(".md")
+        }
+        // the journal and write-ahead log count too
+        Backend::Sqlite => file_name.starts_with(DATABASE_FILE),
+    }
+}
+
+/// One thing another program changed in the store, relative to the app's last save.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExternalChange {
+    /// a new note, it needs a window
+    Added(Note),
+    /// the note changed on disk and not in the app, its window gets the new version
+    Updated(Note),
+    /// only the color changed on disk
+    Recolored(Uuid, String),
+    /// the note was deleted or closed on disk, its window gets closed and it goes to the trash
+    Removed(Uuid),
+    /// the note changed both on disk and in the app. The app's version is kept, this is the one from disk.
+    Conflict(Note),
+}
+
+/// Everything that changed on disk, see [`ExternalEdits::diff`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Reload {
+    pub changes: Vec<ExternalChange>,
+    /// the trash after merging in the changes from disk, `None` if it didn't change there
+    pub trash: Option<Vec<TrashedNote>>,
+}
+
+impl Reload {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty() && self.trash.is_none()
+    }
+
+    /// Brings the registry up to date with the changes that don't conflict with the app's own.
+    /// Notes removed on disk end up in the trash, closed at `now` unless they were closed there.
+    pub fn apply_to(&self, registry: &mut NoteRegistry, now: DateTime<Utc>) {
+        if let Some(trash) = &self.trash {
+            registry.set_trash(trash.clone());
+        }
+
+        for change in &self.changes {
+            match change {
+                ExternalChange::Added(note) | ExternalChange::Updated(note) => {
+                    registry.insert(note.clone())
+                }
+                ExternalChange::Recolored(id, color) => {
+                    if let Some(mut note) = registry.get(*id).cloned() {
+                        note.color = color.clone();
+                        registry.insert(note);
+                    }
+                }
+                ExternalChange::Removed(id) => {
+                    if registry
+                        .trash()
+                        .iter()
+                        .any(|trashed| trashed.note.id == *id)
+                    {
+                        registry.remove(*id);
+                    } else {
+                        registry.close(*id, now);
+                    }
+                }
+                ExternalChange::Conflict(_) => {}
+            }
+        }
+    }
+}
+
+/// Remembers what the app last saved, and what the store read back for it, to tell edits by other programs
+/// apart from the app's own saves. The two differ when the store doesn't keep everything, like Markdown files.
+#[derive(Debug, Default)]
+pub struct ExternalEdits {
+    written: HashMap<Uuid, Note>,
+    written_trash: HashSet<Uuid>,
+    read_back: SaveFile,
+}
+
+impl ExternalEdits {
+    /// Starts from notes that were just loaded, so the app and the store agree.
+    pub fn new(loaded: &SaveFile) -> ExternalEdits {
+        let mut edits = ExternalEdits::default();
+        edits.saved(loaded, loaded.clone());
+        edits
+    }
+
+    /// Call after every save, with what was saved and what the store reads back right after.
+    pub fn saved(&mut self, written: &SaveFile, read_back: SaveFile) {
+        self.written = written
+            .notes
+            .iter()
+            .map(|note| (note.id, note.clone()))
+            .collect();
+        self.written_trash = written
+            .trash
+            .iter()
+            .map(|trashed| trashed.note.id)
+            .collect();
+        self.read_back = read_back;
+    }
+
+    /// Compares what is on disk now with what the store read back after the last save, and sorts every difference
+    /// by whether the note also changed in the app since then: `mine` is what the app holds now.
+    pub fn diff(&mut self, disk: SaveFile, mine: &SaveFile) -> Reload {
+        let base = by_id(&self.read_back.notes);
+        let on_disk = by_id(&disk.notes);
+        let in_app = by_id(&mine.notes);
+
+        // whether the app changed the note since the last save, closing it counts too
+        let changed_in_app = |id: &Uuid| in_app.get(id).copied() != self.written.get(id);
+
+        let mut changes = Vec::new();
+
+        for theirs in &disk.notes {
+            let id = theirs.id;
+
+            let base = base.get(&id).copied();
+
+            let change = if base == Some(theirs) {
+                continue;
+            } else if changed_in_app(&id) {
+                ExternalChange::Conflict(theirs.clone())
+            } else if matches!(base, Some(base) if only_color_changed(base, theirs)) {
+                ExternalChange::Recolored(id, theirs.color.clone())
+            } else if in_app.contains_key(&id) {
+                ExternalChange::Updated(theirs.clone())
+            } else {
+                ExternalChange::Added(theirs.clone())
+            };
+
+            changes.push(change);
+        }
+
+        for base in &self.read_back.notes {
+            if on_disk.contains_key(&base.id) || !in_app.contains_key(&base.id) {
+                continue;
+            }
+
+            if changed_in_app(&base.id) {
+                log::warn!(
+                    "note {} was deleted on disk but changed in the app, keeping it",
+                    base.id
+                );
+            } else {
+                changes.push(ExternalChange::Removed(base.id));
+            }
+        }
+
+        let trash = if disk.trash == self.read_back.trash {
+            None
+        } else {
+            Some(self.merge_trash(&disk, mine, &changes))
+        };
+
+        // from now on the notes taken from disk count as saved, the ones changed in the app still don't
+        for change in &changes {
+            match change {
+                ExternalChange::Added(note) | ExternalChange::Updated(note) => {
+                    self.written.insert(note.id, note.clone());
+                }
+                ExternalChange::Recolored(id, color) => {
+                    if let Some(note) = self.written.get_mut(id) {
+                        note.color = color.clone();
+                    }
+                }
+                ExternalChange::Removed(id) => {
+                    self.written.remove(id);
+                }
+                ExternalChange::Conflict(_) => {}
+            }
+        }
+        if let Some(trash) = &trash {
+            self.written_trash = trash.iter().map(|trashed| trashed.note.id).collect();
+        }
+        self.read_back = disk;
+
+        Reload { changes, trash }
+    }
+
+    // the trash from disk, plus the notes closed in the app since the last save, minus the ones that are open now
+    fn merge_trash(
+        &self,
+        disk: &SaveFile,
+        mine: &SaveFile,
+        changes: &[ExternalChange],
+    ) -> Vec<TrashedNote> {
+        let mut open = mine
+            .notes
+            .iter()
+            .map(|note| note.id)
+            .collect::<HashSet<_>>();
+        for change in changes {
+            match change {
+                ExternalChange::Added(note) => {
+                    open.insert(note.id);
+                }
+                ExternalChange::Removed(id) => {
+                    open.remove(id);
+                }
+                _ => {}
+            }
+        }
+
+        let closed_in_app = mine
+            .trash
+            .iter()
+            .filter(|trashed| !self.written_trash.contains(&trashed.note.id))
+            .filter(|trashed| {
+                !disk
+                    .trash
+                    .iter()
+                    .any(|theirs| theirs.note.id == trashed.note.id)
+            });
+
+        let mut trash = disk
+            .trash
+            .iter()
+            .chain(closed_in_app)
+            .filter(|trashed| !open.contains(&trashed.note.id))
+            .cloned()
+            .collect::<Vec<_>>();
+        trash.sort_by_key(|trashed| trashed.closed_at);
+
+        trash
+    }
+}
+
+fn by_id(notes: &[Note]) -> HashMap<Uuid, &Note> {
+    notes.iter().map(|note| (note.id, note)).collect()
+}
+
+fn only_color_changed(before: &Note, after: &Note) -> bool {
+    before.color != after.color
+        && Note {
+            color: after.color.clone(),
+            ..before.clone()
+        } == *after
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::thread;
+
+    fn note(n: u128, text: &str) -> Note {
+        Note {
+            id: Uuid::from_u128(n),
+            color: "#fff9b1".to_string(),
+            contents: format!(r#"{{"ops":[{{"insert":"{text}\n"}}]}}"#),
+            width: 300,
+            height: 250,
+            ..Default::default()
+        }
+    }
+
+    fn saved(notes: Vec<Note>) -> SaveFile {
+        SaveFile::new(notes)
+    }
+
+    #[test]
+    fn the_apps_own_save_is_not_a_change() {
+        let loaded = saved(vec![note(1, "milk")]);
+        let mut edits = ExternalEdits::new(&loaded);
+
+        let mine = saved(vec![note(1, "milk, eggs")]);
+        edits.saved(&mine, mine.clone());
+
+        assert!(edits.diff(mine.clone(), &mine).is_empty());
+    }
+
+    #[test]
+    fn edits_on_disk_are_sorted_by_kind() {
+        let loaded = saved(vec![note(1, "milk"), note(2, "eggs"), note(3, "ham")]);
+        let mut edits = ExternalEdits::new(&loaded);
+
+        let mut recolored = note(2, "eggs");
+        recolored.color = "#ff7eb9".to_string();
+        let disk = saved(vec![note(1, "milk, bread"), recolored, note(4, "jam")]);
+
+        let reload = edits.diff(disk.clone(), &loaded);
+
+        assert_eq!(
+            reload.changes,
+            vec![
+                ExternalChange::Updated(note(1, "milk, bread")),
+                ExternalChange::Recolored(Uuid::from_u128(2), "#ff7eb9".to_string()),
+                ExternalChange::Added(note(4, "jam")),
+                ExternalChange::Removed(Uuid::from_u128(3)),
+            ]
+        );
+        assert_eq!(reload.trash, None);
+
+        let mut registry = NoteRegistry::new(loaded.notes.clone(), Vec::new());
+        let now = Utc.with_ymd_and_hms(2024, 3, 1, 9, 0, 0).unwrap();
+        reload.apply_to(&mut registry, now);
+        assert_eq!(registry.notes(), disk.notes.as_slice());
+        // a file deleted by mistake can still be brought back
+        assert_eq!(
+            registry.trash(),
+            &[TrashedNote {
+                closed_at: now,
+                note: note(3, "ham"),
+            }]
+        );
+
+        // the same files again are nothing new
+        assert!(edits.diff(disk, &registry.save_file()).is_empty());
+    }
+
+    #[test]
+    fn notes_changed_on_both_sides_are_conflicts() {
+        let loaded = saved(vec![note(1, "milk")]);
+        let mut edits = ExternalEdits::new(&loaded);
+
+        let mine = saved(vec![note(1, "milk, eggs")]);
+        let reload = edits.diff(saved(vec![note(1, "milk, ham")]), &mine);
+
+        assert_eq!(
+            reload.changes,
+            vec![ExternalChange::Conflict(note(1, "milk, ham"))]
+        );
+
+        let mut registry = NoteRegistry::new(mine.notes.clone(), Vec::new());
+        reload.apply_to(&mut registry, Utc::now());
+        assert_eq!(registry.notes(), mine.notes.as_slice());
+    }
+
+    #[test]
+    fn trash_from_disk_keeps_the_notes_closed_in_the_app() {
+        let at = |minute| Utc.with_ymd_and_hms(2024, 3, 1, 9, minute, 0).unwrap();
+        let loaded = saved(vec![note(1, "milk"), note(2, "eggs")]);
+        let mut edits = ExternalEdits::new(&loaded);
+
+        // note 1 was closed in the app, note 2 by a script
+        let mine = SaveFile {
+            trash: vec![TrashedNote {
+                closed_at: at(1),
+                note: note(1, "milk"),
+            }],
+            ..saved(vec![note(2, "eggs")])
+        };
+        let disk = SaveFile {
+            trash: vec![TrashedNote {
+                closed_at: at(0),
+                note: note(2, "eggs"),
+            }],
+            ..saved(vec![note(1, "milk")])
+        };
+
+        let reload = edits.diff(disk, &mine);
+
+        // note 1 is still open on disk, but only because the script didn't know it was closed
+        assert_eq!(
+            reload.changes,
+            vec![ExternalChange::Removed(Uuid::from_u128(2))]
+        );
+        let trash = reload.trash.clone().unwrap();
+        assert_eq!(
+            trash
+                .iter()
+                .map(|trashed| trashed.note.id)
+                .collect::<Vec<_>>(),
+            vec![Uuid::from_u128(2), Uuid::from_u128(1)]
+        );
+
+        // the note closed on disk is in the trash once, with the time it was closed there
+        let mut registry = NoteRegistry::new(mine.notes.clone(), mine.trash.clone());
+        reload.apply_to(&mut registry, at(5));
+        assert!(registry.notes().is_empty());
+        assert_eq!(registry.trash(), trash.as_slice());
+    }
+
+    #[test]
+    fn watcher_only_notices_the_store_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let watcher = FileWatcher::store(dir.path(), Backend::Json).unwrap();
+
+        fs::write(dir.path().join("md-sticky.log"), "hello").unwrap();
+        thread::sleep(Duration::from_millis(200));
+        assert!(!watcher.is_pending());
+
+        fs::write(dir.path().join(NOTES_FILE), "[]").unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !watcher.is_pending() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(20));
+        }
+
+        assert!(watcher.is_pending());
+        assert!(!watcher.take_settled(Instant::now(), Duration::from_secs(60)));
+        assert!(watcher.take_settled(Instant::now(), Duration::ZERO));
+        assert!(!watcher.is_pending());
+    }
+}
@@ -1,160 +1,363 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use dirs::data_dir;
-use serde::{Deserialize, Serialize};
-use std::cmp;
-use std::collections::HashSet;
+use chrono::Utc;
+use log::LevelFilter;
+use md_sticky_core::cli;
+use md_sticky_core::control::{self, Notes};
+#[cfg(target_os = "linux")]
+use md_sticky_core::dbus::DbusService;
+use md_sticky_core::editor::{self, ExternalEditor};
+use md_sticky_core::error::{LogErr, StickyError};
+use md_sticky_core::folder;
+use md_sticky_core::format::SaveFile;
+use md_sticky_core::geometry::{self, Direction, Position, Rect, Size};
+use md_sticky_core::history::{HistoryRecorder, Revision, RevisionSummary};
+use md_sticky_core::http;
+use md_sticky_core::instance::{self, InstanceLock};
+use md_sticky_core::keybindings::{Keybindings, KEYBINDINGS_FILE};
+use md_sticky_core::link;
+use md_sticky_core::logging::RotatingLogger;
+use md_sticky_core::mirror::{Mirror, MirrorChange};
+use md_sticky_core::model::{self, Note, NoteUpdate};
+use md_sticky_core::palette;
+use md_sticky_core::registry::{Debounce, NoteRegistry};
+use md_sticky_core::settings::Settings;
+use md_sticky_core::sqlite;
+use md_sticky_core::storage::{self, Backend};
+use md_sticky_core::store::{self, FileStore, NoteStore};
+use md_sticky_core::watch::{ExternalChange, ExternalEdits, FileWatcher};
 use std::fs;
-use std::sync::{mpsc, Arc, Mutex};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Mutex, MutexGuard, PoisonError};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use tauri::api::dialog;
 use tauri::{
-    generate_context, AppHandle, CustomMenuItem, Manager, Menu, PhysicalPosition, Submenu, Window,
+    generate_context, AppHandle, CustomMenuItem, GlobalShortcutManager, Manager, Menu,
+    PhysicalPosition, RunEvent, Submenu, SystemTray, SystemTrayEvent, SystemTrayMenu,
+    SystemTrayMenuItem, Window,
 };
-use window_shadows;
+use uuid::Uuid;
 
 const QUIT: &str = "quit";
 const CLOSE_NOTE: &str = "close_note";
 const NEW_NOTE: &str = "new_note";
+const REOPEN_NOTE: &str = "reopen_note";
 const CLEAR_COLORS: &str = "clear_colors";
 
 const SNAP_UP: &str = "snap_up";
 const SNAP_DOWN: &str = "snap_down";
 const SNAP_LEFT: &str = "snap_left";
 const SNAP_RIGHT: &str = "snap_right";
+const PARTIAL_SNAP_UP: &str = "partial_snap_up";
+const PARTIAL_SNAP_DOWN: &str = "partial_snap_down";
+const PARTIAL_SNAP_LEFT: &str = "partial_snap_left";
+const PARTIAL_SNAP_RIGHT: &str = "partial_snap_right";
 const NEXT_WINDOW: &str = "next_window";
 const PREV_WINDOW: &str = "past_window";
 const FIT_TEXT: &str = "fit_text";
+const OPEN_IN_EDITOR: &str = "open_in_editor";
 
-const CUT: &str = "copy";
-const COPY: &str = "cut";
+const TOGGLE_ALL: &str = "toggle_all";
+/// tray items for notes are `tray-note-<id>`
+const TRAY_NOTE_PREFIX: &str = "tray-note-";
+
+const CUT: &str = "cut";
+const COPY: &str = "copy";
 const SELECT_ALL: &str = "select_all";
 const PASTE: &str = "paste";
 
-const MAIN: &str = "main";
+/// the shortcuts of the menu actions, `keybindings.json` can change any of them
+const DEFAULT_KEYBINDINGS: [(&str, Option<&str>); 30] = [
+    (QUIT, Some("CmdOrCtrl+Q")),
+    (CLOSE_NOTE, Some("CmdOrCtrl+W")),
+    (NEW_NOTE, Some("CmdOrCtrl+N")),
+    (REOPEN_NOTE, Some("CmdOrCtrl+Shift+T")),
+    (CLEAR_COLORS, None),
+    (SNAP_UP, Some("CmdOrCtrl+Alt+Up")),
+    (SNAP_DOWN, Some("CmdOrCtrl+Alt+Down")),
+    (SNAP_LEFT, Some("CmdOrCtrl+Alt+Left")),
+    (SNAP_RIGHT, Some("CmdOrCtrl+Alt+Right")),
+    (PARTIAL_SNAP_UP, Some("CmdOrCtrl+Alt+Shift+Up")),
+    (PARTIAL_SNAP_DOWN, Some("CmdOrCtrl+Alt+Shift+Down")),
+    (PARTIAL_SNAP_LEFT, Some("CmdOrCtrl+Alt+Shift+Left")),
+    (PARTIAL_SNAP_RIGHT, Some("CmdOrCtrl+Alt+Shift+Right")),
+    (NEXT_WINDOW, Some("CmdOrCtrl+Slash")),
+    (PREV_WINDOW, Some("CmdOrCtrl+Alt+Slash")),
+    // not `CmdOrCtrl+F`, which is Find everywhere else
+    (FIT_TEXT, Some("CmdOrCtrl+Shift+F")),
+    (OPEN_IN_EDITOR, Some("CmdOrCtrl+E")),
+    (COPY, Some("CmdOrCtrl+C")),
+    (PASTE, Some("CmdOrCtrl+V")),
+    (CUT, Some("CmdOrCtrl+X")),
+    (SELECT_ALL, Some("CmdOrCtrl+A")),
+    ("color_1", Some("CmdOrCtrl+1")),
+    ("color_2", Some("CmdOrCtrl+2")),
+    ("color_3", Some("CmdOrCtrl+3")),
+    ("color_4", Some("CmdOrCtrl+4")),
+    ("color_5", Some("CmdOrCtrl+5")),
+    ("color_6", Some("CmdOrCtrl+6")),
+    ("color_7", Some("CmdOrCtrl+7")),
+    ("color_8", Some("CmdOrCtrl+8")),
+    ("color_9", Some("CmdOrCtrl+9")),
+];
 
-#[cfg(any(windows, target_os = "macos"))]
-fn main() {
-    // here `"quit".to_string()` defines the menu item id, and the second parameter is the menu item label.
-    let quit = CustomMenuItem::new(QUIT, "Quit").accelerator("Cmd+Q");
-    let close_note = CustomMenuItem::new(CLOSE_NOTE, "Close Current Note").accelerator("Cmd+W");
-    let new_note = CustomMenuItem::new(NEW_NOTE, "New Note").accelerator("Cmd+N");
-    let clear_colors = CustomMenuItem::new(CLEAR_COLORS, "Clear Colors");
-    let file_submenu = Submenu::new(
-        "File",
-        Menu::new()
-            .add_item(new_note)
-            .add_item(close_note)
-            .add_item(clear_colors)
-            .add_item(quit),
-    );
+/// label of the quick capture window
+const CAPTURE: &str = "capture";
+const CAPTURE_WIDTH: f64 = 420.0;
+const CAPTURE_HEIGHT: f64 = 48.0;
 
-    let partial_snap_up = CustomMenuItem::new(format!("partial_{}", SNAP_UP), "Partial Snap Up")
-        .accelerator("Cmd+Alt+Shift+Up");
-    let partial_snap_down =
-        CustomMenuItem::new(format!("partial_{}", SNAP_DOWN), "Partial Snap Down")
-            .accelerator("Cmd+Alt+Shift+Down");
-    let partial_snap_left =
-        CustomMenuItem::new(format!("partial_{}", SNAP_LEFT), "Partial Snap Left")
-            .accelerator("Cmd+Alt+Shift+Left");
-    let partial_snap_right =
-        CustomMenuItem::new(format!("partial_{}", SNAP_RIGHT), "Partial Snap Right")
-            .accelerator("Cmd+Alt+Shift+Right");
-    let snap_up = CustomMenuItem::new(SNAP_UP, "Snap Up").accelerator("Cmd+Alt+Up");
-    let snap_down = CustomMenuItem::new(SNAP_DOWN, "Snap Down").accelerator("Cmd+Alt+Down");
-    let snap_left = CustomMenuItem::new(SNAP_LEFT, "Snap Left").accelerator("Cmd+Alt+Left");
-    let snap_right = CustomMenuItem::new(SNAP_RIGHT, "Snap Right").accelerator("Cmd+Alt+Right");
-    let next_window = CustomMenuItem::new(NEXT_WINDOW, "Next Window").accelerator("Cmd+Slash");
-    let prev_window = CustomMenuItem::new(PREV_WINDOW, "Past Window").accelerator("Cmd+Alt+Slash");
-    let fit_text = CustomMenuItem::new(FIT_TEXT, "Fit Text").accelerator("Cmd+F");
-    let window_submenu = Submenu::new(
-        "Window",
-        Menu::new()
-            .add_item(snap_up)
-            .add_item(snap_down)
-            .add_item(snap_left)
-            .add_item(snap_right)
-            .add_item(partial_snap_up)
-            .add_item(partial_snap_down)
-            .add_item(partial_snap_left)
-            .add_item(partial_snap_right)
-            .add_item(next_window)
-            .add_item(prev_window)
-            .add_item(fit_text),
-    );
+/// how often the writer thread checks for unsaved changes
+const SAVE_INTERVAL: Duration = Duration::from_millis(100);
+/// how often the writer thread checks whether the notes need a new revision in their history
+const HISTORY_INTERVAL: Duration = Duration::from_secs(5);
+/// how long the store has to be left alone after another program changed it before the changes are picked up
+const RELOAD_QUIET: Duration = Duration::from_millis(200);
+/// how often a note open in an external editor is checked for saves
+const EDITOR_INTERVAL: Duration = Duration::from_millis(250);
+/// how long to wait for the windows to send their final state when quitting
+const QUIT_TIMEOUT: Duration = Duration::from_secs(1);
 
-    let copy = CustomMenuItem::new(COPY, "Copy").accelerator("Cmd+C");
-    let paste = CustomMenuItem::new(PASTE, "Paste").accelerator("Cmd+V");
-    let cut = CustomMenuItem::new(CUT, "Cut").accelerator("Cmd+X");
-    let select_all = CustomMenuItem::new(SELECT_ALL, "Select All").accelerator("Cmd+A");
-    let edit_submenu = Submenu::new(
-        "Edit",
-        Menu::new()
-            .add_item(copy)
-            .add_item(paste)
-            .add_item(cut)
-            .add_item(select_all),
-    );
+/// the log file is rotated once it reaches this size
+const LOG_SIZE: u64 = 1024 * 1024;
+/// how many rotated log files to keep next to the current one
+const LOG_FILES: usize = 3;
 
-    let mut color_submenu_menu = Menu::new();
+static QUITTING: AtomicBool = AtomicBool::new(false);
 
-    for (i, color) in get_colors().iter().enumerate() {
-        let mut menu_item =
-            CustomMenuItem::new(format!("color_{color}"), format!("Color {}", i + 1));
+/// the menu new windows get, rebuilt when `keybindings.json` changes
+struct AppMenu(Mutex<Menu>);
+
+/// the notes listed in the tray menu, by id and title, so it's only rebuilt when one of them changes
+#[derive(Default)]
+struct TrayNotes(Mutex<Vec<(Uuid, String)>>);
 
-        if i < 9 {
-            menu_item = menu_item.accelerator(format!("Cmd+{}", i + 1))
+/// held while writing notes to disk, so the writer thread and quitting can't overwrite each other's saves
+#[derive(Default)]
+struct SaveLock(Mutex<()>);
+
+fn main() {
+    let context = generate_context!();
+
+    // the menu has to be built before the app exists, so resolve the app data dir from the config the same way `path_resolver().app_data_dir()` does
+    // without it there is nowhere to log to or save notes in, so these are the only errors that stop the app
+    let data_dir = tauri::api::path::app_data_dir(context.config())
+        .expect("Could not resolve app data directory");
+    fs::create_dir_all(&data_dir).expect("Could not create the appdata directory");
+
+    // `md-sticky list` and the other commands run without opening the app, see `cli::USAGE`
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    if cli::is_command(&args) {
+        std::process::exit(cli::main(&args, &data_dir));
+    }
+
+    // a second launch hands its arguments to the running app, instead of opening every note again and saving over it
+    let _instance = match InstanceLock::acquire(&data_dir) {
+        Ok(Some(lock)) => Some(lock),
+        Ok(None) => match instance::hand_over(&data_dir, args.clone()) {
+            Ok(Some(lock)) => Some(lock),
+            Ok(None) => std::process::exit(0),
+            Err(e) => {
+                eprintln!("md-sticky: {e}");
+                std::process::exit(1);
+            }
+        },
+        Err(e) => {
+            eprintln!("could not check for a running md-sticky, starting anyway: {e}");
+            None
         }
+    };
 
-        color_submenu_menu = color_submenu_menu.add_item(menu_item);
+    // `md-sticky://` links the app was started with, the desktop opens them this way when it isn't running yet
+    let links = args
+        .into_iter()
+        .filter(|arg| link::is_link(arg))
+        .collect::<Vec<String>>();
+
+    if let Err(e) = RotatingLogger::new(&data_dir, LOG_SIZE, LOG_FILES, LevelFilter::Info).init() {
+        eprintln!("could not set up logging: {e}");
     }
 
-    let color_submenu = Submenu::new("Color", color_submenu_menu);
+    let default_panic_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        log::error!("{info}");
+        default_panic_hook(info);
+    }));
 
-    let menu = Menu::new()
-        .add_submenu(file_submenu)
-        .add_submenu(edit_submenu)
-        .add_submenu(window_submenu)
-        .add_submenu(color_submenu);
+    log::info!("starting md-sticky {}", context.package_info().version);
+
+    // a broken keybindings.json is reported once the app is up and can show a dialog
+    let (keybindings, keybindings_error) = match Keybindings::load(&data_dir, &DEFAULT_KEYBINDINGS)
+    {
+        Ok(keybindings) => (keybindings, None),
+        Err(e) => (Keybindings::new(&DEFAULT_KEYBINDINGS), Some(e)),
+    };
+    let menu = build_menu(&keybindings, &data_dir);
 
     tauri::Builder::default()
         .setup(move |app| {
-            let path_buf = app
-                .handle()
-                .path_resolver()
-                .app_data_dir()
-                .expect("Could not resolve appdata directory");
+            let app_data_path = data_dir.as_path();
+
+            if let Some(e) = keybindings_error {
+                keybindings_failed(&e, "The default shortcuts are used until it is fixed.");
+            }
+
+            let settings = Settings::load(app_data_path)
+                .log_err("could not read the settings file, using the defaults")
+                .unwrap_or_default();
+
+            let mut backend = settings.storage;
+            let mut note_store: Box<dyn NoteStore> =
+                match store::open(app_data_path, backend, &settings.backups) {
+                    Ok(note_store) => note_store,
+                    Err(e) => {
+                        store_failed(&e, backend);
+                        backend = Backend::Json;
+                        Box::new(FileStore::new(
+                            app_data_path,
+                            backend,
+                            settings.backups.clone(),
+                        ))
+                    }
+                };
+
+            let save_file = match note_store.load() {
+                Ok(save_file) => save_file,
+                Err(e) => {
+                    load_failed(&e, backend);
+                    SaveFile::new(Vec::new())
+                }
+            };
+
+            let watcher = FileWatcher::store(app_data_path, settings.storage)
+                .log_err("could not watch the notes for changes from other programs");
 
-            let app_data_path = path_buf.as_path();
+            let mirror = settings.export_dir.as_ref().and_then(|export_dir| {
+                Mirror::open(export_dir, app_data_path).log_err("could not open the export folder")
+            });
+            let keybindings_watcher = FileWatcher::file(&app_data_path.join(KEYBINDINGS_FILE))
+                .log_err("could not watch the keybindings, changes to them need a restart");
+
+            let export_watcher = mirror.as_ref().and_then(|mirror| {
+                FileWatcher::markdown_folder(mirror.export_dir())
+                    .log_err("could not watch the export folder")
+            });
 
-            if !app_data_path.exists() {
-                fs::create_dir(app_data_path).expect("Could not create the appdata directory");
+            app.manage(Mutex::new(ExternalEdits::new(&save_file)));
+            app.manage(Mutex::new(NoteRegistry::new(
+                save_file.notes.clone(),
+                save_file.trash,
+            )));
+            app.manage(Mutex::new(HistoryRecorder::new(settings.history.clone())));
+            app.manage(Mutex::new(note_store));
+            app.manage(Mutex::new(mirror));
+            let http_port = settings.http_port;
+            let capture_shortcut = settings.capture.shortcut.clone();
+            app.manage(settings);
+
+            let handle = app.handle();
+            control::listen(app_data_path, move |request| {
+                control::execute(request, &mut Windows(handle.clone()))
+            })
+            .log_err("could not open the control socket, commands will change the notes on disk");
+
+            if let Some(port) = http_port {
+                let handle = app.handle();
+                http::token(app_data_path)
+                    .and_then(|token| {
+                        http::serve(port, token, move |request| {
+                            control::execute(request, &mut Windows(handle.clone()))
+                        })
+                    })
+                    .log_err("could not start the HTTP API");
+            }
+
+            if let Some(shortcut) = capture_shortcut {
+                let handle = app.handle();
+                app.global_shortcut_manager()
+                    .register(&shortcut, move || {
+                        let handle = handle.clone();
+                        thread::spawn(move || {
+                            open_capture(&handle).log_err("could not open the capture window");
+                        });
+                    })
+                    .log_err(&format!(
+                        "could not register the capture shortcut {shortcut}"
+                    ));
             }
 
-            let notes = read_contents(app.handle()).expect("Could not read save file");
+            #[cfg(target_os = "linux")]
+            let dbus = {
+                let handle = app.handle();
+                DbusService::start(move |request| {
+                    control::execute(request, &mut Windows(handle.clone()))
+                })
+                .log_err("could not register on the session bus")
+            };
+
+            purge_trash(&app.handle());
+            // picks up what was edited in the export folder while the app was closed
+            sync_export_folder(&app.handle());
 
+            let notes = registry(&app.handle()).notes().to_vec();
             notes.into_iter().for_each(|note| {
-                let window = create_new_sticky(app.handle());
-                let window_clone = window.clone();
-                window.once("ready", move |_event| {
-                    window_clone
-                        .emit("init", note)
-                        .expect("Error emitting init event");
-                });
+                open_note(app.handle(), note).log_err("could not open a saved note");
             });
 
+            for link in links {
+                follow_link(&app.handle(), &link);
+            }
+
+            update_tray(&app.handle());
+
             let handle_clone = app.handle().clone();
-            let mut save_fail_ct = 0;
-            thread::spawn(move || loop {
-                thread::sleep(Duration::from_millis(500));
-                if let Err(_) = save_notes(&handle_clone) {
-                    save_fail_ct += 1;
-                } else {
-                    save_fail_ct = 0;
-                }
-                if save_fail_ct > 2 {
-                    handle_clone.restart();
+            thread::spawn(move || {
+                let mut last_history = Instant::now();
+
+                loop {
+                    thread::sleep(SAVE_INTERVAL);
+
+                    if let Some(keybindings_watcher) = &keybindings_watcher {
+                        if keybindings_watcher.take_settled(Instant::now(), RELOAD_QUIET) {
+                            reload_keybindings(&handle_clone);
+                        }
+                    }
+
+                    if let Some(export_watcher) = &export_watcher {
+                        if export_watcher.take_settled(Instant::now(), RELOAD_QUIET) {
+                            sync_export_folder(&handle_clone);
+                        }
+                    }
+
+                    if let Some(watcher) = &watcher {
+                        if watcher.take_settled(Instant::now(), RELOAD_QUIET) {
+                            reload_notes(&handle_clone);
+                        }
+
+                        // don't save over files another program is still writing
+                        if watcher.is_pending() {
+                            continue;
+                        }
+                    }
+
+                    if save_notes(&handle_clone) {
+                        // every note that was created, closed or renamed has been saved by now
+                        update_tray(&handle_clone);
+
+                        // lets panel applets refresh their list
+                        #[cfg(target_os = "linux")]
+                        if let Some(dbus) = &dbus {
+                            dbus.notes_changed()
+                                .log_err("could not signal NotesChanged");
+                        }
+                    }
+
+                    if last_history.elapsed() >= HISTORY_INTERVAL {
+                        record_history(&handle_clone);
+                        last_history = Instant::now();
+                    }
                 }
             });
 
@@ -162,76 +365,76 @@ fn main() {
         })
         .invoke_handler(tauri::generate_handler![
             add_color,
+            capture,
             get_colors,
-            remove_window
+            list_revisions,
+            preview_revision,
+            remove_window,
+            restore_revision,
+            update_note
         ])
-        .manage(Mutex::new(0 as u32))
-        .manage(Mutex::new(Vec::<String>::new()))
+        .manage(Mutex::new(Vec::<Uuid>::new()))
+        .manage(SaveLock::default())
+        .manage(TrayNotes::default())
+        .manage(AppMenu(Mutex::new(menu.clone())))
         .menu(menu)
+        .system_tray(SystemTray::new().with_menu(tray_menu(&[])))
+        .on_system_tray_event(|app_handle, event| {
+            if let SystemTrayEvent::MenuItemClick { id, .. } = event {
+                tray_item_clicked(app_handle, &id);
+            }
+        })
         .on_menu_event(|event| match event.menu_item_id() {
-            QUIT => std::process::exit(0),
+            QUIT => quit(event.window().app_handle()),
             NEW_NOTE => {
                 std::thread::spawn(move || {
-                    create_new_sticky(event.window().app_handle());
+                    new_note(event.window().app_handle()).log_err("could not create a note");
+                });
+            }
+            REOPEN_NOTE => {
+                std::thread::spawn(move || {
+                    reopen_note(event.window().app_handle()).log_err("could not reopen the note");
                 });
             }
             CLOSE_NOTE => {
                 if let Some(focused_window) = event.window().get_focused_window() {
-                    let window_label = focused_window.label();
-
-                    if window_label != MAIN {
-                        remove_window(window_label.to_string(), event.window().app_handle())
+                    if let Some(id) = model::note_id(focused_window.label()) {
+                        remove_window(id, event.window().app_handle())
+                            .log_err("could not close the note");
                     }
                 }
             }
             CLEAR_COLORS => {
-                let path_buf = event
-                    .window()
-                    .app_handle()
-                    .path_resolver()
-                    .app_data_dir()
-                    .expect("could not resolve app data directory")
-                    .join("colors.json");
-
-                let file_path = path_buf.as_path();
-
-                fs::remove_file(file_path).expect("Could not remove colors save file");
+                app_data_dir(&event.window().app_handle())
+                    .and_then(|dir| palette::clear_colors(&dir))
+                    .log_err("could not remove colors save file");
             }
             m if [CUT, COPY, PASTE, SELECT_ALL].contains(&m) => {
                 if let Some(focused_window) = event.window().get_focused_window() {
                     focused_window
                         .emit(m, {})
-                        .expect("could not send copy event");
+                        .log_err("could not send clipboard event");
                 }
             }
             m if [SNAP_DOWN, SNAP_UP, SNAP_LEFT, SNAP_RIGHT].contains(&m) => {
                 if let Some(focused_window) = event.window().get_focused_window() {
-                    snap_window(focused_window, m);
+                    snap_window(focused_window, m).log_err("could not snap window");
                 }
             }
             m if m.starts_with("partial_") => {
                 if let Some(focused_window) = event.window().get_focused_window() {
-                    partial_snap_window(focused_window, m.strip_prefix("partial_").unwrap());
+                    partial_snap_window(focused_window, &m["partial_".len()..])
+                        .log_err("could not snap window");
                 }
             }
             NEXT_WINDOW => {
                 if let Some(focused_window) = event.window().get_focused_window() {
-                    let mut collect = event
-                        .window()
-                        .app_handle()
-                        .windows()
-                        .into_iter()
-                        .filter(|(label, _)| label != MAIN)
-                        .collect::<Vec<(String, Window)>>();
-
-                    collect.sort_by(|a, b| {
-                        (a.1.outer_position().unwrap().y).cmp(&b.1.outer_position().unwrap().y)
-                    });
+                    let collect = windows_top_to_bottom(&event.window().app_handle());
 
                     let mut next = false;
-                    for (_label, window) in collect.iter().cycle() {
+                    for window in collect.iter().cycle().take(collect.len() * 2) {
                         if next {
-                            window.set_focus().expect("Could not set focused");
+                            window.set_focus().log_err("could not focus window");
                             break;
                         }
                         if window.label() == focused_window.label() {
@@ -242,30 +445,19 @@ fn main() {
             }
             PREV_WINDOW => {
                 if let Some(focused_window) = event.window().get_focused_window() {
-                    let mut prev_window: Option<Window> = None;
-
-                    let mut collect = event
-                        .window()
-                        .app_handle()
-                        .windows()
-                        .into_iter()
-                        .filter(|(label, _)| label != MAIN)
-                        .collect::<Vec<(String, Window)>>();
-
-                    collect.sort_by(|a, b| {
-                        (a.1.outer_position().unwrap().y).cmp(&b.1.outer_position().unwrap().y)
-                    });
-
-                    for (_label, window) in collect.iter().cycle() {
-                        if window.label() == focused_window.label() && prev_window.is_some() {
-                            prev_window
-                                .unwrap()
-                                .set_focus()
-                                .expect("Could not set focused");
-                            break;
+                    let mut prev_window: Option<&Window> = None;
+
+                    let collect = windows_top_to_bottom(&event.window().app_handle());
+
+                    for window in collect.iter().cycle().take(collect.len() * 2) {
+                        if window.label() == focused_window.label() {
+                            if let Some(prev_window) = prev_window {
+                                prev_window.set_focus().log_err("could not focus window");
+                                break;
+                            }
                         }
 
-                        prev_window = Some(window.clone());
+                        prev_window = Some(window);
                     }
                 }
             }
@@ -273,7 +465,21 @@ fn main() {
                 if let Some(focused_window) = event.window().get_focused_window() {
                     focused_window
                         .emit(FIT_TEXT, {})
-                        .expect("Could not emit event!")
+                        .log_err("could not emit fit_text event");
+                }
+            }
+            OPEN_IN_EDITOR => {
+                if let Some(focused_window) = event.window().get_focused_window() {
+                    if let Some(id) = model::note_id(focused_window.label()) {
+                        if let Err(e) = open_in_editor(&event.window().app_handle(), id) {
+                            log::error!("could not open note {id} in an external editor: {e}");
+                            dialog::message(
+                                Some(&focused_window),
+                                "md-sticky",
+                                format!("The note could not be opened in an external editor: {e}"),
+                            );
+                        }
+                    }
                 }
             }
 
@@ -281,7 +487,7 @@ fn main() {
                 if let Some(focused_window) = event.window().get_focused_window() {
                     focused_window
                         .emit("set_color", m.strip_prefix("color_"))
-                        .expect("Could not emit event!")
+                        .log_err("could not emit set_color event");
                 }
             }
 
@@ -289,226 +495,358 @@ fn main() {
         })
         .on_window_event(|event| match event.event() {
             tauri::WindowEvent::Focused(focused) => {
-                window_shadows::set_shadow(event.window(), *focused).unwrap();
+                set_shadow(event.window(), *focused);
             }
             _ => {}
         })
-        .run(generate_context!())
+        .build(context)
         .expect("error while building tauri application")
+        .run(|app_handle, event| match event {
+            RunEvent::ExitRequested { api, .. } => {
+                api.prevent_exit();
+                quit(app_handle.clone());
+            }
+            // macos hands links to the app in an event rather than its arguments, when it starts the app too
+            #[cfg(target_os = "macos")]
+            RunEvent::Opened { urls } => {
+                let app_handle = app_handle.clone();
+                // the event comes in on the main thread, which opening windows needs free
+                thread::spawn(move || {
+                    for url in urls.iter().filter(|url| url.scheme() == link::SCHEME) {
+                        follow_link(&app_handle, url.as_str());
+                    }
+                });
+            }
+            _ => {}
+        })
 }
 
-fn snap_window<'a>(window: Window, direction: &str) {
-    let window_position = window.outer_position().unwrap();
-    let window_size = window.outer_size().unwrap();
-    let current_monitor = window
-        .current_monitor()
-        .unwrap()
-        .expect("monitor could not be detected");
+fn store_failed(e: &StickyError, backend: Backend) {
+    log::error!(
+        "could not open the {backend:?} note store, using {}: {e}",
+        storage::NOTES_FILE
+    );
+    dialog::message(
+        None::<&Window>,
+        "md-sticky",
+        format!(
+            "Your notes could not be opened: {e}\n\nUntil this is fixed, notes are saved to {}",
+            storage::NOTES_FILE
+        ),
+    );
+}
 
-    let positions = get_window_levels(&window);
+fn load_failed(e: &StickyError, backend: Backend) {
+    log::error!("could not read the save file, starting without notes: {e}");
+
+    // what became of the notes that couldn't be read
+    let kept = match backend {
+        Backend::Json => format!(
+            "The unreadable save file was kept as {}",
+            storage::CORRUPT_NOTES_FILE
+        ),
+        Backend::Markdown => format!(
+            "Files in the {} folder that can't be read were renamed to <name>.md.corrupt",
+            folder::NOTES_DIR
+        ),
+        Backend::Sqlite => format!("{} was left as it is", sqlite::DATABASE_FILE),
+    };
+    dialog::message(
+        None::<&Window>,
+        "md-sticky",
+        format!("Your notes could not be read: {e}\n\n{kept}"),
+    );
+}
 
-    window
-        .set_position(match direction {
-            SNAP_LEFT => PhysicalPosition {
-                x: positions
-                    .iter()
-                    .filter_map(|(_label, window)| {
-                        let position = window.outer_position().unwrap();
-                        let size = window.outer_size().unwrap();
-
-                        if window_overlap(
-                            position.y,
-                            size.height as i32,
-                            window_position.y,
-                            window_size.height as i32,
-                        ) {
-                            Some(position.x + size.width as i32)
-                        } else {
-                            None
-                        }
-                    })
-                    .filter(|edge| *edge < window_position.x as i32)
-                    .max()
-                    .unwrap_or(0)
-                    + 20,
-                y: window_position.y,
-            },
-
-            SNAP_UP => PhysicalPosition {
-                x: window_position.x,
-
-                y: positions
-                    .iter()
-                    .filter_map(|(_label, window)| {
-                        let position = window.outer_position().unwrap();
-                        let size = window.outer_size().unwrap();
-
-                        if window_overlap(
-                            position.x,
-                            size.width as i32,
-                            window_position.x,
-                            window_size.width as i32,
-                        ) {
-                            Some(position.y + size.height as i32)
-                        } else {
-                            None
-                        }
-                    })
-                    .filter(|edge| *edge < window_position.y as i32)
-                    .max()
-                    .unwrap_or(0)
-                    + 20,
-            },
-
-            SNAP_RIGHT => PhysicalPosition {
-                x: positions
-                    .iter()
-                    .filter_map(|(_label, window)| {
-                        let position = window.outer_position().unwrap();
-                        let size = window.outer_size().unwrap();
-
-                        if window_overlap(
-                            position.y,
-                            size.height as i32,
-                            window_position.y,
-                            window_size.height as i32,
-                        ) {
-                            Some(position.x - window_size.width as i32)
-                        } else {
-                            None
-                        }
-                    })
-                    .filter(|edge| *edge > window_position.x as i32)
-                    .min()
-                    .unwrap_or((current_monitor.size().width - window_size.width) as i32)
-                    - 20,
-                y: window_position.y,
-            },
-
-            SNAP_DOWN => PhysicalPosition {
-                x: window_position.x,
-                y: positions
-                    .iter()
-                    .filter_map(|(_label, window)| {
-                        let position = window.outer_position().unwrap();
-                        let size = window.outer_size().unwrap();
-
-                        if window_overlap(
-                            position.x,
-                            size.width as i32,
-                            window_position.x,
-                            window_size.width as i32,
-                        ) {
-                            Some(position.y - window_size.height as i32)
-                        } else {
-                            None
-                        }
-                    })
-                    .filter(|edge| *edge > window_position.y as i32)
-                    .min()
-                    .unwrap_or((current_monitor.size().height - window_size.height) as i32)
-                    - 20,
-            },
+/// Does what an `md-sticky://` link asks for.
+fn follow_link(app_handle: &AppHandle, link: &str) {
+    link::parse(link)
+        .and_then(|request| control::execute(request, &mut Windows(app_handle.clone())))
+        .log_err("could not follow a link");
+}
 
-            _ => PhysicalPosition { x: 0, y: 0 },
-        })
-        .expect("Could not set window position")
+/// The app menu with the shortcuts from `keybindings`. The accelerators of the menu items are the app's shortcuts,
+/// on linux too where the menu itself is hidden.
+fn build_menu(keybindings: &Keybindings, app_data_dir: &Path) -> Menu {
+    // the first parameter is the menu item id, and the second parameter is the menu item label.
+    let item = |id: &str, title: &str| {
+        let item = CustomMenuItem::new(id, title);
+
+        match keybindings.get(id) {
+            Some(accelerator) => item.accelerator(accelerator),
+            None => item,
+        }
+    };
+
+    let file_submenu = Submenu::new(
+        "File",
+        Menu::new()
+            .add_item(item(NEW_NOTE, "New Note"))
+            .add_item(item(CLOSE_NOTE, "Close Current Note"))
+            .add_item(item(REOPEN_NOTE, "Reopen Closed Note"))
+            .add_item(item(CLEAR_COLORS, "Clear Colors"))
+            .add_item(item(QUIT, "Quit")),
+    );
+
+    let window_submenu = Submenu::new(
+        "Window",
+        Menu::new()
+            .add_item(item(SNAP_UP, "Snap Up"))
+            .add_item(item(SNAP_DOWN, "Snap Down"))
+            .add_item(item(SNAP_LEFT, "Snap Left"))
+            .add_item(item(SNAP_RIGHT, "Snap Right"))
+            .add_item(item(PARTIAL_SNAP_UP, "Partial Snap Up"))
+            .add_item(item(PARTIAL_SNAP_DOWN, "Partial Snap Down"))
+            .add_item(item(PARTIAL_SNAP_LEFT, "Partial Snap Left"))
+            .add_item(item(PARTIAL_SNAP_RIGHT, "Partial Snap Right"))
+            .add_item(item(NEXT_WINDOW, "Next Window"))
+            .add_item(item(PREV_WINDOW, "Past Window"))
+            .add_item(item(FIT_TEXT, "Fit Text"))
+            .add_item(item(OPEN_IN_EDITOR, "Open in External Editor")),
+    );
+
+    let edit_submenu = Submenu::new(
+        "Edit",
+        Menu::new()
+            .add_item(item(COPY, "Copy"))
+            .add_item(item(PASTE, "Paste"))
+            .add_item(item(CUT, "Cut"))
+            .add_item(item(SELECT_ALL, "Select All")),
+    );
+
+    let mut color_submenu_menu = Menu::new();
+
+    let colors = palette::get_colors(app_data_dir)
+        .log_err("could not read the color palette")
+        .unwrap_or_else(palette::default_colors);
+
+    for (i, color) in colors.iter().enumerate() {
+        let mut menu_item =
+            CustomMenuItem::new(format!("color_{color}"), format!("Color {}", i + 1));
+
+        if let Some(accelerator) = keybindings.color(i) {
+            menu_item = menu_item.accelerator(accelerator)
+        }
+
+        color_submenu_menu = color_submenu_menu.add_item(menu_item);
+    }
+
+    let color_submenu = Submenu::new("Color", color_submenu_menu);
+
+    Menu::new()
+        .add_submenu(file_submenu)
+        .add_submenu(edit_submenu)
+        .add_submenu(window_submenu)
+        .add_submenu(color_submenu)
+}
+
+/// Rebuilds the menu after `keybindings.json` changed. Tauri can't change the menu of a window that is already open,
+/// so the new shortcuts are used by the notes opened from now on, and by every note after a restart.
+fn reload_keybindings(app_handle: &AppHandle) {
+    let app_data_dir = match app_data_dir(app_handle).log_err("could not reload the keybindings") {
+        Some(app_data_dir) => app_data_dir,
+        None => return,
+    };
+
+    match Keybindings::load(&app_data_dir, &DEFAULT_KEYBINDINGS) {
+        Ok(keybindings) => {
+            *lock(&app_handle.state::<AppMenu>().inner().0) =
+                build_menu(&keybindings, &app_data_dir);
+            log::info!(
+                "reloaded {KEYBINDINGS_FILE}, notes opened from now on use the new shortcuts"
+            );
+
+            // tauri can't change the menu of a window that is already open
+            if app_handle
+                .windows()
+                .keys()
+                .any(|label| model::note_id(label).is_some())
+            {
+                dialog::message(
+                    None::<&Window>,
+                    "md-sticky",
+                    format!("The shortcuts from {KEYBINDINGS_FILE} work in the notes opened from now on.\n\nNotes that are already open keep the old shortcuts until they are closed and opened again, or md-sticky is restarted."),
+                );
+            }
+        }
+        Err(e) => keybindings_failed(&e, "The shortcuts stay as they were until it is fixed."),
+    }
+}
+
+fn keybindings_failed(e: &StickyError, consequence: &str) {
+    log::error!("could not load {KEYBINDINGS_FILE}: {e}");
+    dialog::message(
+        None::<&Window>,
+        "md-sticky",
+        format!("{e}\n\n{consequence}"),
+    );
+}
+
+fn tray_menu(notes: &[(Uuid, String)]) -> SystemTrayMenu {
+    let mut menu = SystemTrayMenu::new()
+        .add_item(CustomMenuItem::new(NEW_NOTE, "New Note"))
+        .add_item(CustomMenuItem::new(TOGGLE_ALL, "Show/Hide All Notes"))
+        .add_native_item(SystemTrayMenuItem::Separator);
+
+    for (id, title) in notes {
+        menu = menu.add_item(CustomMenuItem::new(
+            format!("{TRAY_NOTE_PREFIX}{id}"),
+            title,
+        ));
+    }
+    if !notes.is_empty() {
+        menu = menu.add_native_item(SystemTrayMenuItem::Separator);
+    }
+
+    menu.add_item(CustomMenuItem::new(REOPEN_NOTE, "Reopen Closed Note"))
+        .add_item(CustomMenuItem::new(QUIT, "Quit"))
+}
+
+/// Rebuilds the tray menu if a note was created, closed or renamed since it was last built.
+fn update_tray(app_handle: &AppHandle) {
+    let notes = registry(app_handle)
+        .notes()
+        .iter()
+        .map(|note| (note.id, note.menu_title()))
+        .collect::<Vec<(Uuid, String)>>();
+
+    let mut listed = lock(&app_handle.state::<TrayNotes>().inner().0);
+    if *listed == notes {
+        return;
+    }
+
+    app_handle
+        .tray_handle()
+        .set_menu(tray_menu(&notes))
+        .log_err("could not update the tray menu");
+    *listed = notes;
+}
+
+fn tray_item_clicked(app_handle: &AppHandle, id: &str) {
+    let app_handle = app_handle.clone();
+
+    // like the menu handlers, tray events are handled on the main thread, which can't open windows
+    match id {
+        QUIT => quit(app_handle),
+        NEW_NOTE => {
+            thread::spawn(move || {
+                new_note(app_handle).log_err("could not create a note");
+            });
+        }
+        REOPEN_NOTE => {
+            thread::spawn(move || {
+                reopen_note(app_handle).log_err("could not reopen the note");
+            });
+        }
+        TOGGLE_ALL => {
+            toggle_all_visible(&app_handle).log_err("could not show or hide the notes");
+        }
+        id => {
+            if let Some(id) = id
+                .strip_prefix(TRAY_NOTE_PREFIX)
+                .and_then(|id| Uuid::parse_str(id).ok())
+            {
+                focus_note(&app_handle, id).log_err("could not focus the note");
+            }
+        }
+    }
+}
+
+#[cfg(any(windows, target_os = "macos"))]
+fn set_shadow(window: &Window, enabled: bool) {
+    window_shadows::set_shadow(window, enabled).log_err("could not set window shadow");
+}
+
+// window-shadows only supports windows and macos, the window manager draws shadows everywhere else
+#[cfg(not(any(windows, target_os = "macos")))]
+fn set_shadow(_window: &Window, _enabled: bool) {}
+
+/// Locks a mutex even if a thread panicked while holding it, everything we keep behind one stays usable.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn registry(app_handle: &AppHandle) -> MutexGuard<'_, NoteRegistry> {
+    lock(app_handle.state::<Mutex<NoteRegistry>>().inner())
+}
+
+// have to keep track of this bc if we get the list of windows through the app_handle, messages are sometimes sent to windows that dont have listeners attached yet
+fn windows_ready(app_handle: &AppHandle) -> MutexGuard<'_, Vec<Uuid>> {
+    lock(app_handle.state::<Mutex<Vec<Uuid>>>().inner())
+}
+
+fn history_recorder(app_handle: &AppHandle) -> MutexGuard<'_, HistoryRecorder> {
+    lock(app_handle.state::<Mutex<HistoryRecorder>>().inner())
+}
+
+fn note_store(app_handle: &AppHandle) -> MutexGuard<'_, Box<dyn NoteStore>> {
+    lock(app_handle.state::<Mutex<Box<dyn NoteStore>>>().inner())
+}
+
+fn external_edits(app_handle: &AppHandle) -> MutexGuard<'_, ExternalEdits> {
+    lock(app_handle.state::<Mutex<ExternalEdits>>().inner())
+}
+
+// `None` unless the export_dir setting is set
+fn mirror(app_handle: &AppHandle) -> MutexGuard<'_, Option<Mirror>> {
+    lock(app_handle.state::<Mutex<Option<Mirror>>>().inner())
+}
+
+fn snap_window(window: Window, direction: &str) -> Result<(), StickyError> {
+    move_window(window, direction, geometry::snap_position)
+}
+
+fn partial_snap_window(window: Window, direction: &str) -> Result<(), StickyError> {
+    move_window(window, direction, geometry::partial_snap_position)
 }
 
-fn partial_snap_window(window: Window, direction: &str) {
-    let window_position = window.outer_position().unwrap();
-    let window_size = window.outer_size().unwrap();
+fn move_window(
+    window: Window,
+    direction: &str,
+    position: fn(Rect, &[Rect], Size, Direction) -> Position,
+) -> Result<(), StickyError> {
+    let direction = match direction {
+        SNAP_UP => Direction::Up,
+        SNAP_DOWN => Direction::Down,
+        SNAP_LEFT => Direction::Left,
+        SNAP_RIGHT => Direction::Right,
+        _ => return Ok(()),
+    };
+
     let current_monitor = window
         .current_monitor()
-        .unwrap()
-        .expect("monitor could not be detected");
+        .map_err(StickyError::window)?
+        .ok_or_else(|| StickyError::window("monitor could not be detected"))?;
+    let monitor = Size {
+        width: current_monitor.size().width,
+        height: current_monitor.size().height,
+    };
 
-    let positions = get_window_levels(&window);
+    let others = get_window_levels(&window)
+        .iter()
+        .map(|(_label, window)| window_rect(window))
+        .collect::<Result<Vec<Rect>, StickyError>>()?;
 
-    window
-        .set_position(match direction {
-            SNAP_LEFT => PhysicalPosition {
-                x: positions
-                    .iter()
-                    .map(|(_label, window)| {
-                        let position = window.outer_position().unwrap();
-                        let size = window.outer_size().unwrap();
-
-                        vec![position.x, position.x + size.width as i32]
-                    })
-                    .flatten()
-                    .filter(|position| *position < window_position.x)
-                    .max()
-                    .unwrap_or(20),
-                y: window_position.y,
-            },
-
-            SNAP_UP => PhysicalPosition {
-                x: window_position.x,
-                y: positions
-                    .iter()
-                    .map(|(_label, window)| {
-                        let position = window.outer_position().unwrap();
-                        let size = window.outer_size().unwrap();
-
-                        vec![position.y, position.y + size.height as i32]
-                    })
-                    .flatten()
-                    .filter(|position| *position < window_position.y)
-                    .max()
-                    .unwrap_or(20),
-            },
-
-            SNAP_RIGHT => PhysicalPosition {
-                x: positions
-                    .iter()
-                    .map(|(_label, window)| {
-                        let position = window.outer_position().unwrap();
-                        let size = window.outer_size().unwrap();
-
-                        vec![
-                            (position.x + size.width as i32) - window_size.width as i32,
-                            position.x - window_size.width as i32,
-                        ]
-                    })
-                    .flatten()
-                    .filter(|position| {
-                        let size = window.outer_size().unwrap();
+    let new_position = position(window_rect(&window)?, &others, monitor, direction);
 
-                        *position + size.width as i32 > window_position.x + window_size.width as i32
-                    })
-                    .min()
-                    .unwrap_or((current_monitor.size().width - window_size.width) as i32 - 20),
-                y: window_position.y,
-            },
-
-            SNAP_DOWN => PhysicalPosition {
-                x: window_position.x,
-                y: positions
-                    .iter()
-                    .map(|(_label, window)| {
-                        let position = window.outer_position().unwrap();
-                        let size = window.outer_size().unwrap();
-
-                        vec![
-                            (position.y + size.height as i32) - window_size.height as i32,
-                            position.y - window_size.height as i32,
-                        ]
-                    })
-                    .flatten()
-                    .filter(|position| {
-                        let size = window.outer_size().unwrap();
+    window
+        .set_position(PhysicalPosition {
+            x: new_position.x,
+            y: new_position.y,
+        })
+        .map_err(StickyError::window)
+}
 
-                        *position + size.height as i32
-                            > window_position.y + window_size.height as i32
-                    })
-                    .min()
-                    .unwrap_or((current_monitor.size().height - window_size.height) as i32 - 20),
-            },
+fn window_rect(window: &Window) -> Result<Rect, StickyError> {
+    let position = window.outer_position().map_err(StickyError::window)?;
+    let size = window.outer_size().map_err(StickyError::window)?;
 
-            _ => PhysicalPosition { x: 0, y: 0 },
-        })
-        .expect("Could not set window position")
+    Ok(Rect {
+        x: position.x,
+        y: position.y,
+        width: size.width,
+        height: size.height,
+    })
 }
 
 fn get_window_levels(window: &Window) -> Vec<(String, Window)> {
@@ -516,236 +854,690 @@ fn get_window_levels(window: &Window) -> Vec<(String, Window)> {
         .app_handle()
         .windows()
         .into_iter()
-        .filter(|(label, _)| *label != MAIN && *label != window.label())
+        .filter(|(label, _)| model::note_id(label).is_some() && *label != window.label())
         .collect()
 }
 
-fn window_overlap(start_1: i32, len_1: i32, start_2: i32, len_2: i32) -> bool {
-    let end_1 = start_1 + len_1;
-    let end_2 = start_2 + len_2;
+/// Every note window, sorted by how far down the screen it is.
+fn windows_top_to_bottom(app_handle: &AppHandle) -> Vec<Window> {
+    let mut windows = app_handle
+        .windows()
+        .into_iter()
+        .filter(|(label, _)| model::note_id(label).is_some())
+        .filter_map(|(_label, window)| Some((window.outer_position().ok()?.y, window)))
+        .collect::<Vec<(i32, Window)>>();
 
-    let overlap_start = cmp::max(start_1, start_2);
-    let overlap_end = cmp::min(end_1, end_2);
-    overlap_end - overlap_start > 20 // 20px is the gap value
-}
+    windows.sort_by_key(|(y, _)| *y);
 
-fn create_new_sticky(handle: AppHandle) -> tauri::Window {
-    let wc_binding = handle.state::<Mutex<u32>>();
-    let mut window_count = wc_binding
-        .lock()
-        .expect("error obtaining lock for window count mutex");
-    *window_count += 1;
+    windows.into_iter().map(|(_y, window)| window).collect()
+}
 
-    let window_label = format!("new_sticky_window_{}", window_count);
+fn app_data_dir(app_handle: &AppHandle) -> Result<PathBuf, StickyError> {
+    app_handle
+        .path_resolver()
+        .app_data_dir()
+        .ok_or(StickyError::NoAppDataDir)
+}
 
+fn create_new_sticky(handle: AppHandle, id: Uuid) -> Result<Window, StickyError> {
     let window = tauri::WindowBuilder::new(
         &handle,
-        window_label.clone(),
+        model::window_label(id),
         tauri::WindowUrl::App("index.html".into()),
     )
+    .menu(lock(&handle.state::<AppMenu>().inner().0).clone())
     .decorations(false)
     .resizable(true)
     .visible(false)
-    .inner_size(300.0, 250.0)
+    .inner_size(model::DEFAULT_WIDTH as f64, model::DEFAULT_HEIGHT as f64)
     .build()
-    .expect("Failed to create window");
+    .map_err(StickyError::window)?;
+
+    log::info!("opened note {id}");
+
+    // on linux the menu is drawn inside every window, keep it hidden so only the accelerators are active
+    #[cfg(target_os = "linux")]
+    window
+        .menu_handle()
+        .hide()
+        .log_err("could not hide the window menu");
 
     let handle_clone = handle.clone();
-    let window_label_clone = window_label.clone();
     window.once("ready", move |_| {
-        let wr_binding = handle_clone.state::<Mutex<Vec<String>>>();
-        let mut windows_ready = wr_binding
-            .lock()
-            .expect("error obtaining lock for windows ready mutex");
-
-        windows_ready.push(window_label_clone);
+        windows_ready(&handle_clone).push(id);
     });
 
-    window
+    Ok(window)
 }
 
-const DEFAULT_COLORS: [&str; 7] = [
-    "#fff9b1", "#81B7DD", "#65A65B", "#AAD2CA", "#98C260", "#E1A1B1", "#B98CB3",
-];
+/// Shows the quick capture window, on top of whatever has focus.
+fn open_capture(app_handle: &AppHandle) -> Result<(), StickyError> {
+    let window = match app_handle.get_window(CAPTURE) {
+        Some(window) => window,
+        None => {
+            let window = tauri::WindowBuilder::new(
+                app_handle,
+                CAPTURE,
+                tauri::WindowUrl::App("capture".into()),
+            )
+            .title("Quick Capture")
+            .decorations(false)
+            .resizable(false)
+            .always_on_top(true)
+            .skip_taskbar(true)
+            .inner_size(CAPTURE_WIDTH, CAPTURE_HEIGHT)
+            .center()
+            .build()
+            .map_err(StickyError::window)?;
+
+            #[cfg(target_os = "linux")]
+            window
+                .menu_handle()
+                .hide()
+                .log_err("could not hide the window menu");
+
+            window
+        }
+    };
 
-#[tauri::command]
-fn add_color(color: &str, app_handle: tauri::AppHandle) -> Result<(), String> {
-    let path_buf = app_handle
-        .path_resolver()
-        .app_data_dir()
-        .expect("could not resolve app data directory")
-        .join("colors.json");
+    window.show().map_err(StickyError::window)?;
+    window.set_focus().map_err(StickyError::window)
+}
+
+/// Opens a window for a note that is already in the registry, and sends it the note once it is ready.
+fn open_note(handle: AppHandle, note: Note) -> Result<Window, StickyError> {
+    let window = create_new_sticky(handle, note.id)?;
 
-    let file_path = path_buf.as_path();
+    let window_clone = window.clone();
+    window.once("ready", move |_event| {
+        window_clone
+            .emit("init", note)
+            .log_err("could not emit init event");
+    });
+
+    Ok(window)
+}
 
-    let mut colors = get_colors();
+/// Brings back the most recently closed note, where it was and with the color it had.
+fn reopen_note(handle: AppHandle) -> Result<(), StickyError> {
+    let note = registry(&handle).reopen_last();
 
-    if !colors.iter().any(|c| *c == color) {
-        colors.push(color.to_string());
-        fs::write(
-            file_path,
-            serde_json::to_string(&colors).map_err(|e| e.to_string())?,
-        )
-        .map_err(|e| e.to_string())?;
+    if let Some(note) = note {
+        log::info!("reopening note {}", note.id);
+        open_note(handle, note)?;
     }
 
     Ok(())
 }
 
-#[derive(Clone, Deserialize, Serialize)]
-struct Note {
-    color: String,
-    contents: String,
-    x: u32,
-    y: u32,
-    height: u32,
-    width: u32,
-    label: String,
+/// Opens an empty note with the default color wherever the window manager puts it.
+fn new_note(handle: AppHandle) -> Result<Window, StickyError> {
+    let id = Uuid::new_v4();
+    let window = create_new_sticky(handle.clone(), id)?;
+
+    let position = window.outer_position().map_err(StickyError::window)?;
+    let size = window.inner_size().map_err(StickyError::window)?;
+
+    registry(&handle).insert(Note {
+        id,
+        color: palette::DEFAULT_COLORS[0].to_string(),
+        contents: model::EMPTY_CONTENTS.to_string(),
+        x: position.x.max(0) as u32,
+        y: position.y.max(0) as u32,
+        height: size.height,
+        width: size.width,
+        ..Default::default()
+    });
+
+    Ok(window)
 }
 
 #[tauri::command]
-fn read_contents(app_handle: tauri::AppHandle) -> Result<Vec<Note>, String> {
-    let path_buf = app_handle
-        .path_resolver()
-        .app_data_dir()
-        .expect("could not resolve app data directory")
-        .join("notes.json");
+fn add_color(color: &str, app_handle: tauri::AppHandle) -> Result<(), StickyError> {
+    palette::add_color(color, &app_data_dir(&app_handle)?)
+}
 
-    let file_path = path_buf.as_path();
+/// Writes the registry to disk if it has changes that have settled down.
+fn save_notes(app_handle: &AppHandle) -> bool {
+    let _saving = lock(&app_handle.state::<SaveLock>().inner().0);
 
-    let file_content = if file_path.exists() {
-        fs::read_to_string(file_path).map_err(|e| e.to_string())?
-    } else {
-        fs::File::create(file_path).expect("could not create a notes save file");
-        String::new()
+    // quitting does the last save itself
+    if QUITTING.load(Ordering::SeqCst) {
+        return false;
+    }
+
+    let save_file = registry(app_handle).take_due(Instant::now(), Debounce::default());
+
+    match save_file.map(|save_file| save_contents(save_file, app_handle)) {
+        Some(Ok(())) => true,
+        Some(Err(e)) => {
+            log::error!("could not save notes, trying again: {e}");
+            registry(app_handle).mark_dirty();
+            false
+        }
+        None => false,
+    }
+}
+
+/// Saves `save_file`, and remembers what the store holds now so the save isn't mistaken for another program's edit.
+fn save_contents(save_file: SaveFile, app_handle: &AppHandle) -> Result<(), StickyError> {
+    let read_back = {
+        let mut note_store = note_store(app_handle);
+
+        note_store.save(save_file.clone())?;
+        // the notes are saved either way, without the read-back the next reload compares with what was written
+        note_store
+            .reload()
+            .log_err("could not read back the notes just saved")
+            .unwrap_or_else(|| save_file.clone())
     };
 
-    let notes: Vec<Note> = if file_content.len() > 0 {
-        serde_json::from_str(&file_content).map_err(|e| e.to_string())?
-    } else {
-        Vec::new()
+    external_edits(app_handle).saved(&save_file, read_back);
+
+    // the export folder is a copy, the notes are saved even if it can't be written
+    if let Some(mirror) = mirror(app_handle).as_mut() {
+        mirror
+            .write(&save_file.notes)
+            .log_err("could not update the export folder");
+    }
+
+    Ok(())
+}
+
+/// Brings edits made to the files in the export folder into their notes, closes the notes whose file was deleted,
+/// and then writes out every note that isn't up to date there.
+fn sync_export_folder(app_handle: &AppHandle) {
+    let changes = match mirror(app_handle).as_mut() {
+        Some(mirror) => mirror
+            .changes()
+            .log_err("could not read the export folder")
+            .unwrap_or_default(),
+        None => return,
     };
 
-    Ok(notes)
+    for change in changes {
+        match change {
+            MirrorChange::Edited {
+                id,
+                contents,
+                color,
+            } => {
+                log::info!("note {id} was edited in the export folder");
+
+                let update = NoteUpdate {
+                    contents: Some(contents),
+                    color,
+                    ..Default::default()
+                };
+                update_from_outside(app_handle, id, update);
+            }
+            MirrorChange::Deleted(id) => {
+                windows_ready(app_handle).retain(|ready_id| *ready_id != id);
+
+                if registry(app_handle).close(id, Utc::now()) {
+                    log::info!("closed note {id}, its file in the export folder was deleted");
+                }
+
+                if let Some(window) = app_handle.get_window(&model::window_label(id)) {
+                    window
+                        .close()
+                        .log_err("could not close a note deleted in the export folder");
+                }
+            }
+        }
+    }
+
+    let notes = registry(app_handle).notes().to_vec();
+
+    if let Some(mirror) = mirror(app_handle).as_mut() {
+        mirror
+            .write(&notes)
+            .log_err("could not update the export folder");
+    }
 }
 
-fn save_contents(mut notes: Vec<Note>, app_handle: &tauri::AppHandle) -> Result<(), String> {
-    let path_buf = app_handle
-        .path_resolver()
-        .app_data_dir()
-        .expect("could not resolve app data directory")
-        .join("notes.json");
+/// Changes a note from outside its window, and sends the window the changed note.
+fn update_from_outside(app_handle: &AppHandle, id: Uuid, update: NoteUpdate) {
+    let note = {
+        let mut registry = registry(app_handle);
 
-    let file_path = path_buf.as_path();
+        registry.update(id, update);
+        registry.get(id).cloned()
+    };
 
-    notes = notes.into_iter().filter(|n| n.label != "main").collect();
+    if let (Some(note), Some(window)) = (note, app_handle.get_window(&model::window_label(id))) {
+        window
+            .emit("init", note)
+            .log_err("could not emit init event");
+    }
+}
 
-    fs::write(
-        file_path,
-        serde_json::to_string(&notes).map_err(|e| e.to_string())?,
-    )
-    .map_err(|e| e.to_string())?;
+/// The notes as the windows show them, for requests from the command line and the control socket.
+struct Windows(AppHandle);
+
+impl Notes for Windows {
+    fn list(&mut self) -> Result<Vec<Note>, StickyError> {
+        Ok(registry(&self.0).notes().to_vec())
+    }
+
+    fn create(&mut self, note: Note) -> Result<(), StickyError> {
+        registry(&self.0).insert(note.clone());
+        open_note(self.0.clone(), note)?;
+
+        Ok(())
+    }
+
+    fn update(&mut self, id: Uuid, update: NoteUpdate) -> Result<(), StickyError> {
+        if registry(&self.0).get(id).is_none() {
+            return Err(StickyError::NoteNotFound(id));
+        }
+
+        update_from_outside(&self.0, id, update);
+
+        Ok(())
+    }
+
+    fn close(&mut self, id: Uuid) -> Result<(), StickyError> {
+        remove_window(id, self.0.clone())
+    }
+
+    fn focus(&mut self, id: Uuid) -> Result<(), StickyError> {
+        focus_note(&self.0, id)
+    }
+
+    fn toggle_all_visible(&mut self) -> Result<(), StickyError> {
+        toggle_all_visible(&self.0)
+    }
+
+    // the window sends the color back like one picked from the Color menu
+    fn set_color(&mut self, id: Uuid, color: String) -> Result<(), StickyError> {
+        let window = note_window(&self.0, id)?;
+
+        let update = NoteUpdate {
+            color: Some(color.clone()),
+            ..Default::default()
+        };
+        registry(&self.0).update(id, update);
+
+        window.emit("set_color", color).map_err(StickyError::window)
+    }
+}
+
+/// Brings the note's window to the front, even if it was minimized or hidden.
+fn focus_note(app_handle: &AppHandle, id: Uuid) -> Result<(), StickyError> {
+    let window = note_window(app_handle, id)?;
+
+    window.unminimize().map_err(StickyError::window)?;
+    window.show().map_err(StickyError::window)?;
+    window.set_focus().map_err(StickyError::window)
+}
+
+/// Hides every note window if any is showing, shows them all otherwise.
+fn toggle_all_visible(app_handle: &AppHandle) -> Result<(), StickyError> {
+    let windows = app_handle
+        .windows()
+        .into_iter()
+        .filter(|(label, _)| model::note_id(label).is_some())
+        .map(|(_, window)| window)
+        .collect::<Vec<Window>>();
+
+    let any_visible = windows
+        .iter()
+        .any(|window| window.is_visible().unwrap_or(false));
+
+    for window in windows {
+        if any_visible {
+            window.hide().map_err(StickyError::window)?;
+        } else {
+            window.show().map_err(StickyError::window)?;
+        }
+    }
 
     Ok(())
 }
 
-// so many unwraps... this is bad code...
-fn save_notes(app_handle: &tauri::AppHandle) -> Result<(), String> {
-    let mut contents: Vec<Note> = Vec::new();
+fn note_window(app_handle: &AppHandle, id: Uuid) -> Result<Window, StickyError> {
+    app_handle
+        .get_window(&model::window_label(id))
+        .ok_or(StickyError::NoteNotFound(id))
+}
 
-    let (tx, rx) = mpsc::channel();
+/// Opens the note as Markdown in the user's editor, and brings every save made there back into the note
+/// until the editor is closed.
+fn open_in_editor(app_handle: &AppHandle, id: Uuid) -> Result<(), StickyError> {
+    let note = registry(app_handle)
+        .get(id)
+        .cloned()
+        .ok_or(StickyError::NoteNotFound(id))?;
+    let command = editor::command(app_handle.state::<Settings>().editor.as_deref())?;
+
+    let mut external_editor = ExternalEditor::open(&note, &command)?;
+    log::info!(
+        "opened note {id} in {} as {}",
+        command[0],
+        external_editor.path().display()
+    );
+
+    let handle = app_handle.clone();
+    thread::spawn(move || loop {
+        thread::sleep(EDITOR_INTERVAL);
+
+        let poll = match external_editor.poll(Instant::now()) {
+            Ok(poll) => poll,
+            Err(e) => {
+                log::error!("stopped following the external editor of note {id}: {e}");
+                break;
+            }
+        };
+
+        if let Some(contents) = poll.saved {
+            log::info!("note {id} was saved in the external editor");
+
+            let update = NoteUpdate {
+                contents: Some(contents),
+                ..Default::default()
+            };
+            update_from_outside(&handle, id, update);
+        }
+
+        if poll.exited {
+            log::info!("the external editor of note {id} was closed");
+            break;
+        }
+    });
+
+    Ok(())
+}
+
+/// Picks up what other programs changed in the store since the last save: windows get the new contents or color,
+/// and are opened or closed for notes that were added or removed. Notes that also changed in the app keep the app's
+/// version, the other one goes to their history.
+fn reload_notes(app_handle: &AppHandle) {
+    let _saving = lock(&app_handle.state::<SaveLock>().inner().0);
+
+    if QUITTING.load(Ordering::SeqCst) {
+        return;
+    }
+
+    // a file that is half written or broken is left for the next change, the app still has every note
+    let disk = match note_store(app_handle).reload() {
+        Ok(disk) => disk,
+        Err(e) => {
+            log::error!("could not read the notes changed on disk, skipping them: {e}");
+            return;
+        }
+    };
+
+    let reload = {
+        let mut registry = registry(app_handle);
+
+        let reload = external_edits(app_handle).diff(disk, &registry.save_file());
+        reload.apply_to(&mut registry, Utc::now());
+
+        reload
+    };
+
+    for change in reload.changes {
+        match change {
+            ExternalChange::Added(note) => {
+                log::info!("note {} was added on disk", note.id);
+                open_note(app_handle.clone(), note).log_err("could not open a note added on disk");
+            }
+            ExternalChange::Updated(note) => {
+                log::info!("note {} was changed on disk", note.id);
+                if let Some(window) = app_handle.get_window(&note.label()) {
+                    window
+                        .emit("init", note)
+                        .log_err("could not emit init event");
+                }
+            }
+            ExternalChange::Recolored(id, color) => {
+                if let Some(window) = app_handle.get_window(&model::window_label(id)) {
+                    window
+                        .emit("set_color", color)
+                        .log_err("could not emit set_color event");
+                }
+            }
+            ExternalChange::Removed(id) => {
+                log::info!("note {id} was removed on disk, moved it to the trash");
+                windows_ready(app_handle).retain(|ready_id| *ready_id != id);
+
+                if let Some(window) = app_handle.get_window(&model::window_label(id)) {
+                    window
+                        .close()
+                        .log_err("could not close a note removed on disk");
+                }
+            }
+            ExternalChange::Conflict(theirs) => {
+                log::warn!(
+                    "note {} was changed on disk and in the app, keeping the app's version",
+                    theirs.id
+                );
+
+                history_recorder(app_handle)
+                    .snapshot(note_store(app_handle).as_mut(), &theirs, Utc::now())
+                    .log_err("could not keep the version from disk in the history");
+
+                if let Some(window) = app_handle.get_window(&theirs.label()) {
+                    window
+                        .emit("conflict", {})
+                        .log_err("could not emit conflict event");
+                }
+            }
+        }
+    }
+}
+
+/// Collects the final state of every ready window and saves it, then exits.
+/// If the save fails the app stays open, unless the user chooses to quit anyway.
+fn quit(app_handle: AppHandle) {
+    if QUITTING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    log::info!("quitting");
+
+    // the windows answer through commands and events that are handled on the main thread, so don't block it
+    thread::spawn(move || {
+        flush_windows(&app_handle);
+
+        let result = {
+            let _saving = lock(&app_handle.state::<SaveLock>().inner().0);
+
+            let save_file = registry(&app_handle).save_file();
+
+            save_contents(save_file, &app_handle)
+        };
+
+        match result {
+            Ok(()) => app_handle.exit(0),
+            Err(e) => {
+                log::error!("could not save notes before quitting: {e}");
 
-    // have to have a mutex bc if we get the list of windows through the app_handle, messages are sometimes sent to windows that dont have listeners attached yet
-    let binding = app_handle.state::<Mutex<Vec<String>>>();
-    let windows_ready = binding
-        .lock()
-        .expect("could not obtain lock for windows ready mutex");
+                let quit_anyway = dialog::blocking::ask(
+                    None::<&Window>,
+                    "md-sticky",
+                    format!("Your notes could not be saved: {e}\n\nQuit anyway and lose your latest changes?"),
+                );
 
-    let responded_windows = Arc::new(Mutex::new(HashSet::new()));
+                if quit_anyway {
+                    app_handle.exit(0);
+                } else {
+                    QUITTING.store(false, Ordering::SeqCst);
+                }
+            }
+        }
+    });
+}
 
-    windows_ready.iter().for_each(|window_label| {
-        let window = app_handle
-            .get_window(window_label)
-            .expect("could get the current window from window label");
+/// Asks every ready window to push its current state, and waits until they all did or `QUIT_TIMEOUT` runs out.
+fn flush_windows(app_handle: &AppHandle) {
+    let ids = windows_ready(app_handle).clone();
 
+    let (tx, rx) = mpsc::channel();
+
+    let windows = ids
+        .iter()
+        .filter_map(|id| app_handle.get_window(&model::window_label(*id)))
+        .collect::<Vec<Window>>();
+
+    for window in &windows {
         let sender = tx.clone();
-        let window_clone = window.clone();
-        let window_label_clone = window_label.clone();
-        let responded_windows_clone = Arc::clone(&responded_windows);
-
-        window.listen("save-contents-response", move |event| {
-            // sometimes this event gets called multiple times, not sure why...
-            window_clone.unlisten(event.id());
-
-            let mut binding = responded_windows_clone
-                .lock()
-                .expect("could not obtain lock on responded windows mutex");
-
-            if binding.contains(&window_label_clone) {
-                return; // Skip if this window has already responded
-            }
-
-            binding.insert(window_label_clone.clone());
-            if let Err(_) = sender.send(
-                serde_json::from_str(
-                    &event
-                        .payload()
-                        .expect("could not extract payload from event"),
-                )
-                .expect("Could not decode save-contents-response payload"),
-            ) {}
+        window.once("flushed", move |_| {
+            sender.send(()).ok();
         });
 
         window
-            .emit("save-contents-request", {})
-            .expect("could not emit save-contents-request to the window");
-    });
-
-    for _ in 0..windows_ready.len() {
-        contents.push(
-            rx.recv_timeout(Duration::from_millis(100))
-                .map_err(|_| String::from("Timeout failed"))?,
-        );
+            .emit("flush", {})
+            .log_err("could not emit flush event");
     }
 
-    save_contents(contents, app_handle).expect("could not save contents");
+    let deadline = Instant::now() + QUIT_TIMEOUT;
 
-    Ok(())
+    for _ in 0..windows.len() {
+        // a window that doesn't answer in time keeps whatever it pushed last
+        if rx
+            .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            .is_err()
+        {
+            log::warn!("not every window sent its final state in time");
+            break;
+        }
+    }
+}
+
+#[tauri::command]
+fn update_note(
+    id: Uuid,
+    update: NoteUpdate,
+    app_handle: tauri::AppHandle,
+) -> Result<(), StickyError> {
+    if registry(&app_handle).update(id, update) {
+        Ok(())
+    } else {
+        Err(StickyError::NoteNotFound(id))
+    }
 }
 
 #[tauri::command]
 // sometimes after closing a window, tauri wry's webview tree does not get updated properly, and will crash on the next menu event. I cannot do anything to fix this unfortunately...
-fn remove_window(label: String, app_handle: tauri::AppHandle) {
-    // block scope to unlock mutex before closing window
-    {
-        let wr_binding = app_handle.state::<Mutex<Vec<String>>>();
-        let mut windows_ready = wr_binding
-            .lock()
-            .expect("could not obtain lock on windows_ready mutex");
+/// Closes the note's window and moves the note to the trash, File > Reopen Closed Note brings it back.
+fn remove_window(id: Uuid, app_handle: tauri::AppHandle) -> Result<(), StickyError> {
+    windows_ready(&app_handle).retain(|ready_id| *ready_id != id);
 
-        windows_ready.retain(|s| *s != label);
-    }
+    registry(&app_handle).close(id, Utc::now());
+    purge_trash(&app_handle);
 
     app_handle
-        .get_window(&label)
-        .expect("could not get window from label")
+        .get_window(&model::window_label(id))
+        .ok_or(StickyError::NoteNotFound(id))?
         .close()
-        .expect("could not close window");
+        .map_err(StickyError::window)?;
+
+    log::info!("closed note {id}");
+
+    Ok(())
 }
 
 #[tauri::command]
-fn get_colors() -> Vec<String> {
-    let path_buf = data_dir()
-        .expect("Could not resolve app data directory")
-        .join("md-sticky/colors.json");
+// async so it runs off the main thread, which opening a note's window can't be done from
+/// Adds what was typed into the quick capture window to the inbox note, or opens a note with it, see `CapturePolicy`.
+async fn capture(text: String, app_handle: tauri::AppHandle) -> Result<(), StickyError> {
+    let policy = app_handle.state::<Settings>().capture.clone();
+    let notes = registry(&app_handle).notes().to_vec();
+
+    if let Some(request) = policy.request(&text, &notes) {
+        control::execute(request, &mut Windows(app_handle.clone()))?;
+    }
 
-    let file_path = path_buf.as_path();
+    Ok(())
+}
 
-    if file_path.exists() {
-        let file_content = fs::read_to_string(file_path).expect("could not read colors path");
-        let colors: Vec<String> = serde_json::from_str(&file_content).expect("could parse colors");
-        colors
-    } else {
-        DEFAULT_COLORS.iter().map(|s| s.to_string()).collect()
+#[tauri::command]
+fn get_colors(app_handle: tauri::AppHandle) -> Result<Vec<String>, StickyError> {
+    palette::get_colors(&app_data_dir(&app_handle)?)
+}
+
+/// Deletes the notes that have been in the trash for too long, along with their history.
+fn purge_trash(app_handle: &AppHandle) {
+    let settings = app_handle.state::<Settings>();
+
+    let purged = registry(app_handle).purge_trash(Utc::now(), &settings.trash);
+
+    for trashed in purged {
+        log::info!("purged note {} from the trash", trashed.note.id);
+
+        history_recorder(app_handle)
+            .forget(note_store(app_handle).as_mut(), trashed.note.id)
+            .log_err("could not delete the history of a purged note");
     }
 }
+
+fn record_history(app_handle: &AppHandle) {
+    let notes = registry(app_handle).notes().to_vec();
+
+    history_recorder(app_handle)
+        .record(note_store(app_handle).as_mut(), &notes, Utc::now())
+        .log_err("could not record note history");
+}
+
+#[tauri::command]
+fn list_revisions(
+    id: Uuid,
+    app_handle: tauri::AppHandle,
+) -> Result<Vec<RevisionSummary>, StickyError> {
+    let revisions = note_store(&app_handle).history(id)?;
+
+    Ok(revisions
+        .iter()
+        .enumerate()
+        .map(|(index, revision)| revision.summary(index))
+        .collect())
+}
+
+#[tauri::command]
+fn preview_revision(
+    id: Uuid,
+    index: usize,
+    app_handle: tauri::AppHandle,
+) -> Result<Revision, StickyError> {
+    note_store(&app_handle).revision(id, index)
+}
+
+/// Puts a note back the way it was in one of its revisions, after taking a revision of how it is now so the restore can be undone.
+#[tauri::command]
+fn restore_revision(
+    id: Uuid,
+    index: usize,
+    app_handle: tauri::AppHandle,
+) -> Result<(), StickyError> {
+    let revision = note_store(&app_handle).revision(id, index)?;
+
+    let note = {
+        let mut registry = registry(&app_handle);
+
+        let current = registry.get(id).ok_or(StickyError::NoteNotFound(id))?;
+        history_recorder(&app_handle).snapshot(
+            note_store(&app_handle).as_mut(),
+            current,
+            Utc::now(),
+        )?;
+
+        registry.update(id, revision.to_update());
+        registry
+            .get(id)
+            .cloned()
+            .ok_or(StickyError::NoteNotFound(id))?
+    };
+
+    log::info!(
+        "restored note {id} to its revision from {}",
+        revision.taken_at
+    );
+
+    app_handle
+        .get_window(&model::window_label(id))
+        .ok_or(StickyError::NoteNotFound(id))?
+        .emit("init", note)
+        .map_err(StickyError::window)
+}
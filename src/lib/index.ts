@@ -1 +1,76 @@
 // place files you want to import through the `$lib` alias in this folder.
+
+// windows that show a note are labelled `note-<id>`, see `window_label` in md_sticky_core
+export function noteId(label: string): string {
+  return label.replace(/^note-/, "");
+}
+
+export type NoteUpdate = {
+  contents?: string;
+  color?: string;
+  x?: number;
+  y?: number;
+  height?: number;
+  width?: number;
+};
+
+// what a command rejects with when it fails, see `StickyError` in md_sticky_core
+export type StickyError = {
+  kind: string;
+  message: string;
+};
+
+// pushes a change in this window's note to the backend, which saves it once things settle down
+export async function updateNote(update: NoteUpdate) {
+  const { appWindow } = await import("@tauri-apps/api/window");
+  const { invoke } = await import("@tauri-apps/api/tauri");
+
+  if (appWindow.label == "main") return;
+
+  await invoke("update_note", { id: noteId(appWindow.label), update });
+}
+
+export type RevisionSummary = {
+  index: number;
+  taken_at: string;
+  color: string;
+  title: string;
+};
+
+export type Revision = {
+  taken_at: string;
+  color: string;
+  contents: string;
+  x: number;
+  y: number;
+  height: number;
+  width: number;
+};
+
+// the history of this window's note, oldest first
+export async function listRevisions(): Promise<RevisionSummary[]> {
+  const { appWindow } = await import("@tauri-apps/api/window");
+  const { invoke } = await import("@tauri-apps/api/tauri");
+
+  return await invoke<RevisionSummary[]>("list_revisions", {
+    id: noteId(appWindow.label),
+  });
+}
+
+export async function previewRevision(index: number): Promise<Revision> {
+  const { appWindow } = await import("@tauri-apps/api/window");
+  const { invoke } = await import("@tauri-apps/api/tauri");
+
+  return await invoke<Revision>("preview_revision", {
+    id: noteId(appWindow.label),
+    index,
+  });
+}
+
+// the backend sends the restored note back through the "init" event
+export async function restoreRevision(index: number) {
+  const { appWindow } = await import("@tauri-apps/api/window");
+  const { invoke } = await import("@tauri-apps/api/tauri");
+
+  await invoke("restore_revision", { id: noteId(appWindow.label), index });
+}